@@ -0,0 +1,13 @@
+CREATE TABLE api_keys (
+	api_key_id uuid NOT NULL,
+	user_id uuid NOT NULL REFERENCES users (user_id),
+	name TEXT NOT NULL,
+	-- Public part of the key, used to look it up
+	prefix TEXT NOT NULL UNIQUE,
+	-- SHA-256 of the whole key, hex-encoded
+	key_hash TEXT NOT NULL,
+	created_at timestamptz NOT NULL,
+	last_used_at timestamptz NULL,
+	revoked_at timestamptz NULL,
+	PRIMARY KEY (api_key_id)
+);
@@ -0,0 +1,12 @@
+-- Issues were stored with their publication time as text
+ALTER TABLE newsletter_issues
+	ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+
+-- Number of subscribers the issue was enqueued for, to report delivery progress
+ALTER TABLE newsletter_issues ADD COLUMN n_recipients INTEGER NOT NULL DEFAULT 0;
+UPDATE newsletter_issues i
+SET n_recipients = (
+	SELECT COUNT(*)
+	FROM issue_delivery_queue q
+	WHERE q.newsletter_issue_id = i.newsletter_issue_id
+);
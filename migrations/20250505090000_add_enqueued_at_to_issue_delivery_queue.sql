@@ -0,0 +1,3 @@
+-- Lets us report how long the oldest delivery task has been waiting
+ALTER TABLE issue_delivery_queue
+	ADD COLUMN enqueued_at timestamptz NOT NULL DEFAULT now();
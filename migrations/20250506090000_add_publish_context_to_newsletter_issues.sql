@@ -0,0 +1,11 @@
+-- Link each issue, and every delivery task it spawned, back to the request that published it.
+-- Issues published before this migration have no such context.
+ALTER TABLE newsletter_issues
+	ADD COLUMN published_by uuid NULL REFERENCES users(user_id),
+	ADD COLUMN publish_request_id uuid NULL,
+	ADD COLUMN publish_traceparent TEXT NULL;
+
+ALTER TABLE issue_delivery_queue
+	ADD COLUMN published_by uuid NULL,
+	ADD COLUMN publish_request_id uuid NULL,
+	ADD COLUMN publish_traceparent TEXT NULL;
@@ -0,0 +1,15 @@
+-- What admins need to see on a subscriber's page: when confirmation emails
+-- went out, when they confirmed or unsubscribed, and what they were sent.
+ALTER TABLE subscriptions
+	ADD COLUMN confirmed_at timestamptz NULL,
+	ADD COLUMN unsubscribed_at timestamptz NULL;
+ALTER TABLE subscription_tokens
+	ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+CREATE TABLE newsletter_deliveries (
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	subscriber_email TEXT NOT NULL,
+	-- `sent`, `failed` or `skipped`, as reported by the delivery worker
+	outcome TEXT NOT NULL,
+	delivered_at timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,18 @@
+CREATE TABLE subscriber_imports (
+	import_id uuid PRIMARY KEY,
+	imported_by uuid NOT NULL REFERENCES users (user_id),
+	-- `send_confirmations` or `mark_confirmed`
+	mode TEXT NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+-- The per-row report of an import
+CREATE TABLE subscriber_import_rows (
+	import_id uuid NOT NULL REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+	line_number INT NOT NULL,
+	name TEXT NOT NULL,
+	email TEXT NOT NULL,
+	-- `imported`, `duplicate`, `invalid` or `failed`
+	outcome TEXT NOT NULL,
+	detail TEXT NULL,
+	PRIMARY KEY(import_id, line_number)
+);
@@ -0,0 +1,17 @@
+-- Links emailed to subscribers who ask for the data we hold about them
+CREATE TABLE personal_data_tokens (
+	token TEXT PRIMARY KEY,
+	email TEXT NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+-- Audit log of erasures. We only keep a digest of the address,
+-- enough to answer "did you erase me?" without keeping the data itself.
+CREATE TABLE personal_data_erasures (
+	erasure_id uuid PRIMARY KEY,
+	email_sha256 TEXT NOT NULL,
+	-- `subscriber`, `admin` or `api`
+	requested_by TEXT NOT NULL,
+	user_id uuid NULL REFERENCES users (user_id),
+	rows_erased INT NOT NULL,
+	erased_at timestamptz NOT NULL DEFAULT now()
+);
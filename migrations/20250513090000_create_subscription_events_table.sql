@@ -0,0 +1,37 @@
+-- Append-only record of how and when each subscriber consented
+CREATE TABLE subscription_events (
+	event_id uuid PRIMARY KEY,
+	subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+	-- `subscribed`, `confirmation_email_sent`, `confirmed` or `unsubscribed`
+	event_type TEXT NOT NULL,
+	-- `form`, `api`, `admin`, `import`, `confirmation_link` or `backfill`
+	source TEXT NOT NULL,
+	occurred_at timestamptz NOT NULL DEFAULT now(),
+	ip_address TEXT NULL,
+	user_agent TEXT NULL,
+	subscription_token TEXT NULL
+);
+CREATE INDEX subscription_events_subscriber_id_idx ON subscription_events (subscriber_id, occurred_at);
+
+-- Events can be erased together with their subscriber, but never rewritten
+CREATE FUNCTION reject_subscription_event_updates() RETURNS trigger AS $$
+BEGIN
+	RAISE EXCEPTION 'subscription_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER subscription_events_are_append_only
+	BEFORE UPDATE ON subscription_events
+	FOR EACH ROW EXECUTE FUNCTION reject_subscription_event_updates();
+
+-- What we know of the history of existing subscribers
+INSERT INTO subscription_events (event_id, subscriber_id, event_type, source, occurred_at)
+SELECT gen_random_uuid(), id, 'subscribed', 'backfill', subscribed_at FROM subscriptions;
+INSERT INTO subscription_events (event_id, subscriber_id, event_type, source, occurred_at, subscription_token)
+SELECT gen_random_uuid(), subscriber_id, 'confirmation_email_sent', 'backfill', created_at, subscription_token
+FROM subscription_tokens;
+INSERT INTO subscription_events (event_id, subscriber_id, event_type, source, occurred_at)
+SELECT gen_random_uuid(), id, 'confirmed', 'backfill', confirmed_at FROM subscriptions
+WHERE confirmed_at IS NOT NULL;
+INSERT INTO subscription_events (event_id, subscriber_id, event_type, source, occurred_at)
+SELECT gen_random_uuid(), id, 'unsubscribed', 'backfill', unsubscribed_at FROM subscriptions
+WHERE unsubscribed_at IS NOT NULL;
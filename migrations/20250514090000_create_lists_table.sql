@@ -0,0 +1,34 @@
+-- The mailing lists people can subscribe to
+CREATE TABLE lists (
+	list_id uuid PRIMARY KEY,
+	-- How the list is referred to in forms and through the API
+	slug TEXT NOT NULL UNIQUE,
+	name TEXT NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+-- Everybody so far subscribed to the one newsletter we had
+INSERT INTO lists (list_id, slug, name) VALUES (gen_random_uuid(), 'newsletter', 'Newsletter');
+
+-- A subscription is now to a list: the same address can be on several,
+-- each with its own confirmation
+ALTER TABLE subscriptions ADD COLUMN list_id uuid REFERENCES lists (list_id);
+UPDATE subscriptions SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscriptions ALTER COLUMN list_id SET NOT NULL;
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_list_id_email_key UNIQUE (list_id, email);
+CREATE INDEX subscriptions_email_idx ON subscriptions (email);
+
+-- The lists an issue was published to
+CREATE TABLE newsletter_issue_lists (
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	list_id uuid NOT NULL REFERENCES lists (list_id),
+	PRIMARY KEY (newsletter_issue_id, list_id)
+);
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT newsletter_issue_id, (SELECT list_id FROM lists WHERE slug = 'newsletter')
+FROM newsletter_issues;
+
+-- Imports add subscribers to one list
+ALTER TABLE subscriber_imports ADD COLUMN list_id uuid REFERENCES lists (list_id);
+UPDATE subscriber_imports SET list_id = (SELECT list_id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscriber_imports ALTER COLUMN list_id SET NOT NULL;
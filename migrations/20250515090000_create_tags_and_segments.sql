@@ -0,0 +1,51 @@
+-- Free-form labels on subscribers, e.g. `beta testers`
+CREATE TABLE subscriber_tags (
+	subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+	tag TEXT NOT NULL,
+	tagged_at timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- A part of the audience an issue can be sent to
+CREATE TABLE segments (
+	segment_id uuid PRIMARY KEY,
+	name TEXT NOT NULL UNIQUE,
+	-- Subscribers must have every one of these tags...
+	with_tags TEXT[] NOT NULL DEFAULT '{}',
+	-- ...and none of these
+	without_tags TEXT[] NOT NULL DEFAULT '{}',
+	-- Days of subscription, inclusive, in UTC
+	subscribed_from DATE NULL,
+	subscribed_to DATE NULL,
+	-- Lowercase, without the `@`
+	email_domain TEXT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Issues without a segment go to everybody on their lists
+ALTER TABLE newsletter_issues ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
+
+-- Whether a subscription falls in a segment, everybody being in the NULL segment.
+-- Shared by the recipient count shown before publishing and by the enqueueing itself,
+-- so that both always agree.
+CREATE FUNCTION subscriber_in_segment(p_subscriber_id uuid, p_segment_id uuid)
+RETURNS boolean
+LANGUAGE sql STABLE
+AS $$
+	SELECT p_segment_id IS NULL OR EXISTS (
+		SELECT 1
+		FROM segments g
+		JOIN subscriptions s ON s.id = p_subscriber_id
+		CROSS JOIN LATERAL (
+			SELECT ARRAY(SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id) AS tags
+		) t
+		WHERE
+			g.segment_id = p_segment_id AND
+			t.tags @> g.with_tags AND
+			NOT (t.tags && g.without_tags) AND
+			(g.subscribed_from IS NULL OR s.subscribed_at >= g.subscribed_from::timestamp AT TIME ZONE 'UTC') AND
+			(g.subscribed_to IS NULL OR s.subscribed_at < (g.subscribed_to + 1)::timestamp AT TIME ZONE 'UTC') AND
+			(g.email_domain IS NULL OR lower(split_part(s.email, '@', 2)) = g.email_domain)
+	)
+$$;
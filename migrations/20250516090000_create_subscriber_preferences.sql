@@ -0,0 +1,18 @@
+-- One row per address: the token of its preference center link, and how
+-- it wants to receive issues
+CREATE TABLE subscriber_preferences (
+	email TEXT PRIMARY KEY,
+	preference_token TEXT NOT NULL UNIQUE,
+	-- `html` or `text`
+	format TEXT NOT NULL DEFAULT 'html',
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Changes of address waiting for the new address to be confirmed
+CREATE TABLE email_changes (
+	subscription_token TEXT PRIMARY KEY,
+	old_email TEXT NOT NULL,
+	new_email TEXT NOT NULL,
+	created_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX email_changes_old_email_idx ON email_changes (old_email);
@@ -0,0 +1,3 @@
+-- The source of issues written in Markdown. Their text and HTML content
+-- are rendered from it when they are published.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
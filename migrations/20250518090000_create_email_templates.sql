@@ -0,0 +1,11 @@
+-- Templates edited from the admin area. Emails without a row here use the
+-- default template shipped with the application.
+CREATE TABLE email_templates (
+	-- e.g. `confirmation` or `newsletter_layout`
+	name TEXT PRIMARY KEY,
+	-- Empty for layouts, issues have their own title
+	subject TEXT NOT NULL,
+	html_body TEXT NOT NULL,
+	text_body TEXT NOT NULL,
+	updated_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,10 @@
+-- Files sent with an issue. Images the HTML content refers to as
+-- `cid:<file name>` are shown inline, the others are attached.
+CREATE TABLE newsletter_issue_attachments (
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	file_name TEXT NOT NULL,
+	content_type TEXT NOT NULL,
+	content BYTEA NOT NULL,
+	inline BOOLEAN NOT NULL,
+	PRIMARY KEY(newsletter_issue_id, file_name)
+);
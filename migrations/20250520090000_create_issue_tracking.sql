@@ -0,0 +1,26 @@
+-- Opens and clicks of issues published with tracking on.
+ALTER TABLE newsletter_issues ADD COLUMN tracked BOOLEAN NOT NULL DEFAULT false;
+-- The links of tracked issues, numbered from 1 in the order they appear
+CREATE TABLE issue_links (
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	link_number INT NOT NULL,
+	url TEXT NOT NULL,
+	PRIMARY KEY(newsletter_issue_id, link_number)
+);
+-- One per delivery of a tracked issue
+CREATE TABLE tracking_tokens (
+	token TEXT PRIMARY KEY,
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	subscriber_email TEXT NOT NULL,
+	UNIQUE(newsletter_issue_id, subscriber_email)
+);
+CREATE TABLE tracking_events (
+	newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+	subscriber_email TEXT NOT NULL,
+	-- `open` or `click`
+	kind TEXT NOT NULL,
+	-- Set for clicks
+	link_number INT NULL,
+	occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX tracking_events_newsletter_issue_id_idx ON tracking_events (newsletter_issue_id);
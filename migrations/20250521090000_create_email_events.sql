@@ -0,0 +1,19 @@
+-- What the email provider tells us became of the emails we sent.
+CREATE TABLE email_events (
+	subscriber_email TEXT NOT NULL,
+	-- `delivery`, `bounce` or `spam_complaint`
+	kind TEXT NOT NULL,
+	-- For bounces, their type, e.g. `HardBounce`
+	detail TEXT NULL,
+	message_id TEXT NULL,
+	occurred_at timestamptz NOT NULL,
+	received_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX email_events_subscriber_email_idx ON email_events (subscriber_email);
+-- Addresses issues are no longer sent to
+CREATE TABLE email_suppressions (
+	email TEXT PRIMARY KEY,
+	-- `hard_bounce` or `spam_complaint`
+	reason TEXT NOT NULL,
+	suppressed_at timestamptz NOT NULL DEFAULT now()
+);
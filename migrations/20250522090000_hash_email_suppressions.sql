@@ -0,0 +1,21 @@
+-- Suppressions outlive the erasure of an address: they are keyed on the
+-- SHA-256 of the lowercased address, as the audit log of erasures is.
+CREATE FUNCTION email_sha256(p_email text)
+RETURNS text
+LANGUAGE sql IMMUTABLE
+AS $$
+	SELECT encode(sha256(convert_to(lower(p_email), 'UTF8')), 'hex')
+$$;
+CREATE TABLE hashed_email_suppressions (
+	email_sha256 TEXT PRIMARY KEY,
+	-- `hard_bounce` or `spam_complaint`
+	reason TEXT NOT NULL,
+	suppressed_at timestamptz NOT NULL DEFAULT now()
+);
+INSERT INTO hashed_email_suppressions (email_sha256, reason, suppressed_at)
+SELECT DISTINCT ON (email_sha256(email)) email_sha256(email), reason, suppressed_at
+FROM email_suppressions
+ORDER BY email_sha256(email), suppressed_at;
+DROP TABLE email_suppressions;
+ALTER TABLE hashed_email_suppressions RENAME TO email_suppressions;
+ALTER TABLE email_suppressions RENAME CONSTRAINT hashed_email_suppressions_pkey TO email_suppressions_pkey;
@@ -0,0 +1,10 @@
+-- Confirmation emails owed to imported subscribers, sent by the worker
+CREATE TABLE import_confirmation_queue (
+	import_id uuid NOT NULL REFERENCES subscriber_imports (import_id) ON DELETE CASCADE,
+	-- The row of the import report the subscriber came from
+	line_number INT NOT NULL,
+	subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+	subscription_token TEXT NOT NULL,
+	enqueued_at timestamptz NOT NULL DEFAULT now(),
+	PRIMARY KEY(import_id, line_number)
+);
@@ -0,0 +1,7 @@
+-- Requests for a link to the data held about an address, answered by the
+-- worker so that every request gets the same response, just as fast
+CREATE TABLE personal_data_request_queue (
+	request_id uuid PRIMARY KEY,
+	email TEXT NOT NULL,
+	requested_at timestamptz NOT NULL DEFAULT now()
+);
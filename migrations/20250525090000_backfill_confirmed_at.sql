@@ -0,0 +1,8 @@
+-- Subscribers confirmed before `confirmed_at` was tracked have none, and so
+-- got no `confirmed` event in the backfill of their trail: date both to
+-- when they subscribed, the earliest they could have confirmed.
+INSERT INTO subscription_events (event_id, subscriber_id, event_type, source, occurred_at)
+SELECT gen_random_uuid(), id, 'confirmed', 'backfill', subscribed_at FROM subscriptions
+WHERE status = 'confirmed' AND confirmed_at IS NULL;
+UPDATE subscriptions SET confirmed_at = subscribed_at
+WHERE status = 'confirmed' AND confirmed_at IS NULL;
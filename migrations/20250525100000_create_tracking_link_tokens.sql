@@ -0,0 +1,8 @@
+-- One per link of each delivery of a tracked issue, so that the links of
+-- an issue do not give away the token of its other links
+CREATE TABLE tracking_link_tokens (
+	token TEXT PRIMARY KEY,
+	delivery_token TEXT NOT NULL REFERENCES tracking_tokens (token),
+	link_number INT NOT NULL,
+	UNIQUE(delivery_token, link_number)
+);
@@ -0,0 +1,5 @@
+-- An address is on a list once, whatever its case
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_list_id_email_key;
+CREATE UNIQUE INDEX subscriptions_list_id_lower_email_key ON subscriptions (list_id, lower(email));
+DROP INDEX subscriptions_email_idx;
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
@@ -0,0 +1,22 @@
+-- How far the delivery of each issue got: deliveries done by outcome, and
+-- those still queued
+CREATE VIEW newsletter_issue_progress AS
+SELECT
+	i.newsletter_issue_id,
+	d.sent,
+	d.failed,
+	d.skipped,
+	(
+		SELECT COUNT(*)
+		FROM issue_delivery_queue q
+		WHERE q.newsletter_issue_id = i.newsletter_issue_id
+	) AS pending
+FROM newsletter_issues i
+CROSS JOIN LATERAL (
+	SELECT
+		COUNT(*) FILTER (WHERE outcome = 'sent') AS sent,
+		COUNT(*) FILTER (WHERE outcome = 'failed') AS failed,
+		COUNT(*) FILTER (WHERE outcome = 'skipped') AS skipped
+	FROM newsletter_deliveries d
+	WHERE d.newsletter_issue_id = i.newsletter_issue_id
+) d;
@@ -0,0 +1,226 @@
+//! Files sent with an issue: uploaded with the publish form, and stored
+//! with the issue in the database.
+use std::sync::LazyLock;
+
+use regex::Regex;
+use sqlx::{Executor, PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Postmark refuses messages larger than 10 MB, and attachments grow by
+/// a third once encoded.
+pub const MAX_ATTACHMENTS_SIZE: usize = 7 * 1024 * 1024;
+
+/// Documents and images: nothing that could run on the recipient's computer.
+pub const ATTACHMENT_TYPES: [&str; 8] = [
+    "application/pdf",
+    "image/gif",
+    "image/jpeg",
+    "image/png",
+    "image/webp",
+    "text/calendar",
+    "text/csv",
+    "text/plain",
+];
+
+/// `<img src="cid:logo.png">`, as written by the sanitiser.
+static INLINE_IMAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"src="cid:([^"]+)""#).unwrap());
+
+#[derive(Clone, Debug)]
+pub struct Attachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// Shown in the HTML content, as `cid:<file name>`, rather than attached
+    pub inline: bool,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum AttachmentError {
+    #[error(
+        "`{0}` cannot be attached: only PDF documents, images, calendar invites \
+        and text files can."
+    )]
+    ContentType(String),
+    #[error("Attachments can weigh {} MB at most.", MAX_ATTACHMENTS_SIZE / 1024 / 1024)]
+    TooLarge,
+    #[error("Two attachments are called `{0}`.")]
+    SameName(String),
+    #[error("The HTML content shows `cid:{0}`, but no image attached is called `{0}`.")]
+    MissingImage(String),
+}
+
+impl Attachment {
+    pub fn new(file_name: String, content_type: String, content: Vec<u8>) -> Self {
+        Self {
+            file_name,
+            content_type,
+            content,
+            inline: false,
+        }
+    }
+
+    fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+}
+
+/// Check the files uploaded with an issue, and mark those its HTML content
+/// shows as inline.
+pub fn prepare(
+    mut attachments: Vec<Attachment>,
+    html_content: &str,
+) -> Result<Vec<Attachment>, AttachmentError> {
+    for (i, attachment) in attachments.iter().enumerate() {
+        if !ATTACHMENT_TYPES.contains(&attachment.content_type.as_str()) {
+            return Err(AttachmentError::ContentType(attachment.file_name.clone()));
+        }
+        if attachments[..i]
+            .iter()
+            .any(|a| a.file_name == attachment.file_name)
+        {
+            return Err(AttachmentError::SameName(attachment.file_name.clone()));
+        }
+    }
+    let size: usize = attachments.iter().map(|a| a.content.len()).sum();
+    if size > MAX_ATTACHMENTS_SIZE {
+        return Err(AttachmentError::TooLarge);
+    }
+    for shown in INLINE_IMAGE.captures_iter(html_content) {
+        let file_name = &shown[1];
+        match attachments
+            .iter_mut()
+            .find(|a| a.file_name == file_name && a.is_image())
+        {
+            Some(image) => image.inline = true,
+            None => return Err(AttachmentError::MissingImage(file_name.to_owned())),
+        }
+    }
+    Ok(attachments)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn store(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+    attachments: &[Attachment],
+) -> Result<(), sqlx::Error> {
+    for attachment in attachments {
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_attachments (
+                newsletter_issue_id, file_name, content_type, content, inline
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            newsletter_issue_id,
+            attachment.file_name,
+            attachment.content_type,
+            attachment.content,
+            attachment.inline
+        );
+        transaction.execute(query).await?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn of_issue(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<Attachment>, sqlx::Error> {
+    sqlx::query_as!(
+        Attachment,
+        r#"
+        SELECT file_name, content_type, content, inline
+        FROM newsletter_issue_attachments
+        WHERE newsletter_issue_id = $1
+        ORDER BY file_name
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// An image shown in an issue, for its web view.
+#[tracing::instrument(skip(executor))]
+pub async fn find_inline(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    file_name: &str,
+) -> Result<Option<Attachment>, sqlx::Error> {
+    sqlx::query_as!(
+        Attachment,
+        r#"
+        SELECT file_name, content_type, content, inline
+        FROM newsletter_issue_attachments
+        WHERE newsletter_issue_id = $1 AND file_name = $2 AND inline
+        "#,
+        newsletter_issue_id,
+        file_name
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attachment, AttachmentError, MAX_ATTACHMENTS_SIZE, prepare};
+    use claims::{assert_err, assert_ok};
+
+    fn attachment(file_name: &str, content_type: &str) -> Attachment {
+        Attachment::new(file_name.into(), content_type.into(), vec![0; 16])
+    }
+
+    #[test]
+    fn images_shown_in_the_html_are_inline() {
+        let attachments = vec![
+            attachment("logo.png", "image/png"),
+            attachment("photo.jpg", "image/jpeg"),
+            attachment("programme.pdf", "application/pdf"),
+        ];
+        let attachments = assert_ok!(prepare(
+            attachments,
+            "<p><img src=\"cid:logo.png\" alt=\"Logo\"></p>"
+        ));
+        let inline: Vec<bool> = attachments.iter().map(|a| a.inline).collect();
+        assert_eq!(inline, vec![true, false, false]);
+    }
+
+    #[test]
+    fn images_shown_must_be_attached() {
+        let attachments = vec![attachment("programme.pdf", "application/pdf")];
+        let e = assert_err!(prepare(attachments, "<img src=\"cid:programme.pdf\">"));
+        assert_eq!(e, AttachmentError::MissingImage("programme.pdf".into()));
+    }
+
+    #[test]
+    fn only_some_files_can_be_attached() {
+        let attachments = vec![attachment("setup.exe", "application/octet-stream")];
+        let e = assert_err!(prepare(attachments, ""));
+        assert_eq!(e, AttachmentError::ContentType("setup.exe".into()));
+        let attachments = vec![
+            attachment("a.txt", "text/plain"),
+            attachment("a.txt", "text/plain"),
+        ];
+        let e = assert_err!(prepare(attachments, ""));
+        assert_eq!(e, AttachmentError::SameName("a.txt".into()));
+    }
+
+    #[test]
+    fn attachments_have_a_size_limit() {
+        let attachments = vec![
+            Attachment::new(
+                "a.pdf".into(),
+                "application/pdf".into(),
+                vec![0; MAX_ATTACHMENTS_SIZE],
+            ),
+            attachment("b.pdf", "application/pdf"),
+        ];
+        assert_eq!(
+            assert_err!(prepare(attachments, "")),
+            AttachmentError::TooLarge
+        );
+    }
+}
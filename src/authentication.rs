@@ -1,5 +1,11 @@
+mod api_key;
+mod csrf;
 mod middleware;
 mod password;
 
+pub use api_key::{
+    ApiKey, ApiKeyRecord, create_api_key, list_api_keys, reject_invalid_api_keys, revoke_api_key,
+};
+pub use csrf::{CsrfToken, reject_invalid_csrf_token};
 pub use middleware::{UserId, reject_anonymous_users};
 pub use password::{AuthError, Credentials, change_password, validate_credentials};
@@ -0,0 +1,261 @@
+use actix_web::{
+    HttpMessage,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    web,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{Rng, distr::Alphanumeric};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use super::UserId;
+use crate::routes::ApiError;
+
+const KEY_TAG: &str = "z2p";
+const PREFIX_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 32;
+
+/// A key used by other services to call the JSON API on behalf of an admin user.
+///
+/// Keys look like `z2p_<prefix>_<secret>`. The prefix is stored in clear
+/// to look the key up, while we only ever store a hash of the whole key.
+#[derive(Debug)]
+pub struct ApiKey {
+    prefix: String,
+    secret: SecretString,
+}
+
+impl ApiKey {
+    pub fn generate() -> Self {
+        Self {
+            prefix: random_alphanumeric(PREFIX_LENGTH),
+            secret: SecretString::from(random_alphanumeric(SECRET_LENGTH)),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        let mut parts = s.splitn(3, '_');
+        let (Some(KEY_TAG), Some(prefix), Some(secret)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("The API key is not in the expected format");
+        };
+        let is_valid = |part: &str, length: usize| {
+            part.len() == length && part.chars().all(|c| c.is_ascii_alphanumeric())
+        };
+        if !is_valid(prefix, PREFIX_LENGTH) || !is_valid(secret, SECRET_LENGTH) {
+            anyhow::bail!("The API key is not in the expected format");
+        }
+        Ok(Self {
+            prefix: prefix.to_owned(),
+            secret: SecretString::from(secret.to_owned()),
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The full key, to be shown to its owner exactly once.
+    pub fn expose_secret(&self) -> String {
+        format!(
+            "{}_{}_{}",
+            KEY_TAG,
+            self.prefix,
+            self.secret.expose_secret()
+        )
+    }
+
+    /// API keys are long random strings, so a fast hash is enough:
+    /// there is no dictionary to brute-force.
+    fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.expose_secret().as_bytes()))
+    }
+}
+
+fn random_alphanumeric(length: usize) -> String {
+    let mut rng = rand::rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+pub struct ApiKeyRecord {
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Create API key", skip(pool, name))]
+pub async fn create_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+) -> Result<ApiKey, anyhow::Error> {
+    let api_key = ApiKey::generate();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (api_key_id, user_id, name, prefix, key_hash, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        api_key.prefix(),
+        api_key.hash()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a new API key.")?;
+    Ok(api_key)
+}
+
+#[tracing::instrument(name = "List API keys", skip(pool))]
+pub async fn list_api_keys(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiKeyRecord>, anyhow::Error> {
+    let api_keys = sqlx::query_as!(
+        ApiKeyRecord,
+        r#"
+        SELECT api_key_id, name, prefix, created_at, last_used_at, revoked_at
+        FROM api_keys
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve API keys.")?;
+    Ok(api_keys)
+}
+
+/// Returns `false` if the user has no active key with that id.
+#[tracing::instrument(name = "Revoke API key", skip(pool))]
+pub async fn revoke_api_key(
+    pool: &PgPool,
+    user_id: Uuid,
+    api_key_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = now()
+        WHERE api_key_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        api_key_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke API key.")?
+    .rows_affected();
+    Ok(n_revoked > 0)
+}
+
+#[tracing::instrument(name = "Validate API key", skip_all, fields(api_key_prefix = %api_key.prefix()))]
+async fn validate_api_key(pool: &PgPool, api_key: &ApiKey) -> Result<Uuid, ApiError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT api_key_id, user_id, key_hash
+        FROM api_keys
+        WHERE prefix = $1 AND revoked_at IS NULL
+        "#,
+        api_key.prefix()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate the API key.")?;
+    let Some(row) = row.filter(|row| {
+        row.key_hash
+            .as_bytes()
+            .ct_eq(api_key.hash().as_bytes())
+            .into()
+    }) else {
+        return Err(ApiError::Unauthorized(anyhow::anyhow!(
+            "Unknown, revoked or invalid API key."
+        )));
+    };
+    // Only once the secret checks out: guessing at a prefix must leave no trace
+    sqlx::query!(
+        "UPDATE api_keys SET last_used_at = now() WHERE api_key_id = $1",
+        row.api_key_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the use of an API key.")?;
+    Ok(row.user_id)
+}
+
+/// Authenticate requests to the JSON API through an `Authorization: Bearer <api key>` header.
+/// The id of the key's owner is made available to handlers as `UserId`.
+pub async fn reject_invalid_api_keys(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let api_key = req
+        .headers()
+        .get(AUTHORIZATION)
+        .context("The 'Authorization' header was missing.")
+        .and_then(|value| {
+            value
+                .to_str()
+                .context("The 'Authorization' header was not a valid UTF8 string.")
+        })
+        .and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .context("The authorization scheme was not 'Bearer'.")
+        })
+        .and_then(ApiKey::parse)
+        .map_err(ApiError::Unauthorized)?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .context("The database pool is not registered.")
+        .map_err(ApiError::Unexpected)?;
+    let user_id = validate_api_key(pool, &api_key).await?;
+    req.extensions_mut().insert(UserId(user_id));
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiKey;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_generated_key_can_be_parsed_back() {
+        let api_key = ApiKey::generate();
+
+        let parsed = ApiKey::parse(&api_key.expose_secret());
+
+        assert_ok!(&parsed);
+        assert_eq!(parsed.unwrap().hash(), api_key.hash());
+    }
+
+    #[test]
+    fn keys_in_the_wrong_format_are_rejected() {
+        let api_key = ApiKey::generate().expose_secret();
+        for invalid_key in [
+            "".to_string(),
+            api_key.replacen("z2p", "abc", 1),
+            api_key[..api_key.len() - 1].to_string(),
+            format!("{}_", api_key),
+            api_key.replacen('_', "-", 2),
+        ] {
+            assert_err!(ApiKey::parse(&invalid_key), "{} was accepted", invalid_key);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use actix_multipart::Multipart;
+use actix_web::{
+    FromRequest, HttpMessage, HttpResponse,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::{InternalError, PayloadError},
+    http::header::HeaderMap,
+    middleware::Next,
+    web,
+};
+use futures_util::StreamExt;
+use rand::{Rng, distr::Alphanumeric};
+use subtle::ConstantTimeEq;
+
+use crate::{session_state::TypedSession, utility::e500};
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Header that non-form clients can use to submit the token instead
+/// of the `csrf_token` form field.
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// A per-session token that must accompany every state-changing
+/// request in the admin area.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn generate() -> Self {
+        let mut rng = rand::rng();
+        Self(
+            std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(char::from)
+                .take(TOKEN_LENGTH)
+                .collect(),
+        )
+    }
+
+    /// Compare in constant time, so the token cannot be guessed
+    /// one character at a time from response timings.
+    fn matches(&self, candidate: &str) -> bool {
+        self.0.as_bytes().ct_eq(candidate.as_bytes()).into()
+    }
+}
+
+impl From<String> for CsrfToken {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl AsRef<str> for CsrfToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CsrfFormData {
+    csrf_token: Option<String>,
+}
+
+/// Make the session's CSRF token available to handlers and reject
+/// any unsafe request (e.g. `POST`) that does not carry it.
+///
+/// The token can be submitted either as the `csrf_token` form field
+/// (urlencoded or multipart) or through the `X-CSRF-Token` header.
+pub async fn reject_invalid_csrf_token(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let csrf_token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => {
+            let token = CsrfToken::generate();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+
+    if !req.method().is_safe() {
+        let submitted_token = match req.headers().get(CSRF_HEADER) {
+            Some(value) => value.to_str().ok().map(ToOwned::to_owned),
+            None => {
+                // We need to read the body to get to the form field,
+                // put it back afterwards so the handler can still extract it.
+                let body = req.extract::<web::Bytes>().await?;
+                let token = if req.content_type() == "multipart/form-data" {
+                    csrf_token_from_multipart(req.headers(), body.clone()).await
+                } else {
+                    serde_urlencoded::from_bytes::<CsrfFormData>(&body)
+                        .ok()
+                        .and_then(|f| f.csrf_token)
+                };
+                req.set_payload(body.into());
+                token
+            }
+        };
+        match submitted_token {
+            Some(token) if csrf_token.matches(&token) => {}
+            _ => {
+                let e = anyhow::anyhow!("Missing or invalid CSRF token");
+                let response = HttpResponse::Forbidden().finish();
+                return Err(InternalError::from_response(e, response).into());
+            }
+        }
+    }
+
+    req.extensions_mut().insert(csrf_token);
+    next.call(req).await
+}
+
+/// File uploads are sent as multipart forms, with the token as one of the fields.
+async fn csrf_token_from_multipart(headers: &HeaderMap, body: web::Bytes) -> Option<String> {
+    let stream = futures_util::stream::once(async move { Ok::<_, PayloadError>(body) });
+    let mut multipart = Multipart::new(headers, stream);
+    while let Some(Ok(mut field)) = multipart.next().await {
+        if field.name() == Some("csrf_token") {
+            let mut value = Vec::new();
+            while let Some(Ok(chunk)) = field.next().await {
+                value.extend_from_slice(&chunk);
+            }
+            return String::from_utf8(value).ok();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsrfToken;
+
+    #[test]
+    fn generated_tokens_are_different() {
+        let token1 = CsrfToken::generate();
+        let token2 = CsrfToken::generate();
+        assert_ne!(token1.as_ref(), token2.as_ref());
+    }
+
+    #[test]
+    fn a_token_matches_itself_only() {
+        let token = CsrfToken::generate();
+        assert!(token.matches(token.as_ref()));
+        assert!(!token.matches(""));
+        assert!(!token.matches(&token.as_ref()[1..]));
+        assert!(!token.matches(CsrfToken::generate().as_ref()));
+    }
+}
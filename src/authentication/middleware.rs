@@ -15,7 +15,7 @@ use crate::{
 };
 
 #[derive(Copy, Clone, Debug)]
-pub struct UserId(Uuid);
+pub struct UserId(pub(super) Uuid);
 
 impl std::fmt::Display for UserId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -1,6 +1,7 @@
 use secrecy::{ExposeSecret, SecretString};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use std::net::IpAddr;
 
 use crate::{domain::SubscriberEmail, email_client::EmailClient};
 
@@ -10,6 +11,12 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: SecretString,
+    pub security_headers: SecurityHeadersSettings,
+    pub health_check: HealthCheckSettings,
+    pub metrics: MetricsSettings,
+    pub telemetry: TelemetrySettings,
+    pub tracking: TrackingSettings,
+    pub webhooks: WebhookSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -30,6 +37,125 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub base_url: String,
     pub hmac_secret: SecretString,
+    /// Proxies whose `Forwarded`/`X-Forwarded-For` headers we believe.
+    /// Requests from anywhere else are attributed to their peer address.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// Headers attached to every response. Leave a field out to skip the header.
+#[derive(serde::Deserialize, Clone)]
+pub struct SecurityHeadersSettings {
+    /// `{nonce}` is replaced with the nonce of the current request.
+    pub content_security_policy: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    /// Only enable this when the application is served over HTTPS.
+    pub hsts_max_age_seconds: Option<u64>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct HealthCheckSettings {
+    /// How long each dependency gets to answer the readiness probe.
+    pub timeout_milliseconds: u64,
+    /// Whether the readiness probe should also reach out to the email provider.
+    pub check_email_provider: bool,
+}
+
+impl HealthCheckSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct MetricsSettings {
+    /// When set, `/metrics` requires an `Authorization: Bearer <token>` header.
+    /// Required in production.
+    pub bearer_token: Option<SecretString>,
+}
+
+/// Opens and clicks of issues, for those published with tracking on.
+#[derive(serde::Deserialize, Clone)]
+pub struct TrackingSettings {
+    /// Turned off, issues are published untracked, and nothing is recorded
+    /// for those already sent.
+    pub enabled: bool,
+}
+
+/// What the email provider authenticates its webhooks with, as HTTP Basic
+/// credentials.
+#[derive(serde::Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub username: String,
+    /// Has no default, and must not be left to the one of `local.yaml` in
+    /// production.
+    pub password: SecretString,
+}
+
+/// The webhook password of `local.yaml`, which anyone reading the
+/// repository knows.
+const SAMPLE_WEBHOOK_PASSWORD: &str = "webhook-secret";
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TelemetrySettings {
+    /// Where to export spans over OTLP/HTTP, e.g. `http://localhost:4318/v1/traces`.
+    /// Spans are not exported when unset.
+    pub otlp_traces_endpoint: Option<String>,
+    /// Default filter (e.g. `info` or `zero2prod=debug,info`),
+    /// overridden by the `RUST_LOG` environment variable.
+    pub log_level: String,
+    pub log_format: LogFormat,
+    pub log_output: LogOutput,
+    /// Only used when logging to a file.
+    pub log_file: LogFileSettings,
+    /// How personal data about subscribers shows up in logs and spans.
+    pub redaction: RedactionPolicy,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line
+    Bunyan,
+    /// Multi-line and human-friendly, for local development
+    Pretty,
+    /// One human-readable line per event
+    Compact,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    Stdout,
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LogFileSettings {
+    pub directory: String,
+    /// Log files are named `<prefix>.<date>`.
+    pub prefix: String,
+    pub rotation: LogRotation,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionPolicy {
+    /// Log personal data as is
+    None,
+    /// Keep the first character and the email domain
+    Mask,
+    /// Replace with a short digest, so entries can still be correlated
+    Hash,
 }
 
 impl DatabaseSettings {
@@ -108,7 +234,29 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         )
         .build()?;
     // Try to convert the config values it read into our Settings type
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    if let Environment::Production = environment {
+        settings.check_production_secrets()?;
+    }
+    Ok(settings)
+}
+
+impl Settings {
+    /// Refuse to run in production with the secrets of local development,
+    /// or without those that have none.
+    fn check_production_secrets(&self) -> Result<(), config::ConfigError> {
+        if self.metrics.bearer_token.is_none() {
+            return Err(config::ConfigError::Message(
+                "metrics.bearer_token is required, set APP_METRICS__BEARER_TOKEN".into(),
+            ));
+        }
+        if self.webhooks.password.expose_secret() == SAMPLE_WEBHOOK_PASSWORD {
+            return Err(config::ConfigError::Message(
+                "webhooks.password is still the sample one, set APP_WEBHOOKS__PASSWORD".into(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 pub enum Environment {
@@ -1,7 +1,11 @@
+use base64::Engine;
 use reqwest::{Client, Error, Url};
 use secrecy::{ExposeSecret, SecretString};
 
+use crate::attachments::Attachment;
 use crate::domain::SubscriberEmail;
+use crate::metrics::metrics;
+use crate::telemetry::trace_context_headers;
 
 #[derive(Debug)]
 pub struct EmailClient {
@@ -34,6 +38,22 @@ impl EmailClient {
         html_content: &str,
         text_content: &str,
     ) -> Result<(), Error> {
+        self.send_issue(recipient, subject, Some(html_content), text_content, &[])
+            .await
+    }
+
+    /// An issue, with the files attached to it. There is no `html_content`
+    /// for recipients who asked not to get HTML email: the images it would
+    /// have shown are left out.
+    pub async fn send_issue(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: Option<&str>,
+        text_content: &str,
+        attachments: &[EncodedAttachment],
+    ) -> Result<(), Error> {
+        let _timer = metrics().time_email_send();
         let base_url = Url::parse(&self.base_url).expect("Base url should be valid");
         let url = base_url
             .join("email")
@@ -44,9 +64,18 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            attachments: attachments
+                .iter()
+                .filter(|a| html_content.is_some() || a.content_id.is_none())
+                .collect(),
         };
-        self.http_client
-            .post(url)
+        // Propagate our trace context, so the email API can join the trace
+        let trace_context = trace_context_headers();
+        let mut request = self.http_client.post(url);
+        for (name, value) in &trace_context {
+            request = request.header(name, value);
+        }
+        request
             .json(&request_body)
             .header(
                 "X-Postmark-Server-Token",
@@ -57,6 +86,16 @@ impl EmailClient {
             .error_for_status()?;
         Ok(())
     }
+
+    /// Check that the email API can be reached. Any answer that is not
+    /// a server error means the API is up, whatever the status code.
+    pub async fn check_reachability(&self) -> Result<(), Error> {
+        let response = self.http_client.head(&self.base_url).send().await?;
+        if response.status().is_server_error() {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -65,8 +104,38 @@ struct SendEmailRequest<'a> {
     from: &'a str,
     to: &'a str,
     subject: &'a str,
-    html_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'a str>,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<&'a EncodedAttachment>,
+}
+
+/// An attachment as the email API takes it. Encoding is left to callers,
+/// so that an issue's attachments are encoded once for all its recipients.
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct EncodedAttachment {
+    name: String,
+    /// Base64-encoded
+    content: String,
+    content_type: String,
+    /// `cid:<name>` for images shown in the HTML body
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+impl From<&Attachment> for EncodedAttachment {
+    fn from(attachment: &Attachment) -> Self {
+        Self {
+            name: attachment.file_name.clone(),
+            content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+            content_type: attachment.content_type.clone(),
+            content_id: attachment
+                .inline
+                .then(|| format!("cid:{}", attachment.file_name)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -224,5 +293,89 @@ mod email_client_tests {
         // Assert
         assert_err!(outcome);
     }
+
+    fn attachments() -> Vec<EncodedAttachment> {
+        let mut logo = Attachment::new("logo.png".into(), "image/png".into(), b"png".to_vec());
+        logo.inline = true;
+        let programme = Attachment::new(
+            "programme.pdf".into(),
+            "application/pdf".into(),
+            b"pdf".to_vec(),
+        );
+        vec![(&logo).into(), (&programme).into()]
+    }
+
+    #[tokio::test]
+    async fn send_issue_attaches_files_and_inline_images() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_issue(
+                &email(),
+                &subject(),
+                Some(&content()),
+                &content(),
+                &attachments(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([
+                {
+                    "Name": "logo.png",
+                    "Content": "cG5n",
+                    "ContentType": "image/png",
+                    "ContentID": "cid:logo.png",
+                },
+                {
+                    "Name": "programme.pdf",
+                    "Content": "cGRm",
+                    "ContentType": "application/pdf",
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn text_issues_leave_out_the_html_body_and_inline_images() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_issue(&email(), &subject(), None, &content(), &attachments())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body.get("HtmlBody").is_none());
+        assert!(body.get("TextBody").is_some());
+        let attachments = body["Attachments"].as_array().unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0]["Name"], "programme.pdf");
+    }
 }
 // Assert
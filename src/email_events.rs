@@ -0,0 +1,217 @@
+//! What the email provider tells us became of the emails we sent, through
+//! its webhooks. Addresses that bounced for good, or whose owner marked an
+//! issue as spam, get no more issues, even once their data is erased.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgExecutor, PgPool};
+
+/// A webhook payload from Postmark, as far as we care about it.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    #[serde(rename_all = "PascalCase")]
+    Bounce {
+        /// e.g. `HardBounce`, `SoftBounce`, `Transient`
+        r#type: String,
+        email: String,
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        bounced_at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "PascalCase")]
+    SpamComplaint {
+        email: String,
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        bounced_at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "PascalCase")]
+    Delivery {
+        recipient: String,
+        #[serde(rename = "MessageID")]
+        message_id: Option<String>,
+        delivered_at: DateTime<Utc>,
+    },
+    /// Opens, clicks and subscription changes: we track those ourselves
+    #[serde(other)]
+    Other,
+}
+
+/// Bounces after which the address will never accept our emails.
+const PERMANENT_BOUNCES: [&str; 2] = ["HardBounce", "BadEmailAddress"];
+
+impl PostmarkEvent {
+    /// Why issues should no longer be sent to the address, if they should not.
+    fn suppression_reason(&self) -> Option<&'static str> {
+        match self {
+            PostmarkEvent::Bounce { r#type, .. }
+                if PERMANENT_BOUNCES.contains(&r#type.as_str()) =>
+            {
+                Some("hard_bounce")
+            }
+            PostmarkEvent::SpamComplaint { .. } => Some("spam_complaint"),
+            _ => None,
+        }
+    }
+}
+
+/// Record `event` against the address it is about, suppressing the address
+/// if it should get no more issues.
+#[tracing::instrument(name = "Record an email event", skip(pool))]
+pub async fn record(pool: &PgPool, event: &PostmarkEvent) -> Result<(), anyhow::Error> {
+    let (email, kind, detail, message_id, occurred_at) = match event {
+        PostmarkEvent::Bounce {
+            r#type,
+            email,
+            message_id,
+            bounced_at,
+        } => (email, "bounce", Some(r#type), message_id, bounced_at),
+        PostmarkEvent::SpamComplaint {
+            email,
+            message_id,
+            bounced_at,
+        } => (email, "spam_complaint", None, message_id, bounced_at),
+        PostmarkEvent::Delivery {
+            recipient,
+            message_id,
+            delivered_at,
+        } => (recipient, "delivery", None, message_id, delivered_at),
+        PostmarkEvent::Other => return Ok(()),
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO email_events (subscriber_email, kind, detail, message_id, occurred_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        email,
+        kind,
+        detail,
+        message_id.as_deref(),
+        occurred_at
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to record an email event.")?;
+    if let Some(reason) = event.suppression_reason() {
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO email_suppressions (email_sha256, reason)
+            VALUES (email_sha256($1), $2)
+            ON CONFLICT (email_sha256) DO NOTHING
+            "#,
+            email,
+            reason
+        );
+        transaction
+            .execute(query)
+            .await
+            .context("Failed to suppress an address.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to record an email event.")?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEvent {
+    pub kind: String,
+    pub detail: Option<String>,
+    pub message_id: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl EmailEvent {
+    /// How the event reads on the admin pages.
+    pub fn description(&self) -> String {
+        match (self.kind.as_str(), &self.detail) {
+            ("delivery", _) => "Delivered".into(),
+            ("bounce", Some(detail)) => format!("Bounced ({})", detail),
+            ("spam_complaint", _) => "Marked as spam".into(),
+            (other, _) => other.into(),
+        }
+    }
+}
+
+/// What became of the emails sent to `email`, oldest first.
+pub async fn list(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Vec<EmailEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        EmailEvent,
+        r#"
+        SELECT kind, detail, message_id, occurred_at
+        FROM email_events
+        WHERE lower(subscriber_email) = lower($1)
+        ORDER BY occurred_at
+        "#,
+        email
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve email events.")?;
+    Ok(events)
+}
+
+/// Why `email` gets no more issues, if it does not. Whatever its case:
+/// addresses are matched on the hash of their lowercased form.
+pub async fn suppression_of(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    sqlx::query_scalar!(
+        "SELECT reason FROM email_suppressions WHERE email_sha256 = email_sha256($1)",
+        email
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve the suppression of an address.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkEvent;
+    use claims::{assert_none, assert_some_eq};
+
+    fn parse(payload: &str) -> PostmarkEvent {
+        serde_json::from_str(payload).unwrap()
+    }
+
+    #[test]
+    fn hard_bounces_and_complaints_suppress_the_address() {
+        let hard_bounce = parse(
+            r#"{"RecordType": "Bounce", "Type": "HardBounce", "TypeCode": 1,
+            "Email": "ursula@example.com", "MessageID": "abc",
+            "BouncedAt": "2025-05-21T16:33:54.9070259Z"}"#,
+        );
+        let complaint = parse(
+            r#"{"RecordType": "SpamComplaint", "Type": "SpamComplaint",
+            "Email": "ursula@example.com", "BouncedAt": "2025-05-21T16:33:54Z"}"#,
+        );
+        assert_some_eq!(hard_bounce.suppression_reason(), "hard_bounce");
+        assert_some_eq!(complaint.suppression_reason(), "spam_complaint");
+    }
+
+    #[test]
+    fn soft_bounces_and_deliveries_do_not() {
+        let soft_bounce = parse(
+            r#"{"RecordType": "Bounce", "Type": "SoftBounce",
+            "Email": "ursula@example.com", "BouncedAt": "2025-05-21T16:33:54Z"}"#,
+        );
+        let delivery = parse(
+            r#"{"RecordType": "Delivery", "Recipient": "ursula@example.com",
+            "MessageID": "abc", "DeliveredAt": "2025-05-21T13:28:10.2735393-04:00"}"#,
+        );
+        let open = parse(r#"{"RecordType": "Open", "Recipient": "ursula@example.com"}"#);
+        assert_none!(soft_bounce.suppression_reason());
+        assert_none!(delivery.suppression_reason());
+        assert!(matches!(open, PostmarkEvent::Other));
+    }
+}
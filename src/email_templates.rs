@@ -0,0 +1,392 @@
+//! The emails we send besides issues, and the layout every issue is
+//! wrapped in.
+//!
+//! Each has a default template, shipped in `email_templates/`. Admins can
+//! replace it from the admin area: edited templates are stored in the
+//! database, and the default is used again once they are reset.
+//!
+//! There is no password reset email: admins have no email address on file,
+//! and change their password from the admin area once logged in.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::personalisation::{Template, escape_html};
+
+/// Where the issue goes in the layout.
+const CONTENT: &str = "content";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailKind {
+    Confirmation,
+    PersonalData,
+    EmailChange,
+    NewsletterLayout,
+}
+
+impl EmailKind {
+    pub const ALL: [EmailKind; 4] = [
+        EmailKind::Confirmation,
+        EmailKind::PersonalData,
+        EmailKind::EmailChange,
+        EmailKind::NewsletterLayout,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailKind::Confirmation => "confirmation",
+            EmailKind::PersonalData => "personal_data",
+            EmailKind::EmailChange => "email_change",
+            EmailKind::NewsletterLayout => "newsletter_layout",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            EmailKind::Confirmation => "Sent to new subscribers, to confirm their subscription",
+            EmailKind::PersonalData => "Sent to those asking for the data we hold about them",
+            EmailKind::EmailChange => "Sent to the new address of subscribers changing it",
+            EmailKind::NewsletterLayout => "Wraps every issue",
+        }
+    }
+
+    /// The placeholders the template can use.
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            EmailKind::Confirmation => &["name", "list_name", "confirmation_link"],
+            EmailKind::PersonalData => &["link", "validity_hours"],
+            EmailKind::EmailChange => &["confirmation_link", "validity_hours"],
+            // The placeholders of issues are left for the delivery worker
+            EmailKind::NewsletterLayout => &[
+                CONTENT,
+                "name",
+                "email",
+                "unsubscribe_url",
+                "web_view_url",
+                "preferences_url",
+            ],
+        }
+    }
+
+    /// Layouts have none: issues have a title.
+    pub fn has_subject(&self) -> bool {
+        *self != EmailKind::NewsletterLayout
+    }
+
+    pub fn default_template(&self) -> EmailTemplate {
+        let (subject, html_body, text_body) = match self {
+            EmailKind::Confirmation => (
+                "Welcome to {{ list_name }}!",
+                include_str!("../email_templates/confirmation.html"),
+                include_str!("../email_templates/confirmation.txt"),
+            ),
+            EmailKind::PersonalData => (
+                "Your personal data",
+                include_str!("../email_templates/personal_data.html"),
+                include_str!("../email_templates/personal_data.txt"),
+            ),
+            EmailKind::EmailChange => (
+                "Confirm your new email address",
+                include_str!("../email_templates/email_change.html"),
+                include_str!("../email_templates/email_change.txt"),
+            ),
+            EmailKind::NewsletterLayout => (
+                "",
+                include_str!("../email_templates/newsletter_layout.html"),
+                include_str!("../email_templates/newsletter_layout.txt"),
+            ),
+        };
+        EmailTemplate {
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+        }
+    }
+
+    /// Made-up values, for previews.
+    pub fn example(&self, variable: &str) -> String {
+        match (self, variable) {
+            (EmailKind::NewsletterLayout, CONTENT) => "The content of an issue.",
+            (_, "name") => "Ursula",
+            (_, "email") => "ursula@example.com",
+            (_, "list_name") => "Our newsletter",
+            (EmailKind::Confirmation, "confirmation_link") => {
+                "https://example.com/subscriptions/confirm?subscription_token=example"
+            }
+            (_, "confirmation_link") => {
+                "https://example.com/preferences/confirm_email?subscription_token=example"
+            }
+            (_, "link") => "https://example.com/personal_data/example",
+            (_, "validity_hours") => "24",
+            (_, "unsubscribe_url") => "https://example.com/preferences/example/unsubscribe",
+            (_, "web_view_url") => "https://example.com/issues/example/example",
+            (_, "preferences_url") => "https://example.com/preferences/example",
+            _ => "",
+        }
+        .into()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+/// An email ready to be sent.
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl EmailTemplate {
+    /// Every part must only use the placeholders of `kind`, and layouts must
+    /// say where the issue goes.
+    pub fn check(&self, kind: EmailKind) -> Result<(), String> {
+        let parts = [
+            ("subject", &self.subject),
+            ("HTML body", &self.html_body),
+            ("text body", &self.text_body),
+        ];
+        for (part, source) in parts {
+            let template = Template::parse_with(source, kind.variables())
+                .map_err(|e| format!("In the {}: {}", part, e))?;
+            let is_body = part != "subject";
+            if kind == EmailKind::NewsletterLayout
+                && is_body
+                && template.variables().filter(|v| *v == CONTENT).count() != 1
+            {
+                return Err(format!(
+                    "The {} must contain `{{{{ {} }}}}` exactly once.",
+                    part, CONTENT
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the placeholders with `value_of` their variable,
+    /// escaped in the HTML body.
+    pub fn render(&self, kind: EmailKind, value_of: impl Fn(&str) -> String) -> RenderedEmail {
+        let render =
+            |source: &str, escape: bool| match Template::parse_with(source, kind.variables()) {
+                Ok(template) => template.render_with(|variable| {
+                    if escape {
+                        escape_html(&value_of(variable))
+                    } else {
+                        value_of(variable)
+                    }
+                }),
+                // Templates are checked before they are stored
+                Err(_) => source.to_owned(),
+            };
+        RenderedEmail {
+            subject: render(&self.subject, false),
+            html_body: render(&self.html_body, true),
+            text_body: render(&self.text_body, false),
+        }
+    }
+
+    /// Put the bodies of an issue in this layout, leaving the other
+    /// placeholders to be filled in for each subscriber.
+    pub fn wrap(&self, html_content: &str, text_content: &str) -> (String, String) {
+        let wrap = |source: &str, content: &str| match Template::parse_with(
+            source,
+            EmailKind::NewsletterLayout.variables(),
+        ) {
+            Ok(template) => template.render_with(|variable| match variable {
+                CONTENT => content.to_owned(),
+                variable => format!("{{{{ {} }}}}", variable),
+            }),
+            Err(_) => content.to_owned(),
+        };
+        (
+            wrap(&self.html_body, html_content),
+            wrap(&self.text_body, text_content),
+        )
+    }
+
+    /// Everything filled in with made-up values.
+    pub fn preview(&self, kind: EmailKind) -> RenderedEmail {
+        self.render(kind, |variable| kind.example(variable))
+    }
+}
+
+/// The template of `kind`, as edited by admins if it was.
+///
+/// Edited templates that no longer check out, e.g. after a variable was
+/// renamed, are replaced with the default.
+pub async fn load(
+    executor: impl PgExecutor<'_>,
+    kind: EmailKind,
+) -> Result<EmailTemplate, anyhow::Error> {
+    let stored = sqlx::query_as!(
+        EmailTemplate,
+        "SELECT subject, html_body, text_body FROM email_templates WHERE name = $1",
+        kind.name()
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve an email template.")?;
+    match stored {
+        Some(template) => match template.check(kind) {
+            Ok(()) => Ok(template),
+            Err(e) => {
+                tracing::warn!(template = kind.name(), error = %e, "Invalid email template");
+                Ok(kind.default_template())
+            }
+        },
+        None => Ok(kind.default_template()),
+    }
+}
+
+/// When each edited template was last saved, by name.
+pub async fn customised(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<(String, DateTime<Utc>)>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT name, updated_at FROM email_templates")
+        .fetch_all(executor)
+        .await
+        .context("Failed to retrieve the email templates.")?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.name, row.updated_at))
+        .collect())
+}
+
+/// Must have been checked.
+pub async fn save(
+    executor: impl PgExecutor<'_>,
+    kind: EmailKind,
+    template: &EmailTemplate,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_templates (name, subject, html_body, text_body)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO UPDATE SET
+            subject = EXCLUDED.subject,
+            html_body = EXCLUDED.html_body,
+            text_body = EXCLUDED.text_body,
+            updated_at = now()
+        "#,
+        kind.name(),
+        template.subject,
+        template.html_body,
+        template.text_body
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store an email template.")?;
+    Ok(())
+}
+
+/// Go back to the default template.
+pub async fn reset(executor: impl PgExecutor<'_>, kind: EmailKind) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM email_templates WHERE name = $1", kind.name())
+        .execute(executor)
+        .await
+        .context("Failed to reset an email template.")?;
+    Ok(())
+}
+
+/// Render the template of `kind` with `values` and send it to `recipient`.
+pub async fn send(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    kind: EmailKind,
+    recipient: &SubscriberEmail,
+    values: &[(&str, &str)],
+) -> Result<(), anyhow::Error> {
+    let email = load(pool, kind).await?.render(kind, |variable| {
+        values
+            .iter()
+            .find(|(name, _)| *name == variable)
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default()
+    });
+    email_client
+        .send_email(
+            recipient,
+            &email.subject,
+            &email.html_body,
+            &email.text_body,
+        )
+        .await
+        .with_context(|| format!("Failed to send the {} email.", kind.name()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailKind, EmailTemplate};
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn default_templates_check_out() {
+        for kind in EmailKind::ALL {
+            assert_ok!(kind.default_template().check(kind));
+            assert_eq!(EmailKind::parse(kind.name()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn templates_only_use_their_own_variables() {
+        let template = EmailTemplate {
+            subject: "Your data".into(),
+            html_body: "{{ link }}".into(),
+            text_body: "{{ confirmation_link }}".into(),
+        };
+        let error = template.check(EmailKind::PersonalData).unwrap_err();
+        assert!(error.starts_with("In the text body: There is no `confirmation_link` variable"));
+    }
+
+    #[test]
+    fn layouts_need_the_content_exactly_once() {
+        let layout = |html_body: &str| EmailTemplate {
+            subject: "".into(),
+            html_body: html_body.into(),
+            text_body: "{{ content }}".into(),
+        };
+        assert_err!(layout("<div></div>").check(EmailKind::NewsletterLayout));
+        assert_err!(layout("{{ content }}{{content}}").check(EmailKind::NewsletterLayout));
+        assert_ok!(layout("{{content}}{{ name }}").check(EmailKind::NewsletterLayout));
+    }
+
+    #[test]
+    fn values_are_escaped_in_the_html_body_only() {
+        let template = EmailTemplate {
+            subject: "Hi {{ name }}".into(),
+            html_body: "<p>Hi {{ name }}</p>".into(),
+            text_body: "Hi {{ name }}".into(),
+        };
+        let email = template.render(EmailKind::Confirmation, |_| "<Ursula>".into());
+        assert_eq!(email.subject, "Hi <Ursula>");
+        assert_eq!(email.html_body, "<p>Hi &lt;Ursula&gt;</p>");
+        assert_eq!(email.text_body, "Hi <Ursula>");
+    }
+
+    #[test]
+    fn layouts_keep_the_placeholders_of_issues() {
+        let layout = EmailTemplate {
+            subject: "".into(),
+            html_body: "<div>{{content}}<a href=\"{{preferences_url}}\">x</a></div>".into(),
+            text_body: "{{ content }}\n-- \n{{ name }}".into(),
+        };
+        let (html, text) = layout.wrap("<p>Hi {{ name }}</p>", "Hi {{ name }}");
+        assert_eq!(
+            html,
+            "<div><p>Hi {{ name }}</p><a href=\"{{ preferences_url }}\">x</a></div>"
+        );
+        assert_eq!(text, "Hi {{ name }}\n-- \n{{ name }}");
+    }
+}
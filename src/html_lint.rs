@@ -0,0 +1,316 @@
+//! Checks on the HTML content of issues written by hand, before they are
+//! published. Whatever could run a script or point somewhere unsafe is
+//! removed, and whatever email clients may show badly is pointed out.
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+};
+
+/// Gmail clips messages whose HTML is larger than this.
+const CLIPPED_AT: usize = 102 * 1024;
+
+/// Elements without content, and so without end tag.
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose end tag can be left out.
+const OPTIONAL_END_TAGS: [&str; 17] = [
+    "p", "li", "dt", "dd", "tr", "td", "th", "thead", "tbody", "tfoot", "caption", "colgroup",
+    "option", "optgroup", "rt", "rp", "rtc",
+];
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Finding {
+    #[error("<{0}> is not allowed: it is removed.")]
+    DisallowedTag(String),
+    #[error("<{0}> is not allowed: it is removed, with its content.")]
+    DisallowedContent(String),
+    #[error("The `{1}` attribute of <{0}> is not allowed: it is removed.")]
+    DisallowedAttribute(String, String),
+    #[error("`{0}:` addresses are not allowed: the link to `{1}` is removed.")]
+    DisallowedUrl(String, String),
+    #[error("Comments are removed.")]
+    Comment,
+    #[error("Line {0}: the HTML is malformed ({1}).")]
+    Malformed(u64, String),
+    #[error("Line {0}: <{1}> is not closed.")]
+    Unclosed(u64, String),
+    #[error("Line {0}: </{1}> does not close anything.")]
+    Stray(u64, String),
+    #[error("Line {0}: the image `{1}` has no alt text, shown when images are not loaded.")]
+    MissingAlt(u64, String),
+    #[error("The HTML content is {0} KB: Gmail clips messages larger than 102 KB.")]
+    TooLarge(usize),
+}
+
+/// HTML content, as it will be sent, and what was found in it.
+#[derive(Debug)]
+pub struct CheckedHtml {
+    pub html: String,
+    pub findings: Vec<Finding>,
+}
+
+/// What issues may contain: the defaults of ammonia, plus the presentational
+/// attributes emails are laid out with.
+fn sanitiser() -> ammonia::Builder<'static> {
+    let mut sanitiser = ammonia::Builder::default();
+    sanitiser
+        .add_tags(["font"])
+        .add_tag_attributes("font", ["color", "face", "size"])
+        .add_generic_attributes([
+            "align",
+            "bgcolor",
+            "border",
+            "cellpadding",
+            "cellspacing",
+            "class",
+            "dir",
+            "height",
+            "style",
+            "valign",
+            "width",
+        ])
+        // Images attached to the issue
+        .add_url_schemes(["cid"])
+        // Links are kept as they were written
+        .link_rel(None);
+    sanitiser
+}
+
+/// Remove whatever issues may not contain from `html`.
+pub fn sanitise(html: &str) -> String {
+    sanitiser().clean(html).to_string()
+}
+
+/// Sanitise `html`, listing what was removed and what looks wrong.
+pub fn check(html: &str) -> CheckedHtml {
+    let sanitiser = sanitiser();
+    let linter = Linter::new(&sanitiser);
+    let input = BufferQueue::default();
+    input.push_back(html.into());
+    let tokenizer = Tokenizer::new(
+        linter,
+        TokenizerOpts {
+            exact_errors: true,
+            ..Default::default()
+        },
+    );
+    let _ = tokenizer.feed(&input);
+    tokenizer.end();
+    let mut findings = tokenizer.sink.finish();
+
+    let html = sanitiser.clean(html).to_string();
+    if html.len() > CLIPPED_AT {
+        findings.push(Finding::TooLarge(html.len().div_ceil(1024)));
+    }
+    CheckedHtml { html, findings }
+}
+
+/// Goes through the tags of the HTML, as the sanitiser will.
+struct Linter {
+    tags: HashSet<&'static str>,
+    clean_content_tags: HashSet<&'static str>,
+    tag_attributes: HashMap<&'static str, HashSet<&'static str>>,
+    generic_attributes: HashSet<&'static str>,
+    url_schemes: HashSet<&'static str>,
+    state: RefCell<LintState>,
+}
+
+#[derive(Default)]
+struct LintState {
+    findings: Vec<Finding>,
+    /// The elements open, and the line they were opened on
+    open: Vec<(String, u64)>,
+}
+
+impl Linter {
+    fn new(sanitiser: &ammonia::Builder<'static>) -> Self {
+        Self {
+            tags: sanitiser.clone_tags(),
+            clean_content_tags: sanitiser.clone_clean_content_tags(),
+            tag_attributes: sanitiser.clone_tag_attributes(),
+            generic_attributes: sanitiser.clone_generic_attributes(),
+            url_schemes: sanitiser.clone_url_schemes(),
+            state: Default::default(),
+        }
+    }
+
+    fn start_tag(&self, tag: &Tag, line: u64, state: &mut LintState) {
+        let name = &*tag.name;
+        if self.clean_content_tags.contains(name) {
+            state.report(Finding::DisallowedContent(name.into()));
+        } else if !self.tags.contains(name) {
+            state.report(Finding::DisallowedTag(name.into()));
+        } else {
+            for attribute in &tag.attrs {
+                let attribute_name = &*attribute.name.local;
+                let allowed = self.generic_attributes.contains(attribute_name)
+                    || self
+                        .tag_attributes
+                        .get(name)
+                        .is_some_and(|allowed| allowed.contains(attribute_name));
+                if !allowed {
+                    state.report(Finding::DisallowedAttribute(
+                        name.into(),
+                        attribute_name.into(),
+                    ));
+                } else if matches!(attribute_name, "href" | "src")
+                    && let Some(scheme) = scheme(&attribute.value)
+                    && !self.url_schemes.contains(scheme.to_lowercase().as_str())
+                {
+                    state.report(Finding::DisallowedUrl(
+                        scheme.to_lowercase(),
+                        attribute.value.to_string(),
+                    ));
+                }
+            }
+            if name == "img" && !tag.attrs.iter().any(|a| &*a.name.local == "alt") {
+                let src = tag
+                    .attrs
+                    .iter()
+                    .find(|a| &*a.name.local == "src")
+                    .map(|a| a.value.to_string())
+                    .unwrap_or_default();
+                state.report(Finding::MissingAlt(line, src));
+            }
+        }
+        if !VOID_ELEMENTS.contains(&name) && !tag.self_closing {
+            state.open.push((name.into(), line));
+        }
+    }
+
+    fn end_tag(&self, tag: &Tag, line: u64, state: &mut LintState) {
+        let name = &*tag.name;
+        let Some(position) = state.open.iter().rposition(|(open, _)| open == name) else {
+            state.report(Finding::Stray(line, name.into()));
+            return;
+        };
+        for (inner, opened_on) in state.open.split_off(position).into_iter().skip(1) {
+            if !OPTIONAL_END_TAGS.contains(&inner.as_str()) {
+                state.report(Finding::Unclosed(opened_on, inner));
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<Finding> {
+        let mut state = self.state.into_inner();
+        for (name, opened_on) in std::mem::take(&mut state.open) {
+            if !OPTIONAL_END_TAGS.contains(&name.as_str()) {
+                state.report(Finding::Unclosed(opened_on, name));
+            }
+        }
+        state.findings
+    }
+}
+
+impl LintState {
+    /// Each finding is reported once.
+    fn report(&mut self, finding: Finding) {
+        if !self.findings.contains(&finding) {
+            self.findings.push(finding);
+        }
+    }
+}
+
+impl TokenSink for Linter {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, line: u64) -> TokenSinkResult<()> {
+        let mut state = self.state.borrow_mut();
+        match token {
+            Token::TagToken(tag) if tag.kind == TagKind::StartTag => {
+                self.start_tag(&tag, line, &mut state);
+                // Their content is text, not markup
+                match &*tag.name {
+                    "script" => return TokenSinkResult::RawData(RawKind::ScriptData),
+                    "style" => return TokenSinkResult::RawData(RawKind::Rawtext),
+                    "title" | "textarea" => return TokenSinkResult::RawData(RawKind::Rcdata),
+                    _ => {}
+                }
+            }
+            Token::TagToken(tag) => self.end_tag(&tag, line, &mut state),
+            Token::CommentToken(_) => state.report(Finding::Comment),
+            Token::ParseError(error) => state.report(Finding::Malformed(line, error.into())),
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+}
+
+/// The scheme of `url`, if it is absolute.
+fn scheme(url: &str) -> Option<&str> {
+    // Browsers ignore leading spaces and control characters
+    let url = url.trim_start_matches(|c: char| c <= ' ');
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next()?.is_ascii_alphabetic()
+        && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    valid.then_some(scheme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Finding, check};
+
+    #[test]
+    fn clean_html_is_left_alone() {
+        let html = "<p>Hi {{ name }}, <a href=\"{{ unsubscribe_url }}\">unsubscribe</a>.</p>\n\
+            <table width=\"100%\"><tbody><tr><td style=\"color: red\">\
+            <img src=\"https://example.com/cat.png\" alt=\"A cat\"></td></tr></tbody></table>";
+        let checked = check(html);
+        assert_eq!(checked.findings, vec![]);
+        assert_eq!(checked.html, html);
+    }
+
+    #[test]
+    fn scripts_and_their_ways_in_are_removed() {
+        let checked = check(
+            "<script>if (a < b) alert(1)</script>\
+            <p onclick=\"alert(1)\">Hi</p>\
+            <a href=\" javascript:alert(1)\">x</a><!-- note -->",
+        );
+        assert_eq!(
+            checked.findings,
+            vec![
+                Finding::DisallowedContent("script".into()),
+                Finding::DisallowedAttribute("p".into(), "onclick".into()),
+                Finding::DisallowedUrl("javascript".into(), " javascript:alert(1)".into()),
+                Finding::Comment,
+            ]
+        );
+        assert_eq!(checked.html, "<p>Hi</p><a>x</a>");
+    }
+
+    #[test]
+    fn broken_structure_is_reported() {
+        let checked = check("<div><p>One\n<li>Two\n<b>bold</div>\n</span><div>");
+        assert_eq!(
+            checked.findings,
+            vec![
+                Finding::Unclosed(3, "b".into()),
+                Finding::Stray(4, "span".into()),
+                Finding::Unclosed(4, "div".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn images_need_an_alt_text() {
+        let checked = check("<img src=\"a.png\"><img src=\"b.png\" alt=\"\">");
+        assert_eq!(
+            checked.findings,
+            vec![Finding::MissingAlt(1, "a.png".into())]
+        );
+    }
+
+    #[test]
+    fn large_content_is_reported() {
+        let checked = check(&"<p>Lorem ipsum</p>".repeat(6000));
+        assert_eq!(checked.findings, vec![Finding::TooLarge(106)]);
+    }
+}
@@ -0,0 +1,139 @@
+//! The confirmation emails of subscribers imported with
+//! `send_confirmations`. Imports only queue them, so that large files do
+//! not keep the request waiting on the email API: the worker sends them,
+//! and notes on the import report those it could not send.
+use anyhow::Context;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::routes::subscriptions::send_confirmation_email;
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
+
+/// What the report says of rows whose confirmation email was not sent.
+const NOT_SENT: &str = "The confirmation email could not be sent, \
+    re-send it from the subscriber's page.";
+
+/// Queue the confirmation email of the subscriber imported from
+/// `line_number`, along with the rest of their import.
+pub async fn enqueue(
+    transaction: &mut Transaction<'_, Postgres>,
+    import_id: Uuid,
+    line_number: i32,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO import_confirmation_queue
+            (import_id, line_number, subscriber_id, subscription_token)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        import_id,
+        line_number,
+        subscriber_id,
+        subscription_token
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to queue the confirmation email of an imported subscriber.")?;
+    Ok(())
+}
+
+/// Send the next queued confirmation email, if there is one.
+#[tracing::instrument(
+    skip_all,
+    fields(import_id=tracing::field::Empty, line_number=tracing::field::Empty),
+    err
+)]
+pub async fn try_send_next(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(task) = sqlx::query!(
+        r#"
+        SELECT q.import_id, q.line_number, q.subscriber_id, q.subscription_token,
+            s.email, s.name, l.name AS list_name
+        FROM import_confirmation_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        JOIN lists l ON l.list_id = s.list_id
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current()
+        .record("import_id", tracing::field::display(task.import_id))
+        .record("line_number", task.line_number);
+    let sent = async {
+        let new_sub = NewSubscriber {
+            email: SubscriberEmail::parse(task.email).map_err(anyhow::Error::msg)?,
+            name: SubscriberName::parse(task.name).map_err(anyhow::Error::msg)?,
+        };
+        send_confirmation_email(
+            pool,
+            email_client,
+            new_sub,
+            &task.list_name,
+            base_url,
+            &task.subscription_token,
+        )
+        .await
+    }
+    .await;
+    match sent {
+        Ok(()) => {
+            subscription_events::record(
+                &mut *transaction,
+                task.subscriber_id,
+                NewEvent {
+                    event_type: EventType::ConfirmationEmailSent,
+                    source: EventSource::Import,
+                    // Sent by the worker, long after the import request
+                    origin: &RequestOrigin::default(),
+                    subscription_token: Some(&task.subscription_token),
+                },
+            )
+            .await?;
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the confirmation email of an imported subscriber"
+            );
+            let query = sqlx::query!(
+                r#"
+                UPDATE subscriber_import_rows
+                SET detail = $3
+                WHERE import_id = $1 AND line_number = $2
+                "#,
+                task.import_id,
+                task.line_number,
+                NOT_SENT
+            );
+            transaction.execute(query).await?;
+        }
+    }
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM import_confirmation_queue
+        WHERE import_id = $1 AND line_number = $2
+        "#,
+        task.import_id,
+        task.line_number
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
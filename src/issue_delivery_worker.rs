@@ -5,26 +5,63 @@ use tracing::{Span, field::display};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
+    attachments,
+    configuration::{Settings, TrackingSettings},
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EncodedAttachment},
+    email_events, import_confirmations,
+    metrics::{EmailOutcome, metrics},
+    personal_data,
+    personalisation::{Variables, personalise},
+    preferences::{self, EmailFormat},
     startup::get_connection_pool,
+    telemetry::{Pii, link_to_traceparent},
+    tracking,
 };
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(&connection_pool, email_client).await
+    worker_loop(
+        &connection_pool,
+        email_client,
+        &configuration.application.base_url,
+        &configuration.tracking,
+    )
+    .await
 }
 
-async fn worker_loop(pool: &PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+async fn worker_loop(
+    pool: &PgPool,
+    email_client: EmailClient,
+    base_url: &str,
+    tracking_settings: &TrackingSettings,
+) -> Result<(), anyhow::Error> {
+    let mut issues = IssueCache::default();
     loop {
-        match try_execute_task(pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
-            }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        let outcomes = [
+            try_execute_task(
+                pool,
+                &email_client,
+                base_url,
+                tracking_settings,
+                &mut issues,
+            )
+            .await,
+            import_confirmations::try_send_next(pool, &email_client, base_url).await,
+            personal_data::try_answer_next_request(pool, &email_client, base_url).await,
+        ];
+        if let Ok(ExecutionOutcome::EmptyQueue) = outcomes[0] {
+            // The issues sent so far have been sent to everyone
+            issues.clear();
+        }
+        if outcomes.iter().any(Result::is_err) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        } else if outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, Ok(ExecutionOutcome::EmptyQueue)))
+        {
+            tokio::time::sleep(Duration::from_secs(10)).await;
         }
     }
 }
@@ -38,65 +75,159 @@ pub enum ExecutionOutcome {
 	skip_all,
 	fields(
 		newsletter_issue_id=tracing::field::Empty,
-		subscripber_email=tracing::field::Empty
+		subscriber_email=tracing::field::Empty,
+		publish_request_id=tracing::field::Empty,
+		published_by=tracing::field::Empty
 	),
 	err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    tracking_settings: &TrackingSettings,
+    issues: &mut IssueCache,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", display(issue_id))
-        .record("subscriber_email", display(&email));
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
+    let (transaction, task) = task.unwrap();
+    let DeliveryTask {
+        newsletter_issue_id: issue_id,
+        subscriber_email: email,
+        published_by,
+        publish_request_id,
+        publish_traceparent,
+    } = task;
+    let span = Span::current();
+    span.record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(Pii(&email)));
+    // Relate the delivery to the request that published the issue
+    if let Some(request_id) = publish_request_id {
+        span.record("publish_request_id", display(request_id));
+    }
+    if let Some(user_id) = published_by {
+        span.record("published_by", display(user_id));
+    }
+    if let Some(traceparent) = publish_traceparent {
+        link_to_traceparent(&span, &traceparent);
+    }
+    // The address may have bounced since the issue was published
+    let suppression = email_events::suppression_of(pool, &email).await?;
+    let outcome = match (SubscriberEmail::parse(email.clone()), suppression) {
+        (Ok(_), Some(reason)) => {
+            tracing::info!(
+                suppression.reason = %reason,
+                "Skipping a confirmed subscriber. \
+                Their address no longer gets issues.",
+            );
+            EmailOutcome::Skipped
+        }
+        (Ok(email), None) => {
+            let issue = issues.get(pool, issue_id).await?;
+            let variables =
+                Variables::for_subscriber(pool, base_url, issue_id, email.as_ref()).await?;
+            let text_content = format!(
+                "{}\n\n--\nManage your subscription: {}",
+                personalise(&issue.text_content, &variables, false),
+                variables.preferences_url
+            );
+            let sent = match preferences::format_of(pool, email.as_ref()).await? {
+                EmailFormat::Html => {
+                    // Before personalising: links to the subscriber's own pages
+                    // are not tracked. Issues published with tracking on are
+                    // sent untracked once it is turned off.
+                    let html_content = if issue.tracked && tracking_settings.enabled {
+                        tracking::instrument(
+                            pool,
+                            base_url,
+                            issue_id,
+                            email.as_ref(),
+                            &issue.html_content,
+                        )
+                        .await?
+                    } else {
+                        issue.html_content.clone()
+                    };
+                    let html_content = format!(
+                        "{}<p><a href=\"{}\">Manage your subscription</a></p>",
+                        personalise(&html_content, &variables, true),
+                        variables.preferences_url
+                    );
+                    email_client
+                        .send_issue(
+                            &email,
+                            &issue.title,
+                            Some(&html_content),
+                            &text_content,
+                            &issue.attachments,
+                        )
+                        .await
+                }
+                EmailFormat::Text => {
+                    email_client
+                        .send_issue(
+                            &email,
+                            &issue.title,
+                            None,
+                            &text_content,
+                            &issue.attachments,
+                        )
+                        .await
+                }
+            };
+            if let Err(e) = sent {
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
                     "Failed to deliver issue to a confirmed subscriber. \
                     Skipping.",
-                )
+                );
+                EmailOutcome::Failed
+            } else {
+                EmailOutcome::Sent
             }
         }
-        Err(e) => {
+        (Err(e), _) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
                 "Skipping a confirmed subscriber. \
                 Their stored contact details are invalid.",
             );
+            EmailOutcome::Skipped
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    complete_task(transaction, issue_id, &email, &outcome).await?;
+    metrics().record_email(outcome);
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    published_by: Option<Uuid>,
+    publish_request_id: Option<Uuid>,
+    publish_traceparent: Option<String>,
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let row = sqlx::query!(
+    let task = sqlx::query_as!(
+        DeliveryTask,
         r#"
-		SELECT newsletter_issue_id, subscriber_email
+		SELECT
+			newsletter_issue_id,
+			subscriber_email,
+			published_by,
+			publish_request_id,
+			publish_traceparent
 		FROM issue_delivery_queue
 		FOR UPDATE
 		SKIP LOCKED
@@ -105,23 +236,29 @@ async fn dequeue_task(
     )
     .fetch_optional(&mut *transaction)
     .await?;
-    if let Some(row) = row {
-        Ok(Some((
-            transaction,
-            row.newsletter_issue_id,
-            row.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok(task.map(|task| (transaction, task)))
 }
 
+/// Record what happened to the delivery and remove it from the queue.
 #[tracing::instrument(skip_all)]
-async fn delete_task(
+async fn complete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
     email: &str,
+    outcome: &EmailOutcome,
 ) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+		INSERT INTO newsletter_deliveries (newsletter_issue_id, subscriber_email, outcome)
+		VALUES ($1, $2, $3)
+		ON CONFLICT (newsletter_issue_id, subscriber_email)
+		DO UPDATE SET outcome = EXCLUDED.outcome, delivered_at = now()
+		"#,
+        issue_id,
+        email,
+        outcome.as_str()
+    );
+    transaction.execute(query).await?;
     let query = sqlx::query!(
         r#"
 		DELETE FROM issue_delivery_queue
@@ -141,14 +278,53 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    /// Whether its opens and clicks are tracked
+    tracked: bool,
+    attachments: Vec<EncodedAttachment>,
+}
+
+/// How many issues, attachments included, the worker keeps in memory.
+const CACHED_ISSUES: usize = 4;
+
+/// The issues being delivered, loaded and with their attachments encoded
+/// once rather than for each recipient. Only the most recently used ones
+/// are kept, least recently used first.
+#[derive(Default)]
+pub struct IssueCache(Vec<(Uuid, NewsletterIssue)>);
+
+impl IssueCache {
+    async fn get(
+        &mut self,
+        pool: &PgPool,
+        issue_id: Uuid,
+    ) -> Result<&NewsletterIssue, anyhow::Error> {
+        match self.0.iter().position(|(id, _)| *id == issue_id) {
+            Some(index) => {
+                let entry = self.0.remove(index);
+                self.0.push(entry);
+            }
+            None => {
+                let issue = get_issue(pool, issue_id).await?;
+                if self.0.len() == CACHED_ISSUES {
+                    self.0.remove(0);
+                }
+                self.0.push((issue_id, issue));
+            }
+        }
+        let (_, issue) = self.0.last().expect("The issue was just cached");
+        Ok(issue)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
 }
 
 #[tracing::instrument(skip_all)]
 async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+    let issue = sqlx::query!(
         r#"
-		SELECT title, text_content, html_content
+		SELECT title, text_content, html_content, tracked
 		FROM newsletter_issues
 		WHERE
 			newsletter_issue_id = $1
@@ -157,5 +333,15 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     )
     .fetch_one(pool)
     .await?;
-    Ok(issue)
+    Ok(NewsletterIssue {
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        tracked: issue.tracked,
+        attachments: attachments::of_issue(pool, issue_id)
+            .await?
+            .iter()
+            .map(EncodedAttachment::from)
+            .collect(),
+    })
 }
@@ -1,11 +1,27 @@
+pub mod attachments;
 pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_events;
+pub mod email_templates;
+pub mod html_lint;
 pub mod idempotency;
+pub mod import_confirmations;
 pub mod issue_delivery_worker;
+pub mod lists;
+pub mod markdown;
+pub mod metrics;
+pub mod personal_data;
+pub mod personalisation;
+pub mod preferences;
 pub mod routes;
+pub mod security_headers;
+pub mod segments;
 pub mod session_state;
 pub mod startup;
+pub mod subscription_events;
 pub mod telemetry;
+pub mod templates;
+pub mod tracking;
 pub mod utility;
@@ -0,0 +1,146 @@
+//! The mailing lists people subscribe to, each with its own subscribers.
+use std::fmt::Formatter;
+
+use anyhow::Context;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::utility::error_chain_fmt;
+
+/// The list subscriptions and issues go to when none is named,
+/// as they did before there were several lists.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ListError {
+    #[error("There is no list called '{0}'.")]
+    Unknown(String),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// All lists, in the order they were created.
+pub async fn all(executor: impl PgExecutor<'_>) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists ORDER BY created_at, slug"
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve the mailing lists.")?;
+    Ok(lists)
+}
+
+/// The lists named by `slugs`, failing on the first one that does not exist.
+pub async fn find_all(
+    executor: impl PgExecutor<'_>,
+    slugs: &[String],
+) -> Result<Vec<MailingList>, ListError> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists WHERE slug = ANY($1) ORDER BY created_at, slug",
+        slugs
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve mailing lists.")?;
+    if let Some(unknown) = slugs
+        .iter()
+        .find(|slug| !lists.iter().any(|list| &list.slug == *slug))
+    {
+        return Err(ListError::Unknown(unknown.clone()));
+    }
+    Ok(lists)
+}
+
+/// The list named by `slug`, or the default list.
+pub async fn find_or_default(
+    executor: impl PgExecutor<'_>,
+    slug: Option<&str>,
+) -> Result<MailingList, ListError> {
+    let slug = slug.filter(|s| !s.is_empty()).unwrap_or(DEFAULT_LIST);
+    let mut lists = find_all(executor, &[slug.to_owned()]).await?;
+    Ok(lists.remove(0))
+}
+
+/// Returns `false` when a list with the same slug already exists.
+pub async fn create(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+    name: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name
+    )
+    .execute(executor)
+    .await
+    .context("Failed to create a mailing list.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Slugs are short, lowercase and URL-safe, e.g. `weekly-digest`.
+pub fn parse_slug(slug: &str) -> Result<String, String> {
+    let slug = slug.trim();
+    let is_valid = !slug.is_empty()
+        && slug.len() <= 64
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-');
+    if is_valid {
+        Ok(slug.to_owned())
+    } else {
+        Err(format!(
+            "'{}' is not a valid list identifier: use lowercase letters, digits and dashes.",
+            slug
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_slug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_words_separated_by_dashes_are_valid() {
+        assert_ok!(parse_slug("weekly-digest-2"));
+        assert_ok!(parse_slug(" newsletter "));
+    }
+
+    #[test]
+    fn other_slugs_are_rejected() {
+        for slug in [
+            "",
+            "Weekly",
+            "weekly digest",
+            "-weekly",
+            "weekly-",
+            "wéékly",
+        ] {
+            assert_err!(parse_slug(slug), "{:?} was accepted", slug);
+        }
+        assert_err!(parse_slug(&"a".repeat(65)));
+    }
+}
@@ -8,9 +8,13 @@ use zero2prod::telemetry;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = telemetry::get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
-    telemetry::init_subscriber(subscriber);
     let configuration = configuration::get_configuration().expect("Failed to read config");
+    let subscriber = telemetry::get_subscriber(
+        "zero2prod".into(),
+        &configuration.telemetry,
+        telemetry::get_sink(&configuration.telemetry),
+    )?;
+    telemetry::init_subscriber(subscriber);
     let app = startup::Application::build(configuration.clone()).await?;
     let app_task = tokio::spawn(app.run_until_stopped());
     let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
@@ -19,6 +23,7 @@ async fn main() -> anyhow::Result<()> {
         o = app_task => report_exit("API", o),
         o = worker_task => report_exit("Background worker", o)
     }
+    telemetry::shutdown_tracer_provider();
 
     Ok(())
 }
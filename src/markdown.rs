@@ -0,0 +1,243 @@
+//! Issues written in Markdown. They are rendered once, when they are
+//! published, to the HTML and text bodies stored with every issue: the
+//! delivery worker never sees the Markdown.
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::html_lint;
+use crate::personalisation::VARIABLES;
+
+/// The bodies of an issue written in Markdown.
+#[derive(Debug)]
+pub struct RenderedIssue {
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Render `markdown` to both bodies.
+pub fn render(markdown: &str) -> RenderedIssue {
+    RenderedIssue {
+        text_content: to_text(markdown),
+        html_content: to_html(markdown),
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH)
+}
+
+/// Raw HTML is allowed in Markdown: it is sanitised as the HTML content
+/// of issues written by hand is.
+fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser(markdown));
+    restore_placeholders(&html_lint::sanitise(&html))
+}
+
+/// Links are percent-encoded, `[Unsubscribe]({{unsubscribe_url}})` must
+/// still point to the placeholder.
+fn restore_placeholders(html: &str) -> String {
+    let mut html = html.to_owned();
+    for variable in VARIABLES {
+        let placeholder = format!("{{{{ {} }}}}", variable);
+        for encoded in [
+            format!("%7B%7B{}%7D%7D", variable),
+            format!("%7B%7B%20{}%20%7D%7D", variable),
+        ] {
+            html = html.replace(&encoded, &placeholder);
+        }
+    }
+    html
+}
+
+/// Plain text, readable as it is: links and images are numbered, and
+/// their addresses listed at the end.
+fn to_text(markdown: &str) -> String {
+    let mut text = TextWriter::default();
+    for event in parser(markdown) {
+        text.write(event);
+    }
+    text.finish()
+}
+
+#[derive(Default)]
+struct TextWriter {
+    out: String,
+    /// Addresses of links and images, numbered from 1
+    footnotes: Vec<String>,
+    /// The next number of each list being written, `None` for bullets
+    lists: Vec<Option<u64>>,
+    /// Where the text of each open link or heading starts, and its address
+    open: Vec<(usize, String)>,
+    quote_depth: usize,
+}
+
+impl TextWriter {
+    fn write(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(Tag::Paragraph) => self.quote_prefix(),
+            Event::End(TagEnd::Paragraph) if self.lists.is_empty() => self.blank_line(),
+            Event::End(TagEnd::Paragraph) => self.newline(),
+            Event::Start(Tag::Heading { .. }) => self.open.push((self.out.len(), String::new())),
+            Event::End(TagEnd::Heading(level)) => {
+                let (start, _) = self.open.pop().unwrap_or_default();
+                let width = self.out[start..].chars().count();
+                let underline = if level == HeadingLevel::H1 { "=" } else { "-" };
+                self.out.push('\n');
+                self.out.push_str(&underline.repeat(width));
+                self.blank_line();
+            }
+            Event::Start(Tag::BlockQuote(_)) => self.quote_depth += 1,
+            Event::End(TagEnd::BlockQuote(_)) => {
+                self.quote_depth -= 1;
+                self.blank_line();
+            }
+            Event::Start(Tag::List(first)) => {
+                self.newline();
+                self.lists.push(first);
+            }
+            Event::End(TagEnd::List(_)) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank_line();
+                }
+            }
+            Event::Start(Tag::Item) => {
+                self.newline();
+                self.quote_prefix();
+                let indent = "   ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "- ".to_owned(),
+                };
+                self.out.push_str(&indent);
+                self.out.push_str(&marker);
+            }
+            Event::End(TagEnd::Item) => self.newline(),
+            Event::End(TagEnd::CodeBlock) => self.blank_line(),
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                self.open.push((self.out.len(), dest_url.into_string()))
+            }
+            Event::End(TagEnd::Link) => {
+                let (start, url) = self.open.pop().unwrap_or_default();
+                let text = &self.out[start..];
+                // `<https://example.com>` is readable as it is
+                if text != url && url.strip_prefix("mailto:") != Some(text) {
+                    self.footnote(url);
+                }
+            }
+            Event::End(TagEnd::Image) => {
+                let (_, url) = self.open.pop().unwrap_or_default();
+                self.footnote(url);
+            }
+            Event::Text(text) | Event::Code(text) => self.out.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => {
+                self.out.push('\n');
+                self.quote_prefix();
+            }
+            Event::Rule => {
+                self.out.push_str("---");
+                self.blank_line();
+            }
+            // Raw HTML has no text equivalent
+            _ => {}
+        }
+    }
+
+    fn footnote(&mut self, url: String) {
+        let n = match self.footnotes.iter().position(|f| *f == url) {
+            Some(i) => i + 1,
+            None => {
+                self.footnotes.push(url);
+                self.footnotes.len()
+            }
+        };
+        self.out.push_str(&format!("[{}]", n));
+    }
+
+    fn quote_prefix(&mut self) {
+        self.out.push_str(&"> ".repeat(self.quote_depth));
+    }
+
+    fn newline(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn blank_line(&mut self) {
+        self.newline();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut text = self.out.trim_end().to_owned();
+        if !self.footnotes.is_empty() {
+            text.push_str("\n\n");
+            for (i, url) in self.footnotes.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", i + 1, url));
+            }
+        }
+        text.trim_end().to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, to_html, to_text};
+
+    #[test]
+    fn markdown_is_rendered_to_both_bodies() {
+        let issue = render("Hello **world**");
+        assert_eq!(issue.html_content, "<p>Hello <strong>world</strong></p>\n");
+        assert_eq!(issue.text_content, "Hello world");
+    }
+
+    #[test]
+    fn scripts_are_removed() {
+        let html = to_html("Hi <script>alert(1)</script><a href=\"javascript:alert(1)\">x</a>");
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("javascript"));
+    }
+
+    #[test]
+    fn placeholders_survive_in_links() {
+        let html = to_html("[Unsubscribe]({{unsubscribe_url}}) or [not](<{{ web_view_url }}>)");
+        assert!(html.contains("href=\"{{ unsubscribe_url }}\""));
+        assert!(html.contains("href=\"{{ web_view_url }}\""));
+        let text = to_text("[Unsubscribe]({{unsubscribe_url}})");
+        assert_eq!(text, "Unsubscribe[1]\n\n[1] {{unsubscribe_url}}");
+    }
+
+    #[test]
+    fn links_are_listed_as_footnotes() {
+        let text = to_text(
+            "Read [this](https://example.com/a), [that](https://example.com/b) \
+            and [this again](https://example.com/a), or <https://example.com/c>.\n\n\
+            ![A cat](https://example.com/cat.png)",
+        );
+        assert_eq!(
+            text,
+            "Read this[1], that[2] and this again[1], or https://example.com/c.\n\n\
+            A cat[3]\n\n\
+            [1] https://example.com/a\n\
+            [2] https://example.com/b\n\
+            [3] https://example.com/cat.png"
+        );
+    }
+
+    #[test]
+    fn blocks_are_laid_out_as_text() {
+        let text = to_text(
+            "# Title\n\nIntro\n\n## Part\n\n- one\n- two\n   1. a\n   2. b\n\n> Quoted\n> words\n\n---\n\nEnd",
+        );
+        assert_eq!(
+            text,
+            "Title\n=====\n\nIntro\n\nPart\n----\n\n- one\n- two\n   1. a\n   2. b\n\n> Quoted\n> words\n\n---\n\nEnd"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::LazyLock;
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use prometheus::{
+    Encoder, Gauge, Histogram, HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder, proto::MetricFamily,
+};
+
+/// The metrics exposed on `/metrics`.
+///
+/// They live in a single, process-wide registry: the background worker
+/// records into the same metrics the API serves.
+pub struct Metrics {
+    registry: Registry,
+    http_requests_total: IntCounterVec,
+    http_request_duration_seconds: HistogramVec,
+    emails_total: IntCounterVec,
+    email_send_duration_seconds: Histogram,
+}
+
+static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("Failed to register metrics"));
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// What happened to a delivery task picked up by the worker.
+pub enum EmailOutcome {
+    Sent,
+    Failed,
+    /// The subscriber's stored email address is invalid
+    Skipped,
+}
+
+impl EmailOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailOutcome::Sent => "sent",
+            EmailOutcome::Failed => "failed",
+            EmailOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to serve HTTP requests",
+            ),
+            &["method", "route"],
+        )?;
+        let emails_total = IntCounterVec::new(
+            Opts::new(
+                "newsletter_emails_total",
+                "Newsletter deliveries processed by the worker, by outcome",
+            ),
+            &["outcome"],
+        )?;
+        let email_send_duration_seconds = Histogram::with_opts(HistogramOpts::new(
+            "email_send_duration_seconds",
+            "Time taken by the email API to accept an email",
+        ))?;
+
+        registry.register(Box::new(http_requests_total.clone()))?;
+        registry.register(Box::new(http_request_duration_seconds.clone()))?;
+        registry.register(Box::new(emails_total.clone()))?;
+        registry.register(Box::new(email_send_duration_seconds.clone()))?;
+        Ok(Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            emails_total,
+            email_send_duration_seconds,
+        })
+    }
+
+    pub fn record_email(&self, outcome: EmailOutcome) {
+        self.emails_total
+            .with_label_values(&[outcome.as_str()])
+            .inc();
+    }
+
+    /// The send is timed until the returned timer is dropped.
+    pub fn time_email_send(&self) -> HistogramTimer {
+        self.email_send_duration_seconds.start_timer()
+    }
+
+    /// Render all metrics in the Prometheus text format,
+    /// together with the gauges sampled for this scrape.
+    pub fn encode(&self, snapshot: &Snapshot) -> Result<String, prometheus::Error> {
+        let mut metric_families = self.registry.gather();
+        metric_families.extend(snapshot.gather()?);
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&metric_families, &mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+    }
+}
+
+/// Values sampled when `/metrics` is scraped, rather than recorded as they change.
+pub struct Snapshot {
+    pub pool_connections: u32,
+    pub pool_idle_connections: usize,
+    pub delivery_queue_depth: i64,
+    pub delivery_queue_oldest_task_age_seconds: f64,
+}
+
+impl Snapshot {
+    fn gather(&self) -> Result<Vec<MetricFamily>, prometheus::Error> {
+        let registry = Registry::new();
+        let pool_connections = IntGauge::new(
+            "pg_pool_connections",
+            "Connections currently open in the Postgres pool",
+        )?;
+        pool_connections.set(self.pool_connections.into());
+        registry.register(Box::new(pool_connections))?;
+        let pool_idle_connections = IntGauge::new(
+            "pg_pool_idle_connections",
+            "Idle connections in the Postgres pool",
+        )?;
+        pool_idle_connections.set(self.pool_idle_connections as i64);
+        registry.register(Box::new(pool_idle_connections))?;
+        let delivery_queue_depth = IntGauge::new(
+            "issue_delivery_queue_depth",
+            "Newsletter deliveries waiting to be sent",
+        )?;
+        delivery_queue_depth.set(self.delivery_queue_depth);
+        registry.register(Box::new(delivery_queue_depth))?;
+        let oldest_task_age = Gauge::new(
+            "issue_delivery_queue_oldest_task_age_seconds",
+            "Time the oldest waiting delivery has been in the queue",
+        )?;
+        oldest_task_age.set(self.delivery_queue_oldest_task_age_seconds);
+        registry.register(Box::new(oldest_task_age))?;
+        Ok(registry.gather())
+    }
+}
+
+/// Count and time every request, labelled by route pattern
+/// (e.g. `/api/v1/subscribers/{subscriber_id}`) to keep the number
+/// of series bounded.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+
+    let outcome = next.call(req).await;
+
+    let status = match &outcome {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let metrics = metrics();
+    metrics
+        .http_requests_total
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    metrics
+        .http_request_duration_seconds
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    outcome
+}
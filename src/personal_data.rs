@@ -0,0 +1,487 @@
+//! Everything we hold about a subscriber, keyed by their email address
+//! whatever its case: gathered for data subject access requests, and erased
+//! on request.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::{SubcriptionToken, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_events::{self, EmailEvent};
+use crate::email_templates::{self, EmailKind};
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::subscription_events::{self, SubscriptionEvent};
+
+/// How long the link emailed to a subscriber gives access to their data.
+pub const TOKEN_VALIDITY_HOURS: i32 = 24;
+
+#[derive(serde::Serialize)]
+pub struct PersonalData {
+    pub email: String,
+    /// One per list
+    pub subscriptions: Vec<Subscription>,
+    pub confirmation_tokens: Vec<ConfirmationToken>,
+    pub events: Vec<SubscriptionEvent>,
+    pub deliveries: Vec<Delivery>,
+    pub pending_deliveries: Vec<PendingDelivery>,
+    /// Opens and clicks of tracked issues
+    pub tracking_events: Vec<TrackingEvent>,
+    /// Bounces, spam complaints and deliveries reported by the email provider
+    pub email_events: Vec<EmailEvent>,
+    /// Why issues are no longer sent to the address, if they are not.
+    /// Kept, as a hash of the address, when the rest is erased.
+    pub suppression: Option<String>,
+    pub imports: Vec<ImportRow>,
+    pub preferences: Option<Preferences>,
+}
+
+impl PersonalData {
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+            && self.confirmation_tokens.is_empty()
+            && self.events.is_empty()
+            && self.deliveries.is_empty()
+            && self.pending_deliveries.is_empty()
+            && self.tracking_events.is_empty()
+            && self.email_events.is_empty()
+            && self.suppression.is_none()
+            && self.imports.is_empty()
+            && self.preferences.is_none()
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    /// The name of the list
+    pub list: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConfirmationToken {
+    pub subscription_token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Delivery {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub outcome: String,
+    pub delivered_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PendingDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub enqueued_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct TrackingEvent {
+    pub newsletter_issue_id: Uuid,
+    /// `open` or `click`
+    pub kind: String,
+    /// Where the link clicked leads
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// A row of a CSV import that mentioned the address.
+#[derive(serde::Serialize)]
+pub struct ImportRow {
+    pub import_id: Uuid,
+    pub line_number: i32,
+    pub name: String,
+    pub outcome: String,
+    pub imported_at: DateTime<Utc>,
+}
+
+/// What was chosen in the preference center.
+#[derive(serde::Serialize)]
+pub struct Preferences {
+    pub format: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Collect personal data", skip_all)]
+pub async fn collect(pool: &PgPool, email: &str) -> Result<PersonalData, anyhow::Error> {
+    let subscriptions = sqlx::query_as!(
+        Subscription,
+        r#"
+        SELECT
+            s.id, l.name AS list, s.name, s.status,
+            s.subscribed_at, s.confirmed_at, s.unsubscribed_at,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS "tags!"
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE lower(s.email) = lower($1)
+        ORDER BY s.subscribed_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscriptions.")?;
+    let confirmation_tokens = sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+        SELECT t.subscription_token, t.created_at
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY t.created_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscription tokens.")?;
+    let mut events = Vec::new();
+    for subscription in &subscriptions {
+        events.extend(subscription_events::list(pool, subscription.id).await?);
+    }
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.outcome, d.delivered_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE lower(d.subscriber_email) = lower($1)
+        ORDER BY d.delivered_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve deliveries.")?;
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT newsletter_issue_id, enqueued_at
+        FROM issue_delivery_queue
+        WHERE lower(subscriber_email) = lower($1)
+        ORDER BY enqueued_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending deliveries.")?;
+    let tracking_events = sqlx::query_as!(
+        TrackingEvent,
+        r#"
+        SELECT e.newsletter_issue_id, e.kind, l.url AS "url?", e.occurred_at
+        FROM tracking_events e
+        LEFT JOIN issue_links l USING (newsletter_issue_id, link_number)
+        WHERE lower(e.subscriber_email) = lower($1)
+        ORDER BY e.occurred_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve tracking events.")?;
+    let email_events = email_events::list(pool, email).await?;
+    let suppression = email_events::suppression_of(pool, email).await?;
+    let imports = sqlx::query_as!(
+        ImportRow,
+        r#"
+        SELECT r.import_id, r.line_number, r.name, r.outcome, i.created_at AS imported_at
+        FROM subscriber_import_rows r
+        JOIN subscriber_imports i USING (import_id)
+        WHERE lower(r.email) = lower($1)
+        ORDER BY i.created_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve imported rows.")?;
+    let preferences = sqlx::query_as!(
+        Preferences,
+        "SELECT format, created_at FROM subscriber_preferences WHERE lower(email) = lower($1)",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve preferences.")?;
+    Ok(PersonalData {
+        email: email.to_owned(),
+        subscriptions,
+        confirmation_tokens,
+        events,
+        deliveries,
+        pending_deliveries,
+        tracking_events,
+        email_events,
+        suppression,
+        imports,
+        preferences,
+    })
+}
+
+/// Who asked for personal data to be erased, for the audit log.
+pub enum ErasureRequester {
+    /// Through the link we emailed them
+    Subscriber,
+    Admin(Uuid),
+    Api(Uuid),
+}
+
+/// Delete every row mentioning `email`, and record the erasure.
+/// Returns the number of rows deleted.
+#[tracing::instrument(name = "Erase personal data", skip_all)]
+pub async fn erase(
+    pool: &PgPool,
+    email: &str,
+    requester: ErasureRequester,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut rows_erased = 0;
+    // Tokens, events and tags first: they reference the subscription
+    for query in [
+        sqlx::query!(
+            r#"
+            DELETE FROM subscription_events
+            WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))
+            "#,
+            email
+        ),
+        sqlx::query!(
+            r#"
+            DELETE FROM subscription_tokens
+            WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))
+            "#,
+            email
+        ),
+        sqlx::query!(
+            r#"
+            DELETE FROM subscriber_tags
+            WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = lower($1))
+            "#,
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM subscriptions WHERE lower(email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM issue_delivery_queue WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM newsletter_deliveries WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM tracking_events WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            r#"
+            DELETE FROM tracking_link_tokens
+            WHERE delivery_token IN (
+                SELECT token FROM tracking_tokens WHERE lower(subscriber_email) = lower($1)
+            )
+            "#,
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM tracking_tokens WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM email_events WHERE lower(subscriber_email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM subscriber_import_rows WHERE lower(email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM personal_data_tokens WHERE lower(email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM personal_data_request_queue WHERE lower(email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM subscriber_preferences WHERE lower(email) = lower($1)",
+            email
+        ),
+        sqlx::query!(
+            "DELETE FROM email_changes WHERE lower(old_email) = lower($1) OR lower(new_email) = lower($1)",
+            email
+        ),
+    ] {
+        rows_erased += transaction
+            .execute(query)
+            .await
+            .context("Failed to erase personal data.")?
+            .rows_affected();
+    }
+    let (requested_by, user_id) = match requester {
+        ErasureRequester::Subscriber => ("subscriber", None),
+        ErasureRequester::Admin(user_id) => ("admin", Some(user_id)),
+        ErasureRequester::Api(user_id) => ("api", Some(user_id)),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO personal_data_erasures (erasure_id, email_sha256, requested_by, user_id, rows_erased)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        email_sha256(email),
+        requested_by,
+        user_id,
+        rows_erased as i32
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to record an erasure in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase personal data.")?;
+    Ok(rows_erased)
+}
+
+/// What the audit log keeps instead of the address itself.
+pub fn email_sha256(email: &str) -> String {
+    Sha256::digest(email.to_lowercase().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Store a token giving access to the data held about `email`.
+pub async fn create_token(pool: &PgPool, email: &str, token: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "INSERT INTO personal_data_tokens (token, email) VALUES ($1, $2)",
+        token,
+        email
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a personal data token.")?;
+    Ok(())
+}
+
+/// The email address a token gives access to, unless it has expired.
+pub async fn email_from_token(pool: &PgPool, token: &str) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email
+        FROM personal_data_tokens
+        WHERE token = $1 AND created_at > now() - make_interval(hours => $2)
+        "#,
+        token,
+        TOKEN_VALIDITY_HOURS
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up a personal data token.")?;
+    Ok(row.map(|r| r.email))
+}
+
+/// Queue a request for a link to the data held about `email`. Whether we
+/// hold any is only looked up by the worker: requests about addresses we
+/// know must not stand out, neither by their response nor by its timing.
+pub async fn enqueue_request(pool: &PgPool, email: &str) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "INSERT INTO personal_data_request_queue (request_id, email) VALUES ($1, $2)",
+        Uuid::new_v4(),
+        email
+    )
+    .execute(pool)
+    .await
+    .context("Failed to queue a personal data request.")?;
+    Ok(())
+}
+
+/// Answer the next queued request, if there is one, by emailing a link to
+/// the address if we hold data about it. Failures to send are only logged:
+/// the subscriber can ask again.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_answer_next_request(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(request) = sqlx::query!(
+        r#"
+        SELECT request_id, email
+        FROM personal_data_request_queue
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    let is_subscribed = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        request.email
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up a subscriber.")?
+    .is_some();
+    if is_subscribed && let Err(e) = send_link(pool, email_client, base_url, request.email).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a personal data link"
+        );
+    }
+    let query = sqlx::query!(
+        "DELETE FROM personal_data_request_queue WHERE request_id = $1",
+        request.request_id
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn send_link(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: String,
+) -> Result<(), anyhow::Error> {
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    let token = SubcriptionToken::generate();
+    create_token(pool, email.as_ref(), token.as_ref()).await?;
+    let link = format!("{}/personal_data/{}", base_url, token.as_ref());
+    email_templates::send(
+        pool,
+        email_client,
+        EmailKind::PersonalData,
+        &email,
+        &[
+            ("link", &link),
+            ("validity_hours", &TOKEN_VALIDITY_HOURS.to_string()),
+        ],
+    )
+    .await
+    .context("Failed to send a personal data link.")
+}
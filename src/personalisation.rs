@@ -0,0 +1,271 @@
+//! Placeholders in the body of an issue, e.g. `Hi {{ name }}!`, filled in
+//! for each subscriber when the issue is sent.
+//!
+//! Issues are checked when they are published, so that a typo is reported
+//! to whoever is publishing rather than sent to every subscriber.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::preferences;
+
+/// The placeholders an issue can use.
+pub const VARIABLES: [&str; 5] = [
+    "name",
+    "email",
+    "unsubscribe_url",
+    "web_view_url",
+    "preferences_url",
+];
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("A placeholder opened on line {0} is never closed with `}}}}`.")]
+    Unclosed(usize),
+    #[error("`{{{{{0}}}}}` on line {1} is not a valid placeholder.")]
+    Invalid(String, usize),
+    #[error("There is no `{0}` variable (line {1}), use one of: {vars}.", vars = .2.join(", "))]
+    UnknownVariable(String, usize, &'static [&'static str]),
+}
+
+/// The values of the placeholders for one subscriber.
+pub struct Variables {
+    pub name: String,
+    pub email: String,
+    pub unsubscribe_url: String,
+    pub web_view_url: String,
+    pub preferences_url: String,
+}
+
+impl Variables {
+    /// The values for `email` receiving `issue_id`, with links to this
+    /// application at `base_url`.
+    pub async fn for_subscriber(
+        pool: &PgPool,
+        base_url: &str,
+        issue_id: Uuid,
+        email: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let token = preferences::token_for(pool, email).await?;
+        let preferences_url = format!("{}/preferences/{}", base_url, token);
+        Ok(Self {
+            name: preferences::name_of(pool, email).await?.unwrap_or_default(),
+            email: email.to_owned(),
+            unsubscribe_url: format!("{}/unsubscribe", preferences_url),
+            web_view_url: format!("{}/issues/{}/{}", base_url, issue_id, token),
+            preferences_url,
+        })
+    }
+
+    fn get(&self, variable: &str) -> &str {
+        match variable {
+            "name" => &self.name,
+            "email" => &self.email,
+            "unsubscribe_url" => &self.unsubscribe_url,
+            "web_view_url" => &self.web_view_url,
+            "preferences_url" => &self.preferences_url,
+            _ => unreachable!("Templates only contain known variables"),
+        }
+    }
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// A parsed body, of an issue or of another email.
+pub struct Template<'a> {
+    parts: Vec<Part<'a>>,
+}
+
+impl<'a> Template<'a> {
+    /// Parse the body of an issue.
+    pub fn parse(source: &'a str) -> Result<Self, TemplateError> {
+        Self::parse_with(source, &VARIABLES)
+    }
+
+    /// Parse a body that can use `variables`.
+    pub fn parse_with(
+        source: &'a str,
+        variables: &'static [&'static str],
+    ) -> Result<Self, TemplateError> {
+        let line_of = |offset: usize| source[..offset].matches('\n').count() + 1;
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            let offset = source.len() - rest.len() + start;
+            parts.push(Part::Text(&rest[..start]));
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or(TemplateError::Unclosed(line_of(offset)))?;
+            let inner = &after[..end];
+            let variable = inner.trim();
+            let is_identifier = !variable.is_empty()
+                && variable
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_identifier {
+                return Err(TemplateError::Invalid(inner.to_owned(), line_of(offset)));
+            }
+            if !variables.contains(&variable) {
+                return Err(TemplateError::UnknownVariable(
+                    variable.to_owned(),
+                    line_of(offset),
+                    variables,
+                ));
+            }
+            parts.push(Part::Variable(variable));
+            rest = &after[end + 2..];
+        }
+        parts.push(Part::Text(rest));
+        Ok(Self { parts })
+    }
+
+    /// Values are HTML-escaped when `escape` is set, for HTML bodies.
+    pub fn render(&self, variables: &Variables, escape: bool) -> String {
+        self.render_with(|variable| {
+            let value = variables.get(variable);
+            if escape {
+                escape_html(value)
+            } else {
+                value.to_owned()
+            }
+        })
+    }
+
+    /// Fill in each placeholder with `value_of` its variable, as it is.
+    pub fn render_with(&self, value_of: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Variable(variable) => rendered.push_str(&value_of(variable)),
+            }
+        }
+        rendered
+    }
+
+    /// The variables used, in order.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Variable(variable) => Some(*variable),
+            Part::Text(_) => None,
+        })
+    }
+}
+
+/// Render `source` for one subscriber. Issues published before placeholders
+/// existed were never checked: those that do not parse are kept as they are.
+pub fn personalise(source: &str, variables: &Variables, escape: bool) -> String {
+    match Template::parse(source) {
+        Ok(template) => template.render(variables, escape),
+        Err(_) => source.to_owned(),
+    }
+}
+
+/// Check both bodies of an issue, naming the one at fault.
+pub fn check_issue(text_content: &str, html_content: &str) -> Result<(), String> {
+    Template::parse(text_content).map_err(|e| format!("In the text content: {}", e))?;
+    Template::parse(html_content).map_err(|e| format!("In the HTML content: {}", e))?;
+    Ok(())
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Template, TemplateError, VARIABLES, Variables, check_issue, personalise};
+    use claims::{assert_err, assert_ok};
+
+    fn variables() -> Variables {
+        Variables {
+            name: "Ursula <Le Guin>".into(),
+            email: "ursula@example.com".into(),
+            unsubscribe_url: "https://example.com/preferences/abc/unsubscribe".into(),
+            web_view_url: "https://example.com/issues/1/abc?a=1&b=2".into(),
+            preferences_url: "https://example.com/preferences/abc".into(),
+        }
+    }
+
+    fn render(source: &str, escape: bool) -> String {
+        Template::parse(source)
+            .unwrap()
+            .render(&variables(), escape)
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_or_without_spaces() {
+        assert_eq!(
+            render("Hi {{ name }}, this is {{email}}.", false),
+            "Hi Ursula <Le Guin>, this is ursula@example.com."
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        assert_eq!(
+            render(r#"<a href="{{ web_view_url }}">{{ name }}</a>"#, true),
+            r#"<a href="https://example.com/issues/1/abc?a=1&amp;b=2">Ursula &lt;Le Guin&gt;</a>"#
+        );
+    }
+
+    #[test]
+    fn text_without_placeholders_is_left_alone() {
+        assert_eq!(
+            render("No placeholders } here {", false),
+            "No placeholders } here {"
+        );
+    }
+
+    #[test]
+    fn bodies_that_do_not_parse_are_sent_as_they_are() {
+        assert_eq!(personalise("{{ old }}", &variables(), true), "{{ old }}");
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_eq!(
+            Template::parse("Hi\n{{ nmae }}").err(),
+            Some(TemplateError::UnknownVariable("nmae".into(), 2, &VARIABLES))
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_rejected() {
+        assert_eq!(
+            Template::parse("Hi {{ name").err(),
+            Some(TemplateError::Unclosed(1))
+        );
+        assert_eq!(
+            Template::parse("Hi {{ name | upper }}").err(),
+            Some(TemplateError::Invalid(" name | upper ".into(), 1))
+        );
+        assert_eq!(
+            Template::parse("Hi {{}}").err(),
+            Some(TemplateError::Invalid("".into(), 1))
+        );
+    }
+
+    #[test]
+    fn both_bodies_are_checked() {
+        assert_ok!(check_issue("Hi {{ name }}", "<p>Hi {{ name }}</p>"));
+        let error = check_issue("Hi", "<p>Hi {{ name </p>").unwrap_err();
+        assert!(error.starts_with("In the HTML content"));
+        assert_err!(check_issue("{{ nmae }}", ""));
+    }
+}
@@ -0,0 +1,417 @@
+//! What each address chooses to receive, managed by subscribers through
+//! the preference center linked from every issue.
+use anyhow::Context;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{SubcriptionToken, SubscriberName};
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
+
+/// How long the link confirming a new address stays valid.
+pub const EMAIL_CHANGE_VALIDITY_HOURS: i32 = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum EmailFormat {
+    #[default]
+    Html,
+    /// Plain text only, for readers who do not want HTML email
+    Text,
+}
+
+impl EmailFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailFormat::Html => "html",
+            EmailFormat::Text => "text",
+        }
+    }
+
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format {
+            "html" => Ok(EmailFormat::Html),
+            "text" => Ok(EmailFormat::Text),
+            other => Err(format!("{} is not a supported email format.", other)),
+        }
+    }
+}
+
+/// The token of the preference center link of `email`, created on first use.
+/// The link does not expire: it is in every issue we have ever sent.
+pub async fn token_for(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<String, anyhow::Error> {
+    let new_token = SubcriptionToken::generate();
+    let token = sqlx::query_scalar!(
+        r#"
+        INSERT INTO subscriber_preferences (email, preference_token)
+        VALUES ($1, $2)
+        ON CONFLICT (email) DO UPDATE SET email = EXCLUDED.email
+        RETURNING preference_token
+        "#,
+        email,
+        new_token.as_ref()
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to retrieve a preference token.")?;
+    Ok(token)
+}
+
+pub async fn email_from_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let email = sqlx::query_scalar!(
+        "SELECT email FROM subscriber_preferences WHERE preference_token = $1",
+        token
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to look up a preference token.")?;
+    Ok(email)
+}
+
+/// Addresses that never chose get HTML.
+pub async fn format_of(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<EmailFormat, anyhow::Error> {
+    let format = sqlx::query_scalar!(
+        "SELECT format FROM subscriber_preferences WHERE email = $1",
+        email
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve an email format.")?;
+    match format {
+        Some(format) => EmailFormat::parse(&format).map_err(anyhow::Error::msg),
+        None => Ok(EmailFormat::default()),
+    }
+}
+
+pub async fn set_format(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    format: EmailFormat,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE subscriber_preferences SET format = $2 WHERE email = $1",
+        email,
+        format.as_str()
+    )
+    .execute(executor)
+    .await
+    .context("Failed to change an email format.")?;
+    Ok(())
+}
+
+/// The name given on the latest subscription of `email`.
+pub async fn name_of(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let name = sqlx::query_scalar!(
+        r#"
+        SELECT name FROM subscriptions
+        WHERE lower(email) = lower($1)
+        ORDER BY subscribed_at DESC
+        LIMIT 1
+        "#,
+        email
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve the name of a subscriber.")?;
+    Ok(name)
+}
+
+/// The name is the same on every list.
+pub async fn set_name(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    name: &SubscriberName,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET name = $2 WHERE lower(email) = lower($1)",
+        email,
+        name.as_ref()
+    )
+    .execute(executor)
+    .await
+    .context("Failed to change the name of a subscriber.")?;
+    Ok(())
+}
+
+/// The subscription of an address to one of the lists, if any.
+pub struct ListChoice {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub subscribed: bool,
+}
+
+/// Every list, and whether `email` gets it.
+pub async fn list_choices(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Vec<ListChoice>, anyhow::Error> {
+    let choices = sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT
+            l.list_id, l.slug, l.name,
+            COALESCE(s.status <> 'unsubscribed', false) AS "subscribed!"
+        FROM lists l
+        LEFT JOIN subscriptions s ON s.list_id = l.list_id AND lower(s.email) = lower($1)
+        ORDER BY l.created_at, l.slug
+        "#,
+        email
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve the lists of a subscriber.")?;
+    Ok(choices)
+}
+
+/// Subscribe `email` to exactly `list_ids`. Following the link proves the
+/// address is theirs, so new subscriptions need no further confirmation.
+#[tracing::instrument(name = "Update the lists of a subscriber", skip_all)]
+pub async fn set_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    name: &str,
+    list_ids: &[Uuid],
+    origin: &RequestOrigin,
+) -> Result<(), anyhow::Error> {
+    let unsubscribed = sqlx::query_scalar!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE lower(email) = lower($1) AND status <> 'unsubscribed' AND NOT (list_id = ANY($2))
+        RETURNING id
+        "#,
+        email,
+        list_ids
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to unsubscribe from lists.")?;
+    for subscriber_id in unsubscribed {
+        record(transaction, subscriber_id, EventType::Unsubscribed, origin).await?;
+    }
+    for &list_id in list_ids {
+        let existing = sqlx::query!(
+            "SELECT id, status FROM subscriptions WHERE lower(email) = lower($1) AND list_id = $2",
+            email,
+            list_id
+        )
+        .fetch_optional(&mut **transaction)
+        .await
+        .context("Failed to look up a subscription.")?;
+        let (subscriber_id, events) = match existing {
+            Some(row) if row.status == "confirmed" => continue,
+            Some(row) if row.status == "pending_confirmation" => {
+                (row.id, &[EventType::Confirmed][..])
+            }
+            Some(row) => (row.id, &[EventType::Subscribed, EventType::Confirmed][..]),
+            None => {
+                let subscriber_id = Uuid::new_v4();
+                sqlx::query!(
+                    r#"
+                    INSERT INTO subscriptions (id, email, name, subscribed_at, status, list_id)
+                    VALUES ($1, $2, $3, now(), 'pending_confirmation', $4)
+                    "#,
+                    subscriber_id,
+                    email,
+                    name,
+                    list_id
+                )
+                .execute(&mut **transaction)
+                .await
+                .context("Failed to subscribe to a list.")?;
+                (
+                    subscriber_id,
+                    &[EventType::Subscribed, EventType::Confirmed][..],
+                )
+            }
+        };
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET status = 'confirmed', confirmed_at = now(), unsubscribed_at = NULL
+            WHERE id = $1
+            "#,
+            subscriber_id
+        )
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to confirm a subscription.")?;
+        for &event_type in events {
+            record(transaction, subscriber_id, event_type, origin).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event_type: EventType,
+    origin: &RequestOrigin,
+) -> Result<(), anyhow::Error> {
+    subscription_events::record(
+        &mut **transaction,
+        subscriber_id,
+        NewEvent {
+            event_type,
+            source: EventSource::Preferences,
+            origin,
+            subscription_token: None,
+        },
+    )
+    .await
+}
+
+/// Whether any list has a subscription for `email`, whatever its status.
+pub async fn is_known(executor: impl PgExecutor<'_>, email: &str) -> Result<bool, anyhow::Error> {
+    let known = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM subscriptions WHERE lower(email) = lower($1)) AS "known!""#,
+        email
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to look up an email address.")?;
+    Ok(known)
+}
+
+pub async fn request_email_change(
+    executor: impl PgExecutor<'_>,
+    old_email: &str,
+    new_email: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_changes (subscription_token, old_email, new_email)
+        VALUES ($1, $2, $3)
+        "#,
+        token,
+        old_email,
+        new_email
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store an email change.")?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum EmailChange {
+    Changed {
+        new_email: String,
+    },
+    /// The token is unknown or has expired
+    Invalid,
+    /// The new address is already subscribed to one of the lists
+    Taken {
+        old_email: String,
+    },
+}
+
+/// Move everything of the old address to the new one.
+#[tracing::instrument(name = "Confirm an email change", skip_all)]
+pub async fn confirm_email_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+    origin: &RequestOrigin,
+) -> Result<EmailChange, anyhow::Error> {
+    let Some(change) = sqlx::query!(
+        r#"
+        DELETE FROM email_changes
+        WHERE
+            subscription_token = $1 AND
+            created_at > now() - make_interval(hours => $2)
+        RETURNING old_email, new_email
+        "#,
+        token,
+        EMAIL_CHANGE_VALIDITY_HOURS
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to look up an email change.")?
+    else {
+        return Ok(EmailChange::Invalid);
+    };
+    if is_known(&mut **transaction, &change.new_email).await? {
+        return Ok(EmailChange::Taken {
+            old_email: change.old_email,
+        });
+    }
+    let subscriber_ids = sqlx::query_scalar!(
+        "UPDATE subscriptions SET email = $2 WHERE lower(email) = lower($1) RETURNING id",
+        change.old_email,
+        change.new_email
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .context("Failed to change the address of subscriptions.")?;
+    sqlx::query!(
+        "UPDATE subscriber_preferences SET email = $2 WHERE email = $1",
+        change.old_email,
+        change.new_email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to change the address of preferences.")?;
+    // Issues still being sent go to the new address
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET subscriber_email = $2 WHERE subscriber_email = $1",
+        change.old_email,
+        change.new_email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to change the address of pending deliveries.")?;
+    sqlx::query!(
+        "DELETE FROM email_changes WHERE old_email = $1",
+        change.old_email
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to discard other email changes.")?;
+    for subscriber_id in subscriber_ids {
+        subscription_events::record(
+            &mut **transaction,
+            subscriber_id,
+            NewEvent {
+                event_type: EventType::EmailChanged,
+                source: EventSource::ConfirmationLink,
+                origin,
+                subscription_token: Some(token),
+            },
+        )
+        .await?;
+    }
+    Ok(EmailChange::Changed {
+        new_email: change.new_email,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailFormat;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn formats_round_trip() {
+        for format in [EmailFormat::Html, EmailFormat::Text] {
+            assert_ok_eq!(EmailFormat::parse(format.as_str()), format);
+        }
+    }
+
+    #[test]
+    fn unknown_formats_are_rejected() {
+        assert_err!(EmailFormat::parse("pdf"));
+    }
+}
@@ -1,13 +1,27 @@
 pub mod admin;
+pub mod api;
 pub mod health_check;
 pub mod home;
 pub mod login;
+pub mod metrics;
+pub mod personal_data;
+pub mod preferences;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod tracking;
+pub mod web_view;
+pub mod webhooks;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use metrics::*;
+pub use personal_data::*;
+pub use preferences::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use tracking::*;
+pub use web_view::*;
+pub use webhooks::*;
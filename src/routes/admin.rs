@@ -1,9 +1,21 @@
+mod api_keys;
 mod dashboard;
+mod email_templates;
+mod lists;
 mod logout;
 mod newsletters;
 mod password;
+mod personal_data;
+mod segments;
+mod subscribers;
 
+pub use api_keys::*;
 pub use dashboard::admin_dashboard;
+pub use email_templates::*;
+pub use lists::*;
 pub use logout::log_out;
 pub use newsletters::*;
 pub use password::*;
+pub use personal_data::*;
+pub use segments::*;
+pub use subscribers::*;
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::get_api_keys_page;
+pub use post::{create_api_key, revoke_api_key};
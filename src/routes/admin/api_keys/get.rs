@@ -0,0 +1,30 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::authentication::{ApiKeyRecord, CsrfToken, UserId, list_api_keys};
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+#[derive(Template)]
+#[template(path = "admin/api_keys.html")]
+struct ApiKeysTemplate {
+    flash_messages: Vec<String>,
+    api_keys: Vec<ApiKeyRecord>,
+    csrf_token: CsrfToken,
+}
+
+pub async fn get_api_keys_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let api_keys = list_api_keys(&pool, **user_id).await.map_err(e500)?;
+    render_html(&ApiKeysTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        api_keys,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
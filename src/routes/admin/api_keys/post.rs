@@ -0,0 +1,62 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::templates::render_html;
+use crate::utility::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/api_key_created.html")]
+struct ApiKeyCreatedTemplate {
+    flash_messages: Vec<String>,
+    name: String,
+    api_key: String,
+}
+
+/// The new key is rendered straight away: this is the only time its owner
+/// gets to see it, we only keep a hash around.
+#[tracing::instrument(name = "Create an API key", skip_all, fields(user_id=%&*user_id))]
+pub async fn create_api_key(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error("The API key needs a name.").send();
+        return Ok(see_other("/admin/api_keys"));
+    }
+    let api_key = crate::authentication::create_api_key(&pool, **user_id, &name)
+        .await
+        .map_err(e500)?;
+    render_html(&ApiKeyCreatedTemplate {
+        flash_messages: Vec::new(),
+        name,
+        api_key: api_key.expose_secret(),
+    })
+}
+
+#[tracing::instrument(name = "Revoke an API key", skip(pool, user_id), fields(user_id=%&*user_id))]
+pub async fn revoke_api_key(
+    api_key_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = crate::authentication::revoke_api_key(&pool, **user_id, *api_key_id)
+        .await
+        .map_err(e500)?;
+    if revoked {
+        FlashMessage::info("The API key has been revoked.").send();
+    } else {
+        FlashMessage::error("The API key does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_keys"))
+}
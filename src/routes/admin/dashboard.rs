@@ -1,46 +1,35 @@
-use crate::{authentication::UserId, utility::e500};
-use actix_web::{HttpResponse, http::header::ContentType, web};
+use crate::{
+    authentication::{CsrfToken, UserId},
+    templates::render_html,
+    utility::e500,
+};
+use actix_web::{HttpResponse, web};
 use anyhow::Context;
+use askama::Template;
 use sqlx::PgPool;
 use uuid::Uuid;
 
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardTemplate {
+    flash_messages: Vec<String>,
+    username: String,
+    csrf_token: CsrfToken,
+}
+
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     // The deserialisation of the type we want from the session store (`Uuid` in this case)
     // could fail.
     let username = get_username(**user_id, &pool).await.map_err(e500)?;
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-            </head>
-            <body>
-                <p>Welcome {username}!</p>
-                <p>Available actions:</p>
-                <ol>
-                <li>
-                    <a href="/admin/newsletters">Publish a newsletter</a>
-                </li>
-                <li>
-                    <a href="/admin/password">Change password</a>
-                </li>
-                <li>
-                    <form name="logoutForm" action="/admin/logout" method="post">
-                        <input type="submit" value="Logout">
-                    </form>
-                </li>
-                </ol>
-            </body>
-            </html>
-            "#
-        )))
+    render_html(&DashboardTemplate {
+        flash_messages: Vec::new(),
+        username,
+        csrf_token: csrf_token.into_inner(),
+    })
 }
 
 pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
@@ -57,3 +46,25 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
 }
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::DashboardTemplate;
+    use crate::authentication::CsrfToken;
+
+    #[test]
+    fn script_tags_in_usernames_are_escaped() {
+        let template = DashboardTemplate {
+            flash_messages: Vec::new(),
+            username: "<script>alert(1)</script>".into(),
+            csrf_token: CsrfToken::generate(),
+        };
+
+        let html = template.render().unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("Welcome &#60;script&#62;alert(1)&#60;/script&#62;!"));
+    }
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{get_email_template_page, get_email_templates_page};
+pub use post::update_email_template;
@@ -0,0 +1,117 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::authentication::CsrfToken;
+use crate::email_templates::{self, EmailKind, EmailTemplate, RenderedEmail};
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+pub struct TemplateRow {
+    pub kind: EmailKind,
+    /// When it was last edited, `None` while it is the default
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/email_templates.html")]
+struct EmailTemplatesTemplate {
+    flash_messages: Vec<String>,
+    templates: Vec<TemplateRow>,
+}
+
+#[tracing::instrument(name = "Show email templates", skip_all)]
+pub async fn get_email_templates_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let customised = email_templates::customised(pool.get_ref())
+        .await
+        .map_err(e500)?;
+    let templates = EmailKind::ALL
+        .into_iter()
+        .map(|kind| TemplateRow {
+            kind,
+            updated_at: customised
+                .iter()
+                .find(|(name, _)| name == kind.name())
+                .map(|(_, updated_at)| *updated_at),
+        })
+        .collect();
+    render_html(&EmailTemplatesTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        templates,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/email_template.html")]
+pub(super) struct EmailTemplatePage {
+    pub(super) flash_messages: Vec<String>,
+    pub(super) kind: EmailKind,
+    /// e.g. `{{ name }}`
+    pub(super) placeholders: Vec<String>,
+    pub(super) template: EmailTemplate,
+    /// Missing when the template does not check out
+    pub(super) preview: Option<RenderedEmail>,
+    pub(super) csrf_token: CsrfToken,
+}
+
+impl EmailTemplatePage {
+    pub(super) fn new(
+        flash_messages: Vec<String>,
+        kind: EmailKind,
+        template: EmailTemplate,
+        csrf_token: CsrfToken,
+    ) -> Self {
+        let mut flash_messages = flash_messages;
+        let preview = match template.check(kind) {
+            Ok(()) => Some(template.preview(kind)),
+            Err(e) => {
+                flash_messages.push(e);
+                None
+            }
+        };
+        Self {
+            flash_messages,
+            kind,
+            placeholders: kind
+                .variables()
+                .iter()
+                .map(|variable| format!("{{{{ {} }}}}", variable))
+                .collect(),
+            template,
+            preview,
+            csrf_token,
+        }
+    }
+}
+
+pub(super) fn email_kind(name: &str) -> Result<EmailKind, actix_web::Error> {
+    EmailKind::parse(name).ok_or_else(|| actix_web::error::ErrorNotFound("No such email template."))
+}
+
+/// The template as it is sent, previewed with made-up values.
+#[tracing::instrument(
+    name = "Show an email template",
+    skip(flash_messages, pool, csrf_token)
+)]
+pub async fn get_email_template_page(
+    name: web::Path<String>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let kind = email_kind(&name)?;
+    let template = email_templates::load(pool.get_ref(), kind)
+        .await
+        .map_err(e500)?;
+    render_html(&EmailTemplatePage::new(
+        templates::flash_messages(&flash_messages),
+        kind,
+        template,
+        csrf_token.into_inner(),
+    ))
+}
@@ -0,0 +1,83 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use super::get::{EmailTemplatePage, email_kind};
+use crate::authentication::CsrfToken;
+use crate::email_templates::{self, EmailTemplate};
+use crate::templates::render_html;
+use crate::utility::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    /// Show the edited template without saving it
+    Preview,
+    Save,
+    /// Go back to the default template
+    Reset,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// Layouts have none
+    #[serde(default)]
+    subject: String,
+    html_body: String,
+    text_body: String,
+    action: Action,
+}
+
+#[tracing::instrument(name = "Update an email template", skip(form, pool, csrf_token))]
+pub async fn update_email_template(
+    name: web::Path<String>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let kind = email_kind(&name)?;
+    let path = format!("/admin/email_templates/{}", kind.name());
+    let FormData {
+        subject,
+        html_body,
+        text_body,
+        action,
+    } = form.0;
+    let template = EmailTemplate {
+        subject,
+        // Browsers send line breaks as CRLF
+        html_body: html_body.replace("\r\n", "\n"),
+        text_body: text_body.replace("\r\n", "\n"),
+    };
+    match action {
+        Action::Preview => render_html(&EmailTemplatePage::new(
+            vec!["This preview has not been saved yet.".into()],
+            kind,
+            template,
+            csrf_token.into_inner(),
+        )),
+        Action::Save => {
+            if template.check(kind).is_err() {
+                // Shown with the error, so that the edits are not lost
+                return render_html(&EmailTemplatePage::new(
+                    Vec::new(),
+                    kind,
+                    template,
+                    csrf_token.into_inner(),
+                ));
+            }
+            email_templates::save(pool.get_ref(), kind, &template)
+                .await
+                .map_err(e500)?;
+            FlashMessage::info("The template has been saved.").send();
+            Ok(see_other(&path))
+        }
+        Action::Reset => {
+            email_templates::reset(pool.get_ref(), kind)
+                .await
+                .map_err(e500)?;
+            FlashMessage::info("The template is back to its default.").send();
+            Ok(see_other(&path))
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::get_lists_page;
+pub use post::create_list;
@@ -0,0 +1,55 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::authentication::CsrfToken;
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+pub struct ListRow {
+    pub slug: String,
+    pub name: String,
+    pub confirmed: i64,
+    pub pending: i64,
+}
+
+#[derive(Template)]
+#[template(path = "admin/lists.html")]
+struct ListsTemplate {
+    flash_messages: Vec<String>,
+    lists: Vec<ListRow>,
+    csrf_token: CsrfToken,
+}
+
+#[tracing::instrument(name = "Show mailing lists", skip_all)]
+pub async fn get_lists_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = sqlx::query_as!(
+        ListRow,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COUNT(s.id) FILTER (WHERE s.status = 'confirmed') AS "confirmed!",
+            COUNT(s.id) FILTER (WHERE s.status = 'pending_confirmation') AS "pending!"
+        FROM lists l
+        LEFT JOIN subscriptions s USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.created_at, l.slug
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists.")
+    .map_err(e500)?;
+    render_html(&ListsTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        lists,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
@@ -0,0 +1,41 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::lists::{self, parse_slug};
+use crate::utility::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip_all, fields(slug = %form.slug))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { slug, name } = form.0;
+    let slug = match parse_slug(&slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    if lists::create(pool.get_ref(), &slug, name)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("The {} list has been created.", name)).send();
+    } else {
+        FlashMessage::error(format!("There already is a list called '{}'.", slug)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -1,5 +1,8 @@
 mod get;
 mod post;
 
-pub use get::get_newsletters_page;
+pub use get::{get_issue_page, get_newsletters_page, get_recipient_count};
 pub use post::publish_newsletter;
+pub(crate) use post::{
+    IssueContent, PublishContext, enqueue_delivery_tasks, insert_newsletter_issue,
+};
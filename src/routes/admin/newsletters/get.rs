@@ -1,71 +1,239 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpRequest, HttpResponse, web};
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments::{ATTACHMENT_TYPES, MAX_ATTACHMENTS_SIZE};
+use crate::authentication::CsrfToken;
+use crate::configuration::TrackingSettings;
+use crate::lists::{self, DEFAULT_LIST, ListError, MailingList};
+use crate::security_headers::CspNonce;
+use crate::segments::{self, Segment};
+use crate::templates::{self, render_html};
+use crate::tracking::{self, Engagement};
+use crate::utility::{e400, e500};
+
+#[derive(Template)]
+#[template(path = "admin/newsletters.html")]
+struct NewslettersTemplate {
+    flash_messages: Vec<String>,
+    lists: Vec<MailingList>,
+    segments: Vec<Segment>,
+    /// For the lists and segment of the draft
+    recipients: i64,
+    draft: Draft,
+    /// What was found in the HTML content of the draft
+    findings: Vec<String>,
+    /// Whether issues can be published with tracking on
+    tracking_enabled: bool,
+    issues: Vec<IssueSummary>,
+    /// The content types of the files that can be attached
+    attachment_types: String,
+    max_attachments_size_mb: usize,
+    csrf_token: CsrfToken,
+    csp_nonce: CspNonce,
+    script: &'static str,
+}
+
+/// What the form is filled in with: nothing yet, or an issue sent back
+/// to be reviewed before it is published.
+pub(crate) struct Draft {
+    pub(crate) title: String,
+    pub(crate) text_content: String,
+    pub(crate) html_content: String,
+    /// Slugs of the lists to publish to
+    pub(crate) lists: Vec<String>,
+    pub(crate) segment: String,
+    pub(crate) idempotency_key: String,
+    /// Set once the HTML content has been reviewed
+    pub(crate) reviewed: String,
+    pub(crate) track: bool,
+}
+
+impl Draft {
+    fn new() -> Self {
+        Self {
+            title: String::new(),
+            text_content: String::new(),
+            html_content: String::new(),
+            lists: vec![DEFAULT_LIST.to_owned()],
+            segment: String::new(),
+            idempotency_key: uuid::Uuid::new_v4().to_string(),
+            reviewed: String::new(),
+            track: false,
+        }
+    }
+
+    fn is_sent_to(&self, list: &MailingList) -> bool {
+        self.lists.contains(&list.slug)
+    }
+}
 
 pub async fn get_newsletters_page(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    csp_nonce: web::ReqData<CspNonce>,
+    pool: web::Data<PgPool>,
+    tracking_settings: web::Data<TrackingSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-    let script = include_str!("./disable-submit-button.js");
-    let idempotency_key = uuid::Uuid::new_v4().to_string();
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-            </head>
-            <body>
-                {msg_html}
-                <h1>Publish a newsletter:</h1>
-				<form id="publishForm" action="/admin/newsletters" method="post">
-				<label
-					>Title
-					<input
-					type="text"
-					placeholder="Something enticing..."
-					name="title"
-                    required
-					/>
-				</label>
-				<br />
-				<label
-					>Content (Text)
-					<textarea
-                    placeholder="Enter the content in plain text"
-					rows="20"
-                    cols="50"
-					name="text_content"
-                    required
-                    ></textarea>
-				</label>
-				<br />
-				<label
-					>Content (HTML)
-					<textarea
-                    placeholder="Enter the content in HTML format"
-					rows="20"
-                    cols="50"
-					name="html_content"
-                    required
-                    ></textarea>
-				</label>
-				<br />
-                <input hidden type="text" name"idempotency_key" value="{idempotency_key}">
-				<button id="submitButton" type="submit">Publish</button>
-				</form>
-				<p><a href="/admin/dashboard">&lt;- Back</a></p>
-            </body>
-            <script>
-            {script}
-            </script>
-            </html>
-            "#
-        )))
+    render_form(
+        pool.get_ref(),
+        templates::flash_messages(&flash_messages),
+        Draft::new(),
+        Vec::new(),
+        tracking_settings.enabled,
+        csrf_token.into_inner(),
+        csp_nonce.into_inner(),
+    )
+    .await
+}
+
+/// The publish form, filled in with `draft`.
+pub(crate) async fn render_form(
+    pool: &PgPool,
+    flash_messages: Vec<String>,
+    draft: Draft,
+    findings: Vec<String>,
+    tracking_enabled: bool,
+    csrf_token: CsrfToken,
+    csp_nonce: CspNonce,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = lists::all(pool).await.map_err(e500)?;
+    let list_ids: Vec<Uuid> = lists
+        .iter()
+        .filter(|list| draft.is_sent_to(list))
+        .map(|list| list.list_id)
+        .collect();
+    let segment_id = Uuid::parse_str(&draft.segment).ok();
+    let recipients = segments::count_recipients(pool, &list_ids, segment_id)
+        .await
+        .map_err(e500)?;
+    let segments = segments::all(pool).await.map_err(e500)?;
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id AS id, title, published_at
+        FROM newsletter_issues
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT 10
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the latest newsletter issues.")
+    .map_err(e500)?;
+    render_html(&NewslettersTemplate {
+        flash_messages,
+        lists,
+        segments,
+        recipients,
+        draft,
+        findings,
+        tracking_enabled,
+        issues,
+        attachment_types: ATTACHMENT_TYPES.join(","),
+        max_attachments_size_mb: MAX_ATTACHMENTS_SIZE / 1024 / 1024,
+        csrf_token,
+        csp_nonce,
+        script: concat!(
+            include_str!("./disable-submit-button.js"),
+            include_str!("./recipient-count.js")
+        ),
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct RecipientsParameters {
+    #[serde(default)]
+    lists: Vec<String>,
+    #[serde(default)]
+    segment: String,
+}
+
+#[derive(serde::Serialize)]
+struct RecipientCount {
+    recipients: i64,
+}
+
+/// How many subscribers an issue to the given lists and segment would go to,
+/// shown on the publish form as they are picked.
+#[tracing::instrument(name = "Count the recipients of an issue", skip_all)]
+pub async fn get_recipient_count(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // `web::Query` cannot read repeated fields, as sent by checkboxes
+    let RecipientsParameters { lists, segment } =
+        serde_html_form::from_str(request.query_string()).map_err(e400)?;
+    let list_ids: Vec<Uuid> = match lists::find_all(pool.get_ref(), &lists).await {
+        Ok(lists) => lists.into_iter().map(|list| list.list_id).collect(),
+        Err(ListError::Unknown(slug)) => {
+            return Err(e400(format!("There is no list called '{}'.", slug)));
+        }
+        Err(ListError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let segment_id = match segment.as_str() {
+        "" => None,
+        segment_id => Some(Uuid::parse_str(segment_id).map_err(e400)?),
+    };
+    let recipients = segments::count_recipients(pool.get_ref(), &list_ids, segment_id)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
+
+struct IssueSummary {
+    id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter_issue.html")]
+struct IssueTemplate {
+    flash_messages: Vec<String>,
+    title: String,
+    published_at: DateTime<Utc>,
+    recipients: i32,
+    tracked: bool,
+    engagement: Engagement,
+}
+
+/// An issue, and how it was received when tracked.
+#[tracing::instrument(name = "Show a newsletter issue", skip(flash_messages, pool))]
+pub async fn get_issue_page(
+    flash_messages: IncomingFlashMessages,
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let Some(issue) = sqlx::query!(
+        r#"
+        SELECT title, published_at, n_recipients, tracked
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?
+    else {
+        return Err(actix_web::error::ErrorNotFound("No such issue."));
+    };
+    let engagement = tracking::engagement(pool.get_ref(), newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    render_html(&IssueTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        title: issue.title,
+        published_at: issue.published_at,
+        recipients: issue.n_recipients,
+        tracked: issue.tracked,
+        engagement,
+    })
 }
@@ -1,19 +1,64 @@
-use actix_web::{HttpResponse, web};
+use actix_multipart::Multipart;
+use actix_web::error::PayloadError;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use tracing_actix_web::RequestId;
 use uuid::Uuid;
 
-use crate::authentication::UserId;
+use super::get::{Draft, render_form};
+use crate::attachments::{self, Attachment};
+use crate::authentication::{CsrfToken, UserId};
+use crate::configuration::TrackingSettings;
+use crate::email_templates::{self, EmailKind, EmailTemplate};
+use crate::html_lint;
 use crate::idempotency::{IdempotencyKey, save_response, try_processing};
+use crate::lists::{self, ListError, MailingList};
+use crate::markdown;
+use crate::personalisation;
+use crate::security_headers::CspNonce;
+use crate::segments;
+use crate::telemetry::current_traceparent;
+use crate::tracking;
 use crate::utility::{e400, e500, see_other};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
+    #[serde(default)]
+    mode: AuthoringMode,
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
+    #[serde(default)]
+    markdown_content: String,
     idempotency_key: String,
+    /// Slugs of the lists to publish to, one checkbox each
+    #[serde(default)]
+    lists: Vec<String>,
+    /// Everybody on the lists when empty
+    #[serde(default)]
+    segment: String,
+    /// Set when the HTML content was sent back to be reviewed
+    #[serde(default)]
+    reviewed: String,
+    /// Whether opens and clicks are tracked
+    #[serde(default)]
+    track: bool,
+}
+
+#[derive(serde::Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum AuthoringMode {
+    /// Both bodies written by hand
+    #[default]
+    Html,
+    /// Both bodies rendered from Markdown
+    Markdown,
 }
 
 #[tracing::instrument(
@@ -21,19 +66,118 @@ pub struct FormData {
     skip_all,
     fields(user_id=%&*user_id)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: web::Bytes,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    request_id: RequestId,
+    csrf_token: web::ReqData<CsrfToken>,
+    csp_nonce: web::ReqData<CspNonce>,
+    tracking_settings: web::Data<TrackingSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    let FormData {
-        title,
-        text_content,
-        html_content,
-        idempotency_key,
-    } = form.0;
+    let publish_context = PublishContext::new(*user_id, request_id);
+    let (
+        FormData {
+            title,
+            mode,
+            text_content,
+            html_content,
+            markdown_content,
+            idempotency_key,
+            lists,
+            segment,
+            reviewed,
+            track,
+        },
+        uploads,
+    ) = read_form(&request, body).await?;
+    let draft_key = idempotency_key.clone();
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let content = match mode {
+        AuthoringMode::Html if text_content.is_empty() || html_content.is_empty() => {
+            FlashMessage::error("Fill in both the text and the HTML content.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+        AuthoringMode::Html => {
+            // Browsers send line breaks as CRLF
+            let html_content = html_content.replace("\r\n", "\n");
+            let checked = html_lint::check(&html_content);
+            if !checked.findings.is_empty() && reviewed != review_digest(&html_content) {
+                // Sent back, with what would be sent instead
+                let draft = Draft {
+                    title,
+                    text_content,
+                    reviewed: review_digest(&checked.html),
+                    html_content: checked.html,
+                    lists,
+                    segment,
+                    idempotency_key: draft_key,
+                    track,
+                };
+                let findings = checked.findings.iter().map(|f| f.to_string()).collect();
+                let mut flash_messages = Vec::new();
+                if !uploads.is_empty() {
+                    flash_messages.push("Attach the files again before publishing.".into());
+                }
+                return render_form(
+                    pool.get_ref(),
+                    flash_messages,
+                    draft,
+                    findings,
+                    tracking_settings.enabled,
+                    csrf_token.into_inner(),
+                    csp_nonce.into_inner(),
+                )
+                .await;
+            }
+            IssueContent::from_html(title, text_content, html_content)
+        }
+        AuthoringMode::Markdown if markdown_content.is_empty() => {
+            FlashMessage::error("Fill in the Markdown content.").send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+        AuthoringMode::Markdown => IssueContent::from_markdown(title, markdown_content),
+    };
+    if lists.is_empty() {
+        FlashMessage::error("Pick at least one list to publish to.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if let Err(e) = personalisation::check_issue(&content.text_content, &content.html_content) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let layout = email_templates::load(pool.get_ref(), EmailKind::NewsletterLayout)
+        .await
+        .map_err(e500)?;
+    let content = content.wrap_in(&layout);
+    let attachments = match attachments::prepare(uploads, &content.html_content) {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    let lists = match lists::find_all(pool.get_ref(), &lists).await {
+        Ok(lists) => lists,
+        Err(ListError::Unknown(slug)) => {
+            return Err(e400(format!("There is no list called '{}'.", slug)));
+        }
+        Err(ListError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let segment_id = match segment.as_str() {
+        "" => None,
+        segment_id => {
+            let segment_id = Uuid::parse_str(segment_id).map_err(e400)?;
+            segments::find(pool.get_ref(), segment_id)
+                .await
+                .map_err(e500)?
+                .ok_or_else(|| e400("There is no such segment."))?;
+            Some(segment_id)
+        }
+    };
     // Return early if we have a saved response in the database
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
@@ -46,10 +190,26 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &content,
+        &lists,
+        segment_id,
+        &publish_context,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    attachments::store(&mut transaction, issue_id, &attachments)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to store the attachments of a newsletter issue")
         .map_err(e500)?;
+    if track && tracking_settings.enabled {
+        tracking::track_issue(&mut transaction, issue_id, &content.html_content)
+            .await
+            .context("Failed to store the links of a newsletter issue")
+            .map_err(e500)?;
+    }
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("Failed to enqueue delivery tasks")
@@ -62,6 +222,56 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
+/// The form is sent as a multipart form when files are attached to it.
+/// `web::Form` cannot read repeated fields, as sent by checkboxes.
+async fn read_form(
+    request: &HttpRequest,
+    body: web::Bytes,
+) -> Result<(FormData, Vec<Attachment>), actix_web::Error> {
+    if request.content_type() != "multipart/form-data" {
+        let form = serde_html_form::from_bytes(&body).map_err(e400)?;
+        return Ok((form, Vec::new()));
+    }
+    let stream = futures_util::stream::once(async move { Ok::<_, PayloadError>(body) });
+    let mut multipart = Multipart::new(request.headers(), stream);
+    let mut fields = Vec::new();
+    let mut uploads = Vec::new();
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(e400)?;
+        let name = field.name().unwrap_or_default().to_owned();
+        let file_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .map(ToOwned::to_owned);
+        let content_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_owned())
+            .unwrap_or_default();
+        let mut content = Vec::new();
+        while let Some(chunk) = field.next().await {
+            content.extend_from_slice(&chunk.map_err(e400)?);
+        }
+        match file_name {
+            // Browsers send an empty file when none was picked
+            Some(file_name) if file_name.is_empty() => {}
+            Some(file_name) => uploads.push(Attachment::new(file_name, content_type, content)),
+            None => fields.push((name, String::from_utf8(content).map_err(e400)?)),
+        }
+    }
+    let fields = serde_html_form::to_string(&fields).map_err(e500)?;
+    let form = serde_html_form::from_str(&fields).map_err(e400)?;
+    Ok((form, uploads))
+}
+
+/// Identifies HTML content sent back to be reviewed, so that it is
+/// published once it comes back unchanged.
+fn review_digest(html_content: &str) -> String {
+    Sha256::digest(html_content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 fn success_message() -> FlashMessage {
     FlashMessage::success(
         "The newsletter issue has been accepted - \
@@ -69,12 +279,76 @@ fn success_message() -> FlashMessage {
     )
 }
 
+/// Who published an issue, and through which request.
+/// Stored with the issue and its delivery tasks, so that deliveries
+/// can be traced back to the publishing request.
+pub(crate) struct PublishContext {
+    user_id: Uuid,
+    request_id: Uuid,
+    /// The W3C trace context of the publishing request
+    traceparent: Option<String>,
+}
+
+impl PublishContext {
+    /// Must be called from within the publishing request's span.
+    pub(crate) fn new(user_id: Uuid, request_id: RequestId) -> Self {
+        Self {
+            user_id,
+            request_id: request_id.into(),
+            traceparent: current_traceparent(),
+        }
+    }
+}
+
+/// What subscribers get, and the Markdown it was rendered from, if any.
+pub(crate) struct IssueContent {
+    pub(crate) title: String,
+    pub(crate) text_content: String,
+    pub(crate) html_content: String,
+    pub(crate) markdown_content: Option<String>,
+}
+
+impl IssueContent {
+    /// Whatever the HTML content may not contain is removed, however
+    /// the issue is published.
+    pub(crate) fn from_html(title: String, text_content: String, html_content: String) -> Self {
+        Self {
+            title,
+            text_content,
+            html_content: html_lint::sanitise(&html_content),
+            markdown_content: None,
+        }
+    }
+
+    pub(crate) fn from_markdown(title: String, markdown: String) -> Self {
+        let rendered = markdown::render(&markdown);
+        Self {
+            title,
+            text_content: rendered.text_content,
+            html_content: rendered.html_content,
+            markdown_content: Some(markdown),
+        }
+    }
+
+    /// Wrap both bodies in the newsletter `layout`, as every issue is, once
+    /// they have been checked: errors are reported on the lines written.
+    pub(crate) fn wrap_in(self, layout: &EmailTemplate) -> Self {
+        let (html_content, text_content) = layout.wrap(&self.html_content, &self.text_content);
+        Self {
+            html_content,
+            text_content,
+            ..self
+        }
+    }
+}
+
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'static, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    content: &IssueContent,
+    lists: &[MailingList],
+    segment_id: Option<Uuid>,
+    publish_context: &PublishContext,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     let query = sqlx::query!(
@@ -84,21 +358,45 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            published_at,
+            published_by,
+            publish_request_id,
+            publish_traceparent,
+            segment_id,
+            markdown_content
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7, $8, $9)
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        content.title,
+        content.text_content,
+        content.html_content,
+        publish_context.user_id,
+        publish_context.request_id,
+        publish_context.traceparent,
+        segment_id,
+        content.markdown_content
+    );
+    transaction.execute(query).await?;
+    let list_ids: Vec<Uuid> = lists.iter().map(|list| list.list_id).collect();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, UNNEST($2::uuid[])
+        "#,
+        newsletter_issue_id,
+        &list_ids
     );
     transaction.execute(query).await?;
     Ok(newsletter_issue_id)
 }
 
+/// Enqueue a delivery task for each address confirmed on any of the issue's lists
+/// and in its segment, once even if it is on several lists or in several cases,
+/// carrying the issue's publish context,
+/// and record on the issue how many were enqueued.
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'static, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
@@ -106,13 +404,33 @@ async fn enqueue_delivery_tasks(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_email,
+            published_by,
+            publish_request_id,
+            publish_traceparent
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT DISTINCT ON (lower(s.email))
+            $1::uuid, s.email, i.published_by, i.publish_request_id, i.publish_traceparent
+        FROM subscriptions s
+        JOIN newsletter_issue_lists il ON il.list_id = s.list_id AND il.newsletter_issue_id = $1
+        JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+        WHERE
+            s.status = 'confirmed' AND
+            subscriber_in_segment(s.id, i.segment_id) AND
+            email_sha256(s.email) NOT IN (SELECT email_sha256 FROM email_suppressions)
+        ORDER BY lower(s.email), s.subscribed_at
+        "#,
+        newsletter_issue_id,
+    );
+    let n_recipients = transaction.execute(query).await?.rows_affected();
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET n_recipients = $2
+        WHERE newsletter_issue_id = $1
         "#,
         newsletter_issue_id,
+        n_recipients as i32
     );
     transaction.execute(query).await?;
     Ok(())
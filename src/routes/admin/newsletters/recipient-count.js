@@ -0,0 +1,15 @@
+
+// Keep the recipient count in sync with the chosen lists and segment
+const recipientCount = document.getElementById('recipientCount');
+
+form.addEventListener('change', async () => {
+  const query = new URLSearchParams();
+  for (const list of form.querySelectorAll('input[name="lists"]:checked')) {
+    query.append('lists', list.value);
+  }
+  query.append('segment', form.elements['segment'].value);
+  const response = await fetch(`/admin/newsletters/recipients?${query}`);
+  if (response.ok) {
+    recipientCount.textContent = (await response.json()).recipients;
+  }
+});
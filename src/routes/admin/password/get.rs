@@ -1,64 +1,23 @@
-use actix_web::HttpResponse;
-use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, web};
 use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 
-use std::fmt::Write;
+use crate::authentication::CsrfToken;
+use crate::templates::{self, render_html};
+
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+struct ChangePasswordTemplate {
+    flash_messages: Vec<String>,
+    csrf_token: CsrfToken,
+}
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-		<!DOCTYPE html>
-		<html lang="en">
-			<head>
-				<meta http-equiv="content-type" content="text/html; charset=utf-8" />
-				<title>Change Password</title>
-			</head>
-			<body>
-				{msg_html}
-				<form action="/admin/password" method="post">
-				<label
-					>Current password
-					<input
-					type="password"
-					placeholder="Enter current password"
-					name="current_password"
-					autocomplete="current_password"
-					/>
-				</label>
-				<br />
-				<label
-					>New password
-					<input
-					type="password"
-					placeholder="Enter new password"
-					name="new_password"
-					autocomplete="new_password"
-					/>
-				</label>
-				<br />
-				<label
-					>Confirm new password
-					<input
-					type="password"
-					placeholder="Type the new password again"
-					name="new_password_check"
-					autocomplete="new_password"
-					/>
-				</label>
-				<br />
-				<button type="submit">Change password</button>
-				</form>
-				<p><a href="/admin/dashboard">&lt;- Back</a></p>
-			</body>
-		</html>
-		"#,
-        )))
+    render_html(&ChangePasswordTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        csrf_token: csrf_token.into_inner(),
+    })
 }
@@ -0,0 +1,89 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::authentication::{CsrfToken, UserId};
+use crate::personal_data::{self, ErasureRequester};
+use crate::routes::personal_data::json_attachment;
+use crate::templates::{self, render_html};
+use crate::utility::{e500, see_other};
+
+pub struct ErasureRecord {
+    pub email_sha256: String,
+    pub requested_by: String,
+    pub rows_erased: i32,
+    pub erased_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/personal_data.html")]
+struct PersonalDataTemplate {
+    flash_messages: Vec<String>,
+    erasures: Vec<ErasureRecord>,
+    csrf_token: CsrfToken,
+}
+
+/// Tools to answer data subject requests, and the erasure audit log.
+pub async fn get_personal_data_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let erasures = sqlx::query_as!(
+        ErasureRecord,
+        r#"
+        SELECT email_sha256, requested_by, rows_erased, erased_at
+        FROM personal_data_erasures
+        ORDER BY erased_at DESC
+        LIMIT 50
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the erasure audit log.")
+    .map_err(e500)?;
+    render_html(&PersonalDataTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        erasures,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailParameters {
+    email: String,
+}
+
+/// Everything we hold about an email address, as a JSON download.
+#[tracing::instrument(name = "Export personal data as an admin", skip_all, fields(user_id=%&*user_id))]
+pub async fn admin_export_personal_data(
+    parameters: web::Query<EmailParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let data = personal_data::collect(&pool, parameters.email.trim())
+        .await
+        .map_err(e500)?;
+    Ok(json_attachment(&data))
+}
+
+#[tracing::instrument(name = "Erase personal data as an admin", skip_all, fields(user_id=%&*user_id))]
+pub async fn admin_erase_personal_data(
+    form: web::Form<EmailParameters>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let rows_erased =
+        personal_data::erase(&pool, form.email.trim(), ErasureRequester::Admin(**user_id))
+            .await
+            .map_err(e500)?;
+    FlashMessage::info(format!(
+        "{} row(s) of personal data have been erased.",
+        rows_erased
+    ))
+    .send();
+    Ok(see_other("/admin/personal_data"))
+}
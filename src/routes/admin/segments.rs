@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::get_segments_page;
+pub use post::{create_segment, delete_segment};
@@ -0,0 +1,55 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::CsrfToken;
+use crate::lists;
+use crate::segments::{self, Segment};
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+pub struct SegmentRow {
+    pub segment: Segment,
+    /// Across all lists
+    pub recipients: i64,
+}
+
+#[derive(Template)]
+#[template(path = "admin/segments.html")]
+struct SegmentsTemplate {
+    flash_messages: Vec<String>,
+    segments: Vec<SegmentRow>,
+    csrf_token: CsrfToken,
+}
+
+#[tracing::instrument(name = "Show segments", skip_all)]
+pub async fn get_segments_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list_ids: Vec<Uuid> = lists::all(pool.get_ref())
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|list| list.list_id)
+        .collect();
+    let mut rows = Vec::new();
+    for segment in segments::all(pool.get_ref()).await.map_err(e500)? {
+        let recipients =
+            segments::count_recipients(pool.get_ref(), &list_ids, Some(segment.segment_id))
+                .await
+                .map_err(e500)?;
+        rows.push(SegmentRow {
+            segment,
+            recipients,
+        });
+    }
+    render_html(&SegmentsTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        segments: rows,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
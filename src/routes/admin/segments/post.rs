@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::segments::{self, Segment, parse_tags};
+use crate::utility::{e500, see_other};
+
+/// Empty filters match everybody.
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    /// Comma-separated
+    #[serde(default)]
+    with_tags: String,
+    /// Comma-separated
+    #[serde(default)]
+    without_tags: String,
+    /// `YYYY-MM-DD`
+    #[serde(default)]
+    subscribed_from: String,
+    /// `YYYY-MM-DD`
+    #[serde(default)]
+    subscribed_to: String,
+    #[serde(default)]
+    email_domain: String,
+}
+
+impl TryFrom<FormData> for Segment {
+    type Error = String;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        let name = form.name.trim().to_owned();
+        if name.is_empty() {
+            return Err("The segment needs a name.".into());
+        }
+        let subscribed_from = parse_date(&form.subscribed_from)?;
+        let subscribed_to = parse_date(&form.subscribed_to)?;
+        if let (Some(from), Some(to)) = (subscribed_from, subscribed_to)
+            && from > to
+        {
+            return Err("The subscription period ends before it starts.".into());
+        }
+        let email_domain = form
+            .email_domain
+            .trim()
+            .trim_start_matches('@')
+            .to_lowercase();
+        if !email_domain.is_empty()
+            && (!email_domain.contains('.')
+                || email_domain.contains(|c: char| c == '@' || c.is_whitespace()))
+        {
+            return Err(format!("{} is not a valid email domain.", email_domain));
+        }
+        Ok(Segment {
+            segment_id: Uuid::new_v4(),
+            name,
+            with_tags: parse_tags(&form.with_tags)?,
+            without_tags: parse_tags(&form.without_tags)?,
+            subscribed_from,
+            subscribed_to,
+            email_domain: Some(email_domain).filter(|d| !d.is_empty()),
+        })
+    }
+}
+
+fn parse_date(value: &str) -> Result<Option<NaiveDate>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::from_str(value)
+        .map(Some)
+        .map_err(|_| format!("{} is not a valid date, use YYYY-MM-DD.", value))
+}
+
+#[tracing::instrument(name = "Create a segment", skip_all)]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment: Segment = match form.0.try_into() {
+        Ok(segment) => segment,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+    if segments::create(pool.get_ref(), &segment)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("The {} segment has been created.", segment.name)).send();
+    } else {
+        FlashMessage::error(format!(
+            "There already is a segment called {}.",
+            segment.name
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+/// Segments that issues were sent to are kept, to know who those went to.
+#[tracing::instrument(name = "Delete a segment", skip(pool))]
+pub async fn delete_segment(
+    segment_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM segments g
+        WHERE
+            segment_id = $1 AND
+            NOT EXISTS (SELECT 1 FROM newsletter_issues i WHERE i.segment_id = g.segment_id)
+        "#,
+        *segment_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete a segment.")
+    .map_err(e500)?;
+    if result.rows_affected() == 1 {
+        FlashMessage::info("The segment has been deleted.").send();
+    } else {
+        FlashMessage::error("Segments that issues were sent to cannot be deleted.").send();
+    }
+    Ok(see_other("/admin/segments"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FormData;
+    use crate::segments::Segment;
+    use chrono::NaiveDate;
+    use claims::{assert_err, assert_ok};
+
+    fn form() -> FormData {
+        FormData {
+            name: "Beta testers".into(),
+            with_tags: "beta".into(),
+            without_tags: String::new(),
+            subscribed_from: String::new(),
+            subscribed_to: String::new(),
+            email_domain: String::new(),
+        }
+    }
+
+    #[test]
+    fn empty_filters_are_left_out() {
+        let segment = assert_ok!(Segment::try_from(form()));
+        assert_eq!(segment.with_tags, vec!["beta".to_string()]);
+        assert!(segment.without_tags.is_empty());
+        assert_eq!(segment.subscribed_from, None);
+        assert_eq!(segment.email_domain, None);
+    }
+
+    #[test]
+    fn dates_and_domains_are_parsed() {
+        let segment = assert_ok!(Segment::try_from(FormData {
+            subscribed_from: "2025-01-01".into(),
+            email_domain: " @Example.COM ".into(),
+            ..form()
+        }));
+        assert_eq!(segment.subscribed_from, NaiveDate::from_ymd_opt(2025, 1, 1));
+        assert_eq!(segment.email_domain.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for invalid in [
+            FormData {
+                name: " ".into(),
+                ..form()
+            },
+            FormData {
+                subscribed_from: "01/01/2025".into(),
+                ..form()
+            },
+            FormData {
+                subscribed_from: "2025-02-01".into(),
+                subscribed_to: "2025-01-01".into(),
+                ..form()
+            },
+            FormData {
+                email_domain: "localhost".into(),
+                ..form()
+            },
+        ] {
+            assert_err!(Segment::try_from(invalid));
+        }
+    }
+}
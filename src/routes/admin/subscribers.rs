@@ -0,0 +1,15 @@
+mod export;
+mod get;
+mod import;
+mod post;
+
+pub use export::export_subscribers;
+pub use get::{download_subscriber_events, get_subscriber_page, get_subscribers_page};
+pub use import::{
+    MAX_IMPORT_SIZE, download_import_report, get_import_page, get_import_report_page,
+    import_subscribers,
+};
+pub use post::{
+    add_subscriber_tags, admin_delete_subscriber, remove_subscriber_tag, resend_confirmation,
+    unsubscribe_subscriber,
+};
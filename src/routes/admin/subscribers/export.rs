@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::api::STATUSES;
+use crate::utility::{e400, e500, spreadsheet_safe};
+
+#[derive(serde::Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Empty values are ignored, as submitted by the export form.
+#[derive(serde::Deserialize)]
+pub struct ExportParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    #[serde(default)]
+    status: String,
+    /// The slug of a list
+    #[serde(default)]
+    list: String,
+    /// First day of subscription to include, as `YYYY-MM-DD`
+    #[serde(default)]
+    from: String,
+    /// Last day of subscription to include, as `YYYY-MM-DD`
+    #[serde(default)]
+    to: String,
+}
+
+#[derive(serde::Serialize)]
+struct ExportRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    /// The slug of the list
+    list: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+}
+
+/// Stream subscribers as CSV or JSON, oldest first.
+///
+/// Rows are sent as they come out of Postgres: the list is never
+/// loaded in memory as a whole.
+#[tracing::instrument(name = "Export subscribers", skip_all)]
+pub async fn export_subscribers(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ExportParameters {
+        format,
+        status,
+        list,
+        from,
+        to,
+    } = parameters.into_inner();
+    let status = Some(status).filter(|s| !s.is_empty());
+    let list = Some(list).filter(|s| !s.is_empty());
+    if let Some(status) = status.as_deref().filter(|s| !STATUSES.contains(s)) {
+        return Err(e400(format!("{} is not a valid status.", status)));
+    }
+    let from = parse_date(&from)?.map(start_of_day);
+    // Up to the end of the last day
+    let to = parse_date(&to)?
+        .and_then(|date| date.checked_add_days(Days::new(1)))
+        .map(start_of_day);
+    let pool = pool.into_inner();
+
+    let rows = async_stream::try_stream! {
+        let mut rows = sqlx::query_as!(
+            ExportRow,
+            r#"
+            SELECT
+                s.id, s.email, s.name, l.slug AS list, s.status,
+                s.subscribed_at, s.confirmed_at, s.unsubscribed_at
+            FROM subscriptions s
+            JOIN lists l USING (list_id)
+            WHERE
+                ($1::text IS NULL OR s.status = $1) AND
+                ($2::timestamptz IS NULL OR s.subscribed_at >= $2) AND
+                ($3::timestamptz IS NULL OR s.subscribed_at < $3) AND
+                ($4::text IS NULL OR l.slug = $4)
+            ORDER BY s.subscribed_at, s.id
+            "#,
+            status,
+            from,
+            to,
+            list
+        )
+        .fetch(pool.as_ref());
+        while let Some(row) = rows.try_next().await? {
+            yield row;
+        }
+    };
+
+    let (content_type, extension, body) = match format {
+        ExportFormat::Csv => (
+            "text/csv; charset=utf-8",
+            "csv",
+            csv_body(rows).boxed_local(),
+        ),
+        ExportFormat::Json => ("application/json", "json", json_body(rows).boxed_local()),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers-{}.{}",
+                Utc::now().format("%Y%m%d%H%M%S"),
+                extension
+            ))],
+        })
+        .streaming(body))
+}
+
+fn parse_date(value: &str) -> Result<Option<NaiveDate>, actix_web::Error> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    NaiveDate::from_str(value)
+        .map(Some)
+        .map_err(|_| e400(format!("{} is not a valid date, use YYYY-MM-DD.", value)))
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+/// A header, then one line per subscriber. Names are whatever subscribers
+/// typed in: they must not run as formulas in the spreadsheet of an admin.
+fn csv_body(
+    rows: impl Stream<Item = Result<ExportRow, sqlx::Error>>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    async_stream::try_stream! {
+        yield csv_line(|writer| {
+            writer.write_record([
+                "id", "email", "name", "list", "status",
+                "subscribed_at", "confirmed_at", "unsubscribed_at",
+            ])
+        })?;
+        for await row in rows {
+            let mut row = row.map_err(e500)?;
+            row.name = spreadsheet_safe(row.name);
+            yield csv_line(|writer| writer.serialize(&row))?;
+        }
+    }
+}
+
+fn csv_line(
+    write: impl FnOnce(&mut csv::Writer<Vec<u8>>) -> Result<(), csv::Error>,
+) -> Result<web::Bytes, actix_web::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    write(&mut writer).map_err(e500)?;
+    Ok(writer.into_inner().map_err(e500)?.into())
+}
+
+/// A JSON array, one subscriber at a time.
+fn json_body(
+    rows: impl Stream<Item = Result<ExportRow, sqlx::Error>>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    async_stream::try_stream! {
+        let mut separator = "[";
+        for await row in rows {
+            let mut chunk = separator.as_bytes().to_vec();
+            serde_json::to_writer(&mut chunk, &row.map_err(e500)?).map_err(e500)?;
+            yield chunk.into();
+            separator = ",";
+        }
+        // An empty export is still an array
+        if separator == "[" {
+            yield web::Bytes::from_static(b"[");
+        }
+        yield web::Bytes::from_static(b"]");
+    }
+}
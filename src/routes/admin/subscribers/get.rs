@@ -0,0 +1,318 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::CsrfToken;
+use crate::email_events::{self, EmailEvent};
+use crate::lists::{self, MailingList};
+use crate::routes::api::STATUSES;
+use crate::segments;
+use crate::subscription_events::{self, SubscriptionEvent};
+use crate::templates::{self, render_html};
+use crate::utility::{e400, e500, escape_like, spreadsheet_safe};
+
+const PAGE_SIZE: i64 = 25;
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct ListParameters {
+    /// Case-insensitive match on either the email or the name
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    status: String,
+    /// The slug of a list
+    #[serde(default)]
+    list: String,
+    #[serde(default)]
+    tag: String,
+    #[serde(default)]
+    sort: SortOrder,
+    /// Starts at 1
+    #[serde(default = "first_page")]
+    page: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+impl ListParameters {
+    /// A link to another page of the same search.
+    fn link_to_page(&self, page: i64) -> String {
+        let parameters = Self {
+            page,
+            ..self.clone()
+        };
+        format!(
+            "/admin/subscribers?{}",
+            serde_urlencoded::to_string(parameters).unwrap_or_default()
+        )
+    }
+}
+
+pub struct SubscriberRow {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// The name of the list
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersTemplate {
+    flash_messages: Vec<String>,
+    parameters: ListParameters,
+    statuses: [&'static str; 3],
+    lists: Vec<MailingList>,
+    subscribers: Vec<SubscriberRow>,
+    total: i64,
+    previous_page: Option<String>,
+    next_page: Option<String>,
+}
+
+#[tracing::instrument(name = "Show subscribers", skip_all)]
+pub async fn get_subscribers_page(
+    flash_messages: IncomingFlashMessages,
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let parameters = parameters.into_inner();
+    let search = Some(parameters.search.trim())
+        .filter(|s| !s.is_empty())
+        .map(escape_like);
+    let status = Some(parameters.status.as_str()).filter(|s| !s.is_empty());
+    let list = Some(parameters.list.as_str()).filter(|s| !s.is_empty());
+    let tag = Some(parameters.tag.trim().to_lowercase()).filter(|s| !s.is_empty());
+    if let Some(status) = status.filter(|s| !STATUSES.contains(s)) {
+        return Err(e400(format!("{} is not a valid status.", status)));
+    }
+    if parameters.page < 1 {
+        return Err(e400("Pages start at 1."));
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id, s.email, s.name, l.name AS list, s.status, s.subscribed_at,
+            COUNT(*) OVER () AS "total!"
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE
+            (
+                $1::text IS NULL OR
+                s.email ILIKE '%' || $1 || '%' ESCAPE '\' OR
+                s.name ILIKE '%' || $1 || '%' ESCAPE '\'
+            ) AND
+            ($2::text IS NULL OR s.status = $2) AND
+            ($6::text IS NULL OR l.slug = $6) AND
+            ($7::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $7
+            ))
+        ORDER BY
+            CASE WHEN $3 THEN s.subscribed_at END ASC,
+            CASE WHEN NOT $3 THEN s.subscribed_at END DESC,
+            s.id
+        LIMIT $4
+        OFFSET $5
+        "#,
+        search,
+        status,
+        parameters.sort == SortOrder::Oldest,
+        PAGE_SIZE,
+        (parameters.page - 1) * PAGE_SIZE,
+        list,
+        tag
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")
+    .map_err(e500)?;
+
+    let total = rows.first().map(|r| r.total).unwrap_or(0);
+    let subscribers = rows
+        .into_iter()
+        .map(|r| SubscriberRow {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            list: r.list,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+        })
+        .collect::<Vec<_>>();
+    let lists = lists::all(pool.get_ref()).await.map_err(e500)?;
+    let previous_page = (parameters.page > 1).then(|| parameters.link_to_page(parameters.page - 1));
+    let next_page =
+        (parameters.page * PAGE_SIZE < total).then(|| parameters.link_to_page(parameters.page + 1));
+    render_html(&SubscribersTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        parameters,
+        statuses: STATUSES,
+        lists,
+        subscribers,
+        total,
+        previous_page,
+        next_page,
+    })
+}
+
+pub struct DeliveryRow {
+    pub title: String,
+    pub outcome: String,
+    pub delivered_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscriber.html")]
+struct SubscriberTemplate {
+    flash_messages: Vec<String>,
+    subscriber: SubscriberRow,
+    tags: Vec<String>,
+    events: Vec<SubscriptionEvent>,
+    deliveries: Vec<DeliveryRow>,
+    /// What the email provider reported
+    email_events: Vec<EmailEvent>,
+    /// Why issues are no longer sent to the subscriber, if they are not
+    suppression: Option<String>,
+    csrf_token: CsrfToken,
+}
+
+#[tracing::instrument(name = "Show a subscriber", skip(flash_messages, pool, csrf_token))]
+pub async fn get_subscriber_page(
+    flash_messages: IncomingFlashMessages,
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, s.name, l.name AS list, s.status, s.subscribed_at
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE s.id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?
+    else {
+        return Err(actix_web::error::ErrorNotFound("No such subscriber."));
+    };
+
+    let tags = segments::tags_of(pool.get_ref(), subscriber_id)
+        .await
+        .map_err(e500)?;
+    let events = subscription_events::list(pool.get_ref(), subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let deliveries = sqlx::query_as!(
+        DeliveryRow,
+        r#"
+        SELECT i.title, d.outcome, d.delivered_at
+        FROM newsletter_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.subscriber_email = $1
+        ORDER BY d.delivered_at DESC
+        "#,
+        subscriber.email
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the deliveries to a subscriber.")
+    .map_err(e500)?;
+    let email_events = email_events::list(pool.get_ref(), &subscriber.email)
+        .await
+        .map_err(e500)?;
+    let suppression = email_events::suppression_of(pool.get_ref(), &subscriber.email)
+        .await
+        .map_err(e500)?;
+
+    render_html(&SubscriberTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        subscriber: SubscriberRow {
+            id: subscriber.id,
+            email: subscriber.email,
+            name: subscriber.name,
+            list: subscriber.list,
+            status: subscriber.status,
+            subscribed_at: subscriber.subscribed_at,
+        },
+        tags,
+        events,
+        deliveries,
+        email_events,
+        suppression,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
+
+/// The subscriber's consent trail as CSV, e.g. to answer a regulator.
+#[tracing::instrument(name = "Download the events of a subscriber", skip(pool))]
+pub async fn download_subscriber_events(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let exists = sqlx::query!("SELECT id FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .context("Failed to retrieve a subscriber.")
+        .map_err(e500)?
+        .is_some();
+    if !exists {
+        return Err(actix_web::error::ErrorNotFound("No such subscriber."));
+    }
+    let events = subscription_events::list(pool.get_ref(), subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if events.is_empty() {
+        writer
+            .write_record([
+                "event_type",
+                "source",
+                "occurred_at",
+                "ip_address",
+                "user_agent",
+                "subscription_token",
+            ])
+            .map_err(e500)?;
+    }
+    for mut event in events {
+        // Sent by whoever made the request
+        event.user_agent = event.user_agent.map(spreadsheet_safe);
+        writer.serialize(&event).map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscriber-{}-events.csv",
+                subscriber_id
+            ))],
+        })
+        .body(body))
+}
@@ -0,0 +1,500 @@
+use std::collections::HashSet;
+
+use actix_multipart::form::{MultipartForm, bytes::Bytes, text::Text};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::authentication::{CsrfToken, UserId};
+use crate::domain::{NewSubscriber, SubcriptionToken};
+use crate::import_confirmations;
+use crate::lists::{self, DEFAULT_LIST, ListError, MailingList};
+use crate::routes::SubscriptionForm;
+use crate::routes::subscriptions::{insert_subscriber, store_token, subcriber_exists};
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
+use crate::templates::{self, render_html};
+use crate::utility::{e400, e500, see_other, spreadsheet_safe};
+
+/// The largest CSV file we accept, in bytes.
+pub const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024;
+
+#[derive(serde::Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Import subscribers as pending, and send each of them a confirmation email
+    SendConfirmations,
+    /// Import subscribers as confirmed, e.g. when they opted in on a previous platform
+    MarkConfirmed,
+}
+
+impl ImportMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::SendConfirmations => "send_confirmations",
+            ImportMode::MarkConfirmed => "mark_confirmed",
+        }
+    }
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    /// `name,email` rows, with an optional header
+    csv: Bytes,
+    mode: Text<ImportMode>,
+    /// The slug of the list to add subscribers to, the default list when missing
+    list: Option<Text<String>>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers_import.html")]
+struct ImportTemplate {
+    flash_messages: Vec<String>,
+    lists: Vec<MailingList>,
+    default_list: &'static str,
+    csrf_token: CsrfToken,
+}
+
+pub async fn get_import_page(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = lists::all(pool.get_ref()).await.map_err(e500)?;
+    render_html(&ImportTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        lists,
+        default_list: DEFAULT_LIST,
+        csrf_token: csrf_token.into_inner(),
+    })
+}
+
+struct CsvRow {
+    line_number: i32,
+    name: String,
+    email: String,
+    /// Set when the row could not be read as `name,email`
+    error: Option<String>,
+}
+
+enum Outcome {
+    Imported,
+    Duplicate,
+    Invalid(String),
+    Failed(String),
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Imported => "imported",
+            Outcome::Duplicate => "duplicate",
+            Outcome::Invalid(_) => "invalid",
+            Outcome::Failed(_) => "failed",
+        }
+    }
+
+    fn detail(&self) -> Option<&str> {
+        match self {
+            Outcome::Imported => None,
+            Outcome::Duplicate => Some("Already subscribed"),
+            Outcome::Invalid(detail) | Outcome::Failed(detail) => Some(detail),
+        }
+    }
+}
+
+/// Import subscribers from an uploaded CSV file, one at a time,
+/// recording what happened to each row in a report. Confirmation emails
+/// are queued, for the worker to send.
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip_all,
+    fields(user_id=%&*user_id, mode=?form.mode.0)
+)]
+pub async fn import_subscribers(
+    form: MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportForm { csv, mode, list } = form.into_inner();
+    let mode = mode.into_inner();
+    let list =
+        match lists::find_or_default(pool.get_ref(), list.as_deref().map(String::as_str)).await {
+            Ok(list) => list,
+            Err(ListError::Unknown(slug)) => {
+                return Err(e400(format!("There is no list called '{}'.", slug)));
+            }
+            Err(ListError::Unexpected(e)) => return Err(e500(e)),
+        };
+    let rows = match parse_rows(&csv.data) {
+        Ok(rows) if !rows.is_empty() => rows,
+        Ok(_) => {
+            FlashMessage::error("The CSV file has no subscribers in it.").send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+        Err(e) => {
+            FlashMessage::error(format!("The CSV file could not be read: {}", e)).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (import_id, imported_by, mode, list_id)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        import_id,
+        **user_id,
+        mode.as_str(),
+        list.list_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to record a subscriber import.")
+    .map_err(e500)?;
+
+    let mut seen = HashSet::new();
+    let mut imported = 0;
+    for row in rows {
+        let outcome = match &row.error {
+            Some(error) => Outcome::Invalid(error.clone()),
+            // The same address twice in the file, whatever its case
+            None if !seen.insert(row.email.to_lowercase()) => Outcome::Duplicate,
+            None => import_row(import_id, &row, mode, &list, &pool, &origin)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        line_number = row.line_number,
+                        "Failed to import a row"
+                    );
+                    Outcome::Failed("Could not be imported, try again.".into())
+                }),
+        };
+        if let Outcome::Imported = outcome {
+            imported += 1;
+        }
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriber_import_rows (import_id, line_number, name, email, outcome, detail)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            import_id,
+            row.line_number,
+            row.name,
+            row.email,
+            outcome.as_str(),
+            outcome.detail()
+        )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to record the outcome of an imported row.")
+        .map_err(e500)?;
+    }
+
+    FlashMessage::info(format!("{} subscriber(s) have been imported.", imported)).send();
+    Ok(see_other(&format!(
+        "/admin/subscribers/imports/{}",
+        import_id
+    )))
+}
+
+/// Read `name,email` records, skipping the header if there is one.
+fn parse_rows(data: &[u8]) -> Result<Vec<CsvRow>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let line_number = record.position().map_or(0, |p| p.line()) as i32;
+        let fields: Vec<&str> = record.iter().collect();
+        let row = match fields.as_slice() {
+            [name, email] if rows.is_empty() && is_header(name, email) => continue,
+            [""] => continue,
+            [name, email] => CsvRow {
+                line_number,
+                name: name.to_string(),
+                email: email.to_string(),
+                error: None,
+            },
+            _ => CsvRow {
+                line_number,
+                name: fields.first().unwrap_or(&"").to_string(),
+                email: fields.get(1).unwrap_or(&"").to_string(),
+                error: Some(format!(
+                    "Expected 2 columns (name,email), found {}.",
+                    fields.len()
+                )),
+            },
+        };
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn is_header(name: &str, email: &str) -> bool {
+    name.eq_ignore_ascii_case("name") && email.eq_ignore_ascii_case("email")
+}
+
+async fn import_row(
+    import_id: Uuid,
+    row: &CsvRow,
+    mode: ImportMode,
+    list: &MailingList,
+    pool: &PgPool,
+    origin: &RequestOrigin,
+) -> Result<Outcome, anyhow::Error> {
+    let new_sub: NewSubscriber = match (SubscriptionForm {
+        name: row.name.clone(),
+        email: row.email.clone(),
+        list: None,
+    })
+    .try_into()
+    {
+        Ok(new_sub) => new_sub,
+        Err(e) => return Ok(Outcome::Invalid(e)),
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if subcriber_exists(&new_sub, list.list_id, &mut transaction)
+        .await
+        .context("Failed to look up existing subscribers.")?
+        .is_some()
+    {
+        return Ok(Outcome::Duplicate);
+    }
+    let event = |event_type, subscription_token| NewEvent {
+        event_type,
+        source: EventSource::Import,
+        origin,
+        subscription_token,
+    };
+
+    let inserted = match mode {
+        ImportMode::MarkConfirmed => {
+            insert_confirmed_subscriber(&new_sub, list.list_id, &mut transaction).await
+        }
+        ImportMode::SendConfirmations => {
+            insert_subscriber(&new_sub, list.list_id, &mut transaction).await
+        }
+    };
+    let subscriber_id = match inserted {
+        Ok(subscriber_id) => subscriber_id,
+        Err(e) => return Ok(Outcome::Failed(format!("Could not be stored: {}", e))),
+    };
+    subscription_events::record(
+        &mut *transaction,
+        subscriber_id,
+        event(EventType::Subscribed, None),
+    )
+    .await?;
+
+    match mode {
+        ImportMode::MarkConfirmed => {
+            subscription_events::record(
+                &mut *transaction,
+                subscriber_id,
+                event(EventType::Confirmed, None),
+            )
+            .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to import a subscriber.")?;
+            Ok(Outcome::Imported)
+        }
+        ImportMode::SendConfirmations => {
+            let subscription_token = SubcriptionToken::generate();
+            store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
+                .await
+                .context("Failed to store the confirmation token for an imported subscriber.")?;
+            import_confirmations::enqueue(
+                &mut transaction,
+                import_id,
+                row.line_number,
+                subscriber_id,
+                subscription_token.as_ref(),
+            )
+            .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to import a subscriber.")?;
+            Ok(Outcome::Imported)
+        }
+    }
+}
+
+async fn insert_confirmed_subscriber(
+    new_sub: &NewSubscriber,
+    list_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, confirmed_at, list_id)
+        VALUES ($1, $2, $3, $4, 'confirmed', $4, $5)
+        "#,
+        subscriber_id,
+        new_sub.email.as_ref(),
+        new_sub.name.as_ref(),
+        Utc::now(),
+        list_id
+    );
+    transaction.execute(query).await?;
+    Ok(subscriber_id)
+}
+
+pub struct ReportRow {
+    pub line_number: i32,
+    pub name: String,
+    pub email: String,
+    pub outcome: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers_import_report.html")]
+struct ReportTemplate {
+    flash_messages: Vec<String>,
+    import_id: Uuid,
+    mode: String,
+    /// The name of the list
+    list: String,
+    created_at: DateTime<Utc>,
+    rows: Vec<ReportRow>,
+}
+
+impl ReportTemplate {
+    fn count(&self, outcome: &str) -> usize {
+        self.rows.iter().filter(|r| r.outcome == outcome).count()
+    }
+}
+
+#[tracing::instrument(name = "Show a subscriber import", skip(flash_messages, pool))]
+pub async fn get_import_report_page(
+    flash_messages: IncomingFlashMessages,
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let Some(import) = sqlx::query!(
+        r#"
+        SELECT i.mode, l.name AS list, i.created_at
+        FROM subscriber_imports i
+        JOIN lists l USING (list_id)
+        WHERE i.import_id = $1
+        "#,
+        import_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber import.")
+    .map_err(e500)?
+    else {
+        return Err(actix_web::error::ErrorNotFound("No such import."));
+    };
+    let rows = get_report_rows(&pool, import_id).await.map_err(e500)?;
+    render_html(&ReportTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        import_id,
+        mode: import.mode,
+        list: import.list,
+        created_at: import.created_at,
+        rows,
+    })
+}
+
+/// The report of an import as a CSV file.
+#[tracing::instrument(name = "Download a subscriber import report", skip(pool))]
+pub async fn download_import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let rows = get_report_rows(&pool, import_id).await.map_err(e500)?;
+    if rows.is_empty() {
+        return Err(actix_web::error::ErrorNotFound("No such import."));
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(["line", "name", "email", "outcome", "detail"])
+        .map_err(e500)?;
+    for row in rows {
+        writer
+            .write_record([
+                row.line_number.to_string(),
+                spreadsheet_safe(row.name),
+                spreadsheet_safe(row.email),
+                row.outcome,
+                row.detail.unwrap_or_default(),
+            ])
+            .map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "import-{}.csv",
+                import_id
+            ))],
+        })
+        .body(body))
+}
+
+async fn get_report_rows(pool: &PgPool, import_id: Uuid) -> Result<Vec<ReportRow>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        ReportRow,
+        r#"
+        SELECT line_number, name, email, outcome, detail
+        FROM subscriber_import_rows
+        WHERE import_id = $1
+        ORDER BY line_number
+        "#,
+        import_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the report of a subscriber import.")?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rows;
+
+    #[test]
+    fn the_header_is_optional() {
+        let with_header = parse_rows(b"Name,Email\nUrsula,ursula@example.com\n").unwrap();
+        let without_header = parse_rows(b"Ursula,ursula@example.com\n").unwrap();
+
+        assert_eq!(with_header.len(), 1);
+        assert_eq!(with_header[0].line_number, 2);
+        assert_eq!(without_header.len(), 1);
+        assert_eq!(without_header[0].email, "ursula@example.com");
+    }
+
+    #[test]
+    fn rows_with_the_wrong_number_of_columns_are_reported() {
+        let rows = parse_rows(b"Ursula\nUrsula,ursula@example.com,extra\n\n").unwrap();
+
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r.error.is_some()));
+        assert_eq!(rows[0].name, "Ursula");
+    }
+}
@@ -0,0 +1,237 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::domain::{NewSubscriber, SubcriptionToken};
+use crate::email_client::EmailClient;
+use crate::personal_data::{self, ErasureRequester};
+use crate::routes::SubscriptionForm;
+use crate::routes::subscriptions::{send_confirmation_email, store_token};
+use crate::segments::{self, parse_tags};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
+use crate::utility::{e500, see_other};
+
+fn subscriber_page(subscriber_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/subscribers/{}", subscriber_id))
+}
+
+/// Stop sending newsletters to a subscriber, while keeping their history around.
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(pool, origin))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let unsubscribed = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = now()
+        WHERE id = $1 AND status <> 'unsubscribed'
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to unsubscribe a subscriber.")
+    .map_err(e500)?;
+    if unsubscribed.is_none() {
+        FlashMessage::error("The subscriber has already unsubscribed.").send();
+        return Ok(subscriber_page(subscriber_id));
+    }
+    subscription_events::record(
+        &mut *transaction,
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::Unsubscribed,
+            source: EventSource::Admin,
+            origin: &origin,
+            subscription_token: None,
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(subscriber_page(subscriber_id))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagsFormData {
+    /// Comma-separated
+    tags: String,
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(form, pool))]
+pub async fn add_subscriber_tags(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<TagsFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let tags = match parse_tags(&form.tags) {
+        Ok(tags) => tags,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(subscriber_page(subscriber_id));
+        }
+    };
+    for tag in &tags {
+        segments::add_tag(pool.get_ref(), subscriber_id, tag)
+            .await
+            .map_err(e500)?;
+    }
+    Ok(subscriber_page(subscriber_id))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TagFormData {
+    tag: String,
+}
+
+#[tracing::instrument(name = "Remove a tag from a subscriber", skip(form, pool))]
+pub async fn remove_subscriber_tag(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<TagFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    segments::remove_tag(pool.get_ref(), subscriber_id, &form.tag)
+        .await
+        .map_err(e500)?;
+    Ok(subscriber_page(subscriber_id))
+}
+
+/// Send a new confirmation link to a subscriber who has not confirmed yet.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(pool, email_client, base_url, origin)
+)]
+pub async fn resend_confirmation(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let Some(subscriber) = sqlx::query!(
+        r#"
+        SELECT s.name, s.email, l.name AS list_name
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE s.id = $1 AND s.status = 'pending_confirmation'
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?
+    else {
+        FlashMessage::error(
+            "Only subscribers pending confirmation can be sent a confirmation email.",
+        )
+        .send();
+        return Ok(subscriber_page(subscriber_id));
+    };
+    let new_sub: NewSubscriber = match (SubscriptionForm {
+        name: subscriber.name,
+        email: subscriber.email,
+        list: None,
+    })
+    .try_into()
+    {
+        Ok(new_sub) => new_sub,
+        Err(e) => {
+            FlashMessage::error(format!("The stored subscriber details are invalid: {}", e)).send();
+            return Ok(subscriber_page(subscriber_id));
+        }
+    };
+
+    let subscription_token = SubcriptionToken::generate();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
+        .await
+        .context("Failed to store a new confirmation token.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new confirmation token.")
+        .map_err(e500)?;
+    send_confirmation_email(
+        pool.get_ref(),
+        &email_client,
+        new_sub,
+        &subscriber.list_name,
+        &base_url.0,
+        subscription_token.as_ref(),
+    )
+    .await
+    .context("Failed to send a confirmation email.")
+    .map_err(e500)?;
+    subscription_events::record(
+        pool.get_ref(),
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::ConfirmationEmailSent,
+            source: EventSource::Admin,
+            origin: &origin,
+            subscription_token: Some(subscription_token.as_ref()),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("A new confirmation email has been sent.").send();
+    Ok(subscriber_page(subscriber_id))
+}
+
+/// Delete a subscriber, together with everything else we hold about them.
+#[tracing::instrument(
+    name = "Delete a subscriber from the admin pages",
+    skip(pool, user_id),
+    fields(user_id=%&*user_id)
+)]
+pub async fn admin_delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        *subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?;
+    match subscriber {
+        Some(subscriber) => {
+            personal_data::erase(&pool, &subscriber.email, ErasureRequester::Admin(**user_id))
+                .await
+                .map_err(e500)?;
+            FlashMessage::info("The subscriber has been deleted.").send();
+        }
+        None => FlashMessage::error("The subscriber does not exist.").send(),
+    }
+    Ok(see_other("/admin/subscribers"))
+}
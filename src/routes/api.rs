@@ -0,0 +1,8 @@
+mod error;
+mod newsletters;
+mod pagination;
+mod subscribers;
+
+pub use error::{ApiError, api_error_handler};
+pub use newsletters::*;
+pub use subscribers::*;
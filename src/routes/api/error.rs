@@ -0,0 +1,101 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderValue, WWW_AUTHENTICATE};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use std::fmt::Formatter;
+
+use crate::lists::ListError;
+use crate::utility::error_chain_fmt;
+
+/// Errors returned by the JSON API.
+///
+/// They are all rendered with the same body,
+/// `{"error": {"code": "...", "message": "..."}}`,
+/// so that clients only have to handle a single error format.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("Missing or invalid API key.")]
+    Unauthorized(#[source] anyhow::Error),
+    #[error("The requested resource does not exist.")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(String),
+    // Do not leak internal details to API consumers
+    #[error("Something went wrong.")]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_error",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::NotFound => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unexpected(_) => "internal_error",
+        }
+    }
+}
+
+impl From<ListError> for ApiError {
+    fn from(e: ListError) -> Self {
+        match e {
+            ListError::Unknown(_) => ApiError::Validation(e.to_string()),
+            ListError::Unexpected(e) => ApiError::Unexpected(e),
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetails<'a>,
+}
+
+#[derive(serde::Serialize)]
+struct ErrorDetails<'a> {
+    code: &'a str,
+    message: String,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: ErrorDetails {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        });
+        if let ApiError::Unauthorized(_) = self {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// Turn extractor failures (malformed JSON, invalid query parameters, ...)
+/// into `ApiError`s, so they share the JSON error body.
+pub fn api_error_handler<E>(e: E, _req: &HttpRequest) -> actix_web::Error
+where
+    E: std::fmt::Display,
+{
+    ApiError::Validation(e.to_string()).into()
+}
@@ -0,0 +1,298 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use tracing_actix_web::RequestId;
+use uuid::Uuid;
+
+use super::ApiError;
+use super::pagination::page;
+use crate::authentication::UserId;
+use crate::email_templates::{self, EmailKind};
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::lists;
+use crate::personalisation;
+use crate::routes::admin::{
+    IssueContent, PublishContext, enqueue_delivery_tasks, insert_newsletter_issue,
+};
+use crate::segments;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(serde::Deserialize)]
+pub struct NewIssueBody {
+    title: String,
+    text_content: Option<String>,
+    html_content: Option<String>,
+    /// Instead of the text and HTML content, rendered to both
+    markdown_content: Option<String>,
+    /// Slugs of the lists to publish to, the default list when empty
+    #[serde(default)]
+    lists: Vec<String>,
+    /// Everybody on the lists when missing
+    segment: Option<Uuid>,
+}
+
+/// A newsletter issue, together with the progress of its delivery.
+#[derive(serde::Serialize)]
+pub struct IssueStatus {
+    id: Uuid,
+    title: String,
+    /// Slugs of the lists the issue was published to
+    lists: Vec<String>,
+    segment: Option<Uuid>,
+    published_at: DateTime<Utc>,
+    /// `in_progress` while some deliveries are still queued, `completed` afterwards
+    status: &'static str,
+    recipients: i64,
+    /// Deliveries done, by outcome: only `sent` ones reached the email API
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+    pending: i64,
+}
+
+struct IssueRecord {
+    id: Uuid,
+    title: String,
+    lists: Vec<String>,
+    segment: Option<Uuid>,
+    published_at: DateTime<Utc>,
+    recipients: i64,
+    sent: i64,
+    failed: i64,
+    skipped: i64,
+    pending: i64,
+}
+
+impl From<IssueRecord> for IssueStatus {
+    fn from(r: IssueRecord) -> Self {
+        Self {
+            id: r.id,
+            title: r.title,
+            lists: r.lists,
+            segment: r.segment,
+            published_at: r.published_at,
+            status: if r.pending > 0 {
+                "in_progress"
+            } else {
+                "completed"
+            },
+            recipients: r.recipients,
+            sent: r.sent,
+            failed: r.failed,
+            skipped: r.skipped,
+            pending: r.pending,
+        }
+    }
+}
+
+/// Publish a newsletter issue to the confirmed subscribers of some lists,
+/// or to a segment of them.
+///
+/// Requests must carry an `Idempotency-Key` header: retrying with the same key
+/// returns the response to the first request instead of publishing again.
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn create_newsletter_issue(
+    request: HttpRequest,
+    body: web::Json<NewIssueBody>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let user_id = user_id.into_inner();
+    let publish_context = PublishContext::new(*user_id, request_id);
+    let idempotency_key = get_idempotency_key(&request)?;
+    let NewIssueBody {
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+        lists,
+        segment,
+    } = body.into_inner();
+    let content = match (text_content, html_content, markdown_content) {
+        (Some(text_content), Some(html_content), None) => {
+            IssueContent::from_html(title, text_content, html_content)
+        }
+        (None, None, Some(markdown)) => IssueContent::from_markdown(title, markdown),
+        _ => {
+            return Err(ApiError::Validation(
+                "Send either `text_content` and `html_content`, or `markdown_content`.".into(),
+            ));
+        }
+    };
+    personalisation::check_issue(&content.text_content, &content.html_content)
+        .map_err(ApiError::Validation)?;
+    let layout = email_templates::load(pool.get_ref(), EmailKind::NewsletterLayout).await?;
+    let content = content.wrap_in(&layout);
+    let lists = if lists.is_empty() {
+        vec![lists::find_or_default(pool.get_ref(), None).await?]
+    } else {
+        lists::find_all(pool.get_ref(), &lists).await?
+    };
+    if let Some(segment_id) = segment {
+        segments::find(pool.get_ref(), segment_id)
+            .await?
+            .ok_or_else(|| ApiError::Validation("There is no such segment.".into()))?;
+    }
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &content,
+        &lists,
+        segment,
+        &publish_context,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    let issue = fetch_issue(&mut *transaction, issue_id)
+        .await?
+        .context("The issue we just published is missing.")?;
+    let response = HttpResponse::Accepted()
+        .insert_header(("Location", format!("/api/v1/newsletters/{}", issue_id)))
+        .json(IssueStatus::from(issue));
+    let response = save_response(transaction, &idempotency_key, *user_id, response).await?;
+    Ok(response)
+}
+
+fn get_idempotency_key(request: &HttpRequest) -> Result<IdempotencyKey, ApiError> {
+    let header_value = request
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .ok_or_else(|| {
+            ApiError::Validation(format!(
+                "The '{}' header is missing.",
+                IDEMPOTENCY_KEY_HEADER
+            ))
+        })?
+        .to_str()
+        .map_err(|_| {
+            ApiError::Validation(format!(
+                "The '{}' header is not a valid string.",
+                IDEMPOTENCY_KEY_HEADER
+            ))
+        })?;
+    header_value
+        .to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| ApiError::Validation(e.to_string()))
+}
+
+#[tracing::instrument(name = "Get newsletter issue status", skip(pool))]
+pub async fn get_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue = fetch_issue(pool.get_ref(), *issue_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(HttpResponse::Ok().json(IssueStatus::from(issue)))
+}
+
+async fn fetch_issue(
+    executor: impl PgExecutor<'_>,
+    issue_id: Uuid,
+) -> Result<Option<IssueRecord>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueRecord,
+        r#"
+        SELECT
+            newsletter_issue_id AS id,
+            title,
+            ARRAY(
+                SELECT l.slug
+                FROM newsletter_issue_lists il
+                JOIN lists l USING (list_id)
+                WHERE il.newsletter_issue_id = i.newsletter_issue_id
+                ORDER BY l.slug
+            ) AS "lists!",
+            segment_id AS segment,
+            published_at,
+            n_recipients::bigint AS "recipients!",
+            p.sent AS "sent!",
+            p.failed AS "failed!",
+            p.skipped AS "skipped!",
+            p.pending AS "pending!"
+        FROM newsletter_issues i
+        JOIN newsletter_issue_progress p USING (newsletter_issue_id)
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve a newsletter issue.")?;
+    Ok(issue)
+}
+
+#[derive(serde::Deserialize)]
+pub struct IssueListParameters {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssuePage {
+    data: Vec<IssueStatus>,
+    limit: i64,
+    offset: i64,
+}
+
+/// The most recently published issues first.
+#[tracing::instrument(name = "List newsletter issues", skip_all)]
+pub async fn list_newsletter_issues(
+    parameters: web::Query<IssueListParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let IssueListParameters { limit, offset } = parameters.into_inner();
+    let (limit, offset) = page(limit, offset)?;
+    let issues = sqlx::query_as!(
+        IssueRecord,
+        r#"
+        SELECT
+            newsletter_issue_id AS id,
+            title,
+            ARRAY(
+                SELECT l.slug
+                FROM newsletter_issue_lists il
+                JOIN lists l USING (list_id)
+                WHERE il.newsletter_issue_id = i.newsletter_issue_id
+                ORDER BY l.slug
+            ) AS "lists!",
+            segment_id AS segment,
+            published_at,
+            n_recipients::bigint AS "recipients!",
+            p.sent AS "sent!",
+            p.failed AS "failed!",
+            p.skipped AS "skipped!",
+            p.pending AS "pending!"
+        FROM newsletter_issues i
+        JOIN newsletter_issue_progress p USING (newsletter_issue_id)
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        OFFSET $2
+        "#,
+        limit,
+        offset
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issues.")?;
+    Ok(HttpResponse::Ok().json(IssuePage {
+        data: issues.into_iter().map(IssueStatus::from).collect(),
+        limit,
+        offset,
+    }))
+}
@@ -0,0 +1,23 @@
+use super::ApiError;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Validate the `limit` and `offset` query parameters of a list endpoint,
+/// falling back to the first page of default size.
+pub(super) fn page(limit: Option<i64>, offset: Option<i64>) -> Result<(i64, i64), ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "The limit must be between 1 and {}.",
+            MAX_PAGE_SIZE
+        )));
+    }
+    let offset = offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(ApiError::Validation(
+            "The offset cannot be negative.".into(),
+        ));
+    }
+    Ok((limit, offset))
+}
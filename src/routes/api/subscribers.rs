@@ -0,0 +1,350 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ApiError;
+use super::pagination::page;
+use crate::authentication::UserId;
+use crate::domain::{NewSubscriber, SubcriptionToken};
+use crate::email_client::EmailClient;
+use crate::lists;
+use crate::personal_data::{self, ErasureRequester};
+use crate::routes::SubscriptionForm;
+use crate::routes::subscriptions::{
+    insert_subscriber, send_confirmation_email, store_token, subcriber_exists,
+};
+use crate::segments::{self, parse_tag};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
+use crate::telemetry::Pii;
+use crate::utility::escape_like;
+
+pub(crate) const STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// The slug of the list this subscription is to
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    /// Case-insensitive match on either the email or the name
+    search: Option<String>,
+    status: Option<String>,
+    /// The slug of a list
+    list: Option<String>,
+    tag: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberPage {
+    data: Vec<Subscriber>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+}
+
+#[tracing::instrument(name = "List subscribers", skip_all)]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let ListParameters {
+        search,
+        status,
+        list,
+        tag,
+        limit,
+        offset,
+    } = parameters.into_inner();
+    let tag = tag.map(|tag| tag.trim().to_lowercase());
+    let search = search.as_deref().map(escape_like);
+    if let Some(status) = status.as_deref().filter(|s| !STATUSES.contains(s)) {
+        return Err(ApiError::Validation(format!(
+            "{} is not a valid status, use one of {}.",
+            status,
+            STATUSES.join(", ")
+        )));
+    }
+    let (limit, offset) = page(limit, offset)?;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id, s.email, s.name, l.slug AS list, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS "tags!",
+            COUNT(*) OVER () AS "total!"
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE
+            (
+                $1::text IS NULL OR
+                s.email ILIKE '%' || $1 || '%' ESCAPE '\' OR
+                s.name ILIKE '%' || $1 || '%' ESCAPE '\'
+            ) AND
+            ($2::text IS NULL OR s.status = $2) AND
+            ($5::text IS NULL OR l.slug = $5) AND
+            ($6::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $6
+            ))
+        ORDER BY s.subscribed_at DESC, s.id
+        LIMIT $3
+        OFFSET $4
+        "#,
+        search,
+        status,
+        limit,
+        offset,
+        list,
+        tag
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")?;
+
+    let total = match rows.first() {
+        Some(row) => row.total,
+        // Past the last page, we need to count separately
+        None if offset > 0 => count_subscribers(&pool, search, status, list, tag).await?,
+        None => 0,
+    };
+    let data = rows
+        .into_iter()
+        .map(|r| Subscriber {
+            id: r.id,
+            email: r.email,
+            name: r.name,
+            list: r.list,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+            tags: r.tags,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        data,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+async fn count_subscribers(
+    pool: &PgPool,
+    search: Option<String>,
+    status: Option<String>,
+    list: Option<String>,
+    tag: Option<String>,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "total!"
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE
+            (
+                $1::text IS NULL OR
+                s.email ILIKE '%' || $1 || '%' ESCAPE '\' OR
+                s.name ILIKE '%' || $1 || '%' ESCAPE '\'
+            ) AND
+            ($2::text IS NULL OR s.status = $2) AND
+            ($3::text IS NULL OR l.slug = $3) AND
+            ($4::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $4
+            ))
+        "#,
+        search,
+        status,
+        list,
+        tag
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers.")?;
+    Ok(row.total)
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = fetch_subscriber(&pool, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+async fn fetch_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            s.id, s.email, s.name, l.slug AS list, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag
+            ) AS "tags!"
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE s.id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewSubscriberBody {
+    name: String,
+    email: String,
+    /// The slug of the list to subscribe to, the default list when missing
+    list: Option<String>,
+}
+
+/// Create a subscriber pending confirmation, and send them the confirmation email.
+#[tracing::instrument(
+    name = "Create subscriber",
+    skip_all,
+    fields(subscriber_email = %Pii(&body.email), subscriber_name = %Pii(&body.name))
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriberBody>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, ApiError> {
+    let NewSubscriberBody { name, email, list } = body.into_inner();
+    let list = lists::find_or_default(pool.get_ref(), list.as_deref()).await?;
+    let new_sub: NewSubscriber = SubscriptionForm {
+        name,
+        email,
+        list: None,
+    }
+    .try_into()
+    .map_err(ApiError::Validation)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    if subcriber_exists(&new_sub, list.list_id, &mut transaction)
+        .await
+        .context("Failed to look up existing subscribers.")?
+        .is_some()
+    {
+        return Err(ApiError::Conflict(format!(
+            "{} is already subscribed to {}.",
+            new_sub.email, list.slug
+        )));
+    }
+    let subscriber_id = insert_subscriber(&new_sub, list.list_id, &mut transaction)
+        .await
+        .context("Failed to insert a new subscriber in the database.")?;
+    subscription_events::record(
+        &mut *transaction,
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::Subscribed,
+            source: EventSource::Api,
+            origin: &origin,
+            subscription_token: None,
+        },
+    )
+    .await?;
+    let subscription_token = SubcriptionToken::generate();
+    store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    send_confirmation_email(
+        pool.get_ref(),
+        &email_client,
+        new_sub,
+        &list.name,
+        &base_url.0,
+        subscription_token.as_ref(),
+    )
+    .await
+    .context("Failed to send a confirmation email.")?;
+    subscription_events::record(
+        pool.get_ref(),
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::ConfirmationEmailSent,
+            source: EventSource::Api,
+            origin: &origin,
+            subscription_token: Some(subscription_token.as_ref()),
+        },
+    )
+    .await?;
+
+    let subscriber = fetch_subscriber(&pool, subscriber_id)
+        .await?
+        .context("The subscriber we just created is missing.")?;
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/api/v1/subscribers/{}", subscriber.id)))
+        .json(subscriber))
+}
+
+/// Delete a subscriber, together with everything else we hold about them.
+#[tracing::instrument(name = "Delete subscriber", skip(pool, user_id), fields(user_id=%&*user_id))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = fetch_subscriber(&pool, *subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    personal_data::erase(&pool, &subscriber.email, ErasureRequester::Api(**user_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// `PUT` is idempotent: the subscriber may already have the tag.
+#[tracing::instrument(name = "Tag subscriber", skip(pool))]
+pub async fn tag_subscriber(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = parse_tag(&tag).map_err(ApiError::Validation)?;
+    fetch_subscriber(&pool, subscriber_id)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    segments::add_tag(pool.get_ref(), subscriber_id, &tag).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(name = "Untag subscriber", skip(pool))]
+pub async fn untag_subscriber(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (subscriber_id, tag) = path.into_inner();
+    let tag = parse_tag(&tag).map_err(ApiError::Validation)?;
+    if !segments::remove_tag(pool.get_ref(), subscriber_id, &tag).await? {
+        return Err(ApiError::NotFound);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
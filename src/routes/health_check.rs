@@ -1,7 +1,134 @@
 #![allow(clippy::async_yields_async)]
-use actix_web::{HttpResponse, Responder};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpResponse, Responder, web};
+use redis::aio::ConnectionManager;
+use sqlx::{Connection, PgPool};
+
+use crate::configuration::HealthCheckSettings;
+use crate::email_client::EmailClient;
 
 #[tracing::instrument(name = "Performing heatlth check")]
 pub async fn health_check() -> impl Responder {
     HttpResponse::Ok()
 }
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Unavailable,
+}
+
+#[derive(serde::Serialize)]
+struct HealthReport {
+    status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, DependencyReport>,
+}
+
+#[derive(serde::Serialize)]
+struct DependencyReport {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Liveness probe: the process is up and serving requests.
+/// It does not look at dependencies, so a database outage
+/// does not get every instance restarted.
+#[tracing::instrument(name = "Performing liveness check")]
+pub async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().json(HealthReport {
+        status: Status::Ok,
+        checks: BTreeMap::new(),
+    })
+}
+
+/// Readiness probe: every dependency we need to serve traffic answers in time.
+/// Returns a 503 if any of them does not.
+#[tracing::instrument(name = "Performing readiness check", skip_all)]
+pub async fn readiness(
+    pool: web::Data<PgPool>,
+    redis: web::Data<ConnectionManager>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<HealthCheckSettings>,
+) -> HttpResponse {
+    let timeout = settings.timeout();
+    let (postgres, redis, email_provider) = tokio::join!(
+        check(timeout, async {
+            pool.acquire().await?.ping().await?;
+            Ok(())
+        }),
+        check(timeout, async {
+            let mut connection = redis.get_ref().clone();
+            redis::cmd("PING")
+                .query_async::<String>(&mut connection)
+                .await?;
+            Ok(())
+        }),
+        async {
+            if settings.check_email_provider {
+                Some(
+                    check(timeout, async {
+                        Ok(email_client.check_reachability().await?)
+                    })
+                    .await,
+                )
+            } else {
+                None
+            }
+        },
+    );
+    let mut checks = BTreeMap::from([("postgres", postgres), ("redis", redis)]);
+    if let Some(email_provider) = email_provider {
+        checks.insert("email_provider", email_provider);
+    }
+
+    if checks
+        .values()
+        .all(|report| matches!(report.status, Status::Ok))
+    {
+        HttpResponse::Ok().json(HealthReport {
+            status: Status::Ok,
+            checks,
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().json(HealthReport {
+            status: Status::Unavailable,
+            checks,
+        })
+    }
+}
+
+async fn check(
+    timeout: Duration,
+    probe: impl Future<Output = Result<(), anyhow::Error>>,
+) -> DependencyReport {
+    let start = Instant::now();
+    let outcome = match tokio::time::timeout(timeout, probe).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(anyhow::anyhow!(
+            "No answer within {} ms",
+            timeout.as_millis()
+        )),
+    };
+    let latency_ms = start.elapsed().as_millis();
+    match outcome {
+        Ok(()) => DependencyReport {
+            status: Status::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "A dependency failed its readiness check");
+            DependencyReport {
+                status: Status::Unavailable,
+                latency_ms,
+                error: Some(e.to_string()),
+            }
+        }
+    }
+}
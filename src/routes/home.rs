@@ -1,8 +1,16 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::HttpResponse;
+use askama::Template;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        // Includes file contents at compile time!!!
-        .body(include_str!("./home/home.html"))
+use crate::templates::render_html;
+
+#[derive(Template)]
+#[template(path = "home.html")]
+struct HomeTemplate {
+    flash_messages: Vec<String>,
+}
+
+pub async fn home() -> Result<HttpResponse, actix_web::Error> {
+    render_html(&HomeTemplate {
+        flash_messages: Vec::new(),
+    })
 }
@@ -1,47 +1,38 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use askama::Template;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
+use crate::templates::{self, render_html};
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate {
+    flash_messages: Vec<String>,
+}
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_html(&LoginTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::LoginTemplate;
+
+    #[test]
+    fn script_tags_in_flash_messages_are_escaped() {
+        let template = LoginTemplate {
+            flash_messages: vec!["<script>alert('pwned')</script>".into()],
+        };
+
+        let html = template.render().unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&#60;script&#62;alert(&#39;pwned&#39;)&#60;/script&#62;"));
     }
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8" />
-                <title>Login</title>
-            </head>
-            <body>
-            	{error_html}
-                <form action="/login" method="post">
-                    <label
-                        >Username
-                        <input
-                        type="text"
-                        name="username"
-                        placeholder="Enter username"
-                        autocomplete="username"
-                    />
-                    </label>
-                    <label
-                        >Password
-                        <input
-                        type="password"
-                        name="password"
-                        placeholder="Enter password"
-                        autocomplete="current-password"
-                    />
-                    </label>
-                    <button type="submit">Login</button>
-                </form>
-            </body>
-            </html>
-        "#,
-        ))
 }
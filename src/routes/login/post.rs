@@ -4,7 +4,7 @@ use secrecy::SecretString;
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{Credentials, validate_credentials},
+    authentication::{Credentials, CsrfToken, validate_credentials},
     session_state::TypedSession,
     utility::see_other,
 };
@@ -41,6 +41,10 @@ pub async fn login(
             session
                 .insert_user_id(user_id)
                 .map_err(|e| login_redirect(LoginError::Unexpected(e.into())))?;
+            // Every session gets its own CSRF token for the admin forms
+            session
+                .insert_csrf_token(&CsrfToken::generate())
+                .map_err(|e| login_redirect(LoginError::Unexpected(e.into())))?;
             Ok(see_other("/admin/dashboard"))
         }
         Err(e) => {
@@ -0,0 +1,65 @@
+use actix_web::http::header::{AUTHORIZATION, ContentType, WWW_AUTHENTICATE};
+use actix_web::{HttpRequest, HttpResponse, web};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::configuration::MetricsSettings;
+use crate::metrics::{Snapshot, metrics};
+use crate::utility::e500;
+
+/// Expose our metrics in the Prometheus text format.
+///
+/// If a bearer token is configured, scrapers must send it
+/// in the `Authorization` header.
+#[tracing::instrument(name = "Export metrics", skip_all)]
+pub async fn get_metrics(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<MetricsSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(expected_token) = &settings.bearer_token {
+        let token = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !bool::from(
+            token
+                .as_bytes()
+                .ct_eq(expected_token.expose_secret().as_bytes()),
+        ) {
+            return Ok(HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, "Bearer"))
+                .finish());
+        }
+    }
+
+    let queue = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "depth!",
+            EXTRACT(EPOCH FROM now() - MIN(enqueued_at))::float8 AS oldest_task_age_seconds
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to inspect the delivery queue.")
+    .map_err(e500)?;
+    let snapshot = Snapshot {
+        pool_connections: pool.size(),
+        pool_idle_connections: pool.num_idle(),
+        delivery_queue_depth: queue.depth,
+        delivery_queue_oldest_task_age_seconds: queue.oldest_task_age_seconds.unwrap_or(0.0),
+    };
+
+    let body = metrics().encode(&snapshot).map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(
+            "text/plain; version=0.0.4; charset=utf-8".parse().unwrap(),
+        ))
+        .body(body))
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub(crate) use get::json_attachment;
+pub use get::{export_personal_data, personal_data_form, personal_data_page};
+pub use post::{erase_personal_data, request_personal_data};
@@ -0,0 +1,85 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::personal_data::{self, PersonalData};
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+#[derive(Template)]
+#[template(path = "personal_data/request.html")]
+struct RequestTemplate {
+    flash_messages: Vec<String>,
+}
+
+/// Where subscribers ask for a link to the data we hold about them.
+pub async fn personal_data_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_html(&RequestTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "personal_data/data.html")]
+struct DataTemplate {
+    flash_messages: Vec<String>,
+    token: String,
+    data: PersonalData,
+}
+
+/// What the link we emailed leads to: a summary of the data we hold,
+/// with a download and an erasure button.
+#[tracing::instrument(name = "Show personal data", skip_all)]
+pub async fn personal_data_page(
+    flash_messages: IncomingFlashMessages,
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = token.into_inner();
+    let email = email_from_token(&pool, &token).await?;
+    let data = personal_data::collect(&pool, &email).await.map_err(e500)?;
+    render_html(&DataTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        token,
+        data,
+    })
+}
+
+#[tracing::instrument(name = "Export personal data", skip_all)]
+pub async fn export_personal_data(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = email_from_token(&pool, &token).await?;
+    let data = personal_data::collect(&pool, &email).await.map_err(e500)?;
+    Ok(json_attachment(&data))
+}
+
+/// The data as a downloadable JSON file.
+pub(crate) fn json_attachment(data: &PersonalData) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("personal-data.json".into())],
+        })
+        .json(data)
+}
+
+/// Unknown and expired tokens get the same 401.
+pub(super) async fn email_from_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<String, actix_web::Error> {
+    personal_data::email_from_token(pool, token)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| {
+            actix_web::error::ErrorUnauthorized(
+                "This link is invalid or has expired, please request a new one.",
+            )
+        })
+}
@@ -0,0 +1,55 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use super::get::email_from_token;
+use crate::domain::SubscriberEmail;
+use crate::personal_data::{self, ErasureRequester};
+use crate::telemetry::Pii;
+use crate::utility::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// Have the worker email a link to the data we hold about an address.
+///
+/// The response is the same whether we know the address or not,
+/// so that the form cannot be used to find out who is subscribed.
+#[tracing::instrument(
+    name = "Request personal data",
+    skip_all,
+    fields(subscriber_email = %Pii(&form.email))
+)]
+pub async fn request_personal_data(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/personal_data"));
+        }
+    };
+    personal_data::enqueue_request(&pool, email.as_ref())
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("If we hold data about this address, we have sent it a link to access it.")
+        .send();
+    Ok(see_other("/personal_data"))
+}
+
+#[tracing::instrument(name = "Erase personal data on request", skip_all)]
+pub async fn erase_personal_data(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = email_from_token(&pool, &token).await?;
+    personal_data::erase(&pool, &email, ErasureRequester::Subscriber)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("All the data we held about you has been erased.").send();
+    Ok(see_other("/personal_data"))
+}
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+
+pub use get::{preferences_page, unsubscribe_page};
+pub use post::{
+    confirm_email_change, request_email_change, unsubscribe_from_everything, update_preferences,
+};
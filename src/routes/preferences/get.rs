@@ -0,0 +1,84 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::preferences::{self, ListChoice};
+use crate::templates::{self, render_html};
+use crate::utility::e500;
+
+#[derive(Template)]
+#[template(path = "preferences/page.html")]
+struct PreferencesTemplate {
+    flash_messages: Vec<String>,
+    token: String,
+    email: String,
+    name: String,
+    lists: Vec<ListChoice>,
+    format: &'static str,
+}
+
+/// The preference center, reached from the link at the bottom of every issue.
+#[tracing::instrument(name = "Show subscriber preferences", skip_all)]
+pub async fn preferences_page(
+    flash_messages: IncomingFlashMessages,
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = token.into_inner();
+    let email = email_from_token(&pool, &token).await?;
+    let name = preferences::name_of(pool.get_ref(), &email)
+        .await
+        .map_err(e500)?
+        .unwrap_or_default();
+    let lists = preferences::list_choices(pool.get_ref(), &email)
+        .await
+        .map_err(e500)?;
+    let format = preferences::format_of(pool.get_ref(), &email)
+        .await
+        .map_err(e500)?;
+    render_html(&PreferencesTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        token,
+        email,
+        name,
+        lists,
+        format: format.as_str(),
+    })
+}
+
+#[derive(Template)]
+#[template(path = "preferences/unsubscribe.html")]
+struct UnsubscribeTemplate {
+    flash_messages: Vec<String>,
+    token: String,
+    email: String,
+}
+
+/// Where `{{ unsubscribe_url }}` leads. Following the link only asks for
+/// confirmation: mail scanners follow links too.
+#[tracing::instrument(name = "Show the unsubscribe page", skip_all)]
+pub async fn unsubscribe_page(
+    flash_messages: IncomingFlashMessages,
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = token.into_inner();
+    let email = email_from_token(&pool, &token).await?;
+    render_html(&UnsubscribeTemplate {
+        flash_messages: templates::flash_messages(&flash_messages),
+        token,
+        email,
+    })
+}
+
+/// Unknown tokens get a 401.
+pub(super) async fn email_from_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<String, actix_web::Error> {
+    preferences::email_from_token(pool, token)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("This link is invalid."))
+}
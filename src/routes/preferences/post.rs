@@ -0,0 +1,220 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::get::email_from_token;
+use crate::domain::{SubcriptionToken, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::email_templates::{self, EmailKind};
+use crate::lists::{self, ListError};
+use crate::preferences::{self, EMAIL_CHANGE_VALIDITY_HOURS, EmailChange, EmailFormat};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscription_events::RequestOrigin;
+use crate::telemetry::Pii;
+use crate::utility::{e400, e500, see_other};
+
+fn preferences_page(token: &str) -> HttpResponse {
+    see_other(&format!("/preferences/{}", token))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    /// Slugs of the lists to receive, one checkbox each
+    #[serde(default)]
+    lists: Vec<String>,
+    format: String,
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip_all)]
+pub async fn update_preferences(
+    token: web::Path<String>,
+    // `web::Form` cannot read repeated fields, as sent by checkboxes
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = email_from_token(&pool, &token).await?;
+    let FormData {
+        name,
+        lists,
+        format,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let name = match SubscriberName::parse(name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(preferences_page(&token));
+        }
+    };
+    let format = EmailFormat::parse(&format).map_err(e400)?;
+    let lists = match lists::find_all(pool.get_ref(), &lists).await {
+        Ok(lists) => lists,
+        Err(ListError::Unknown(slug)) => {
+            return Err(e400(format!("There is no list called '{}'.", slug)));
+        }
+        Err(ListError::Unexpected(e)) => return Err(e500(e)),
+    };
+    let list_ids: Vec<_> = lists.iter().map(|list| list.list_id).collect();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    preferences::set_name(&mut *transaction, &email, &name)
+        .await
+        .map_err(e500)?;
+    preferences::set_format(&mut *transaction, &email, format)
+        .await
+        .map_err(e500)?;
+    preferences::set_lists(&mut transaction, &email, name.as_ref(), &list_ids, &origin)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update preferences.")
+        .map_err(e500)?;
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(preferences_page(&token))
+}
+
+#[tracing::instrument(name = "Unsubscribe from every list", skip_all)]
+pub async fn unsubscribe_from_everything(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = email_from_token(&pool, &token).await?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    preferences::set_lists(&mut transaction, &email, "", &[], &origin)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe.")
+        .map_err(e500)?;
+    FlashMessage::info("You have been unsubscribed from every list.").send();
+    Ok(preferences_page(&token))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailFormData {
+    email: String,
+}
+
+/// Send a confirmation link to the new address: nothing changes until
+/// it is followed.
+#[tracing::instrument(
+    name = "Request an email change",
+    skip_all,
+    fields(subscriber_email = %Pii(&form.email))
+)]
+pub async fn request_email_change(
+    token: web::Path<String>,
+    form: web::Form<EmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let old_email = email_from_token(&pool, &token).await?;
+    let new_email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(preferences_page(&token));
+        }
+    };
+    if new_email.as_ref() == old_email {
+        FlashMessage::error("This is already your email address.").send();
+        return Ok(preferences_page(&token));
+    }
+    let subscription_token = SubcriptionToken::generate();
+    preferences::request_email_change(
+        pool.get_ref(),
+        &old_email,
+        new_email.as_ref(),
+        subscription_token.as_ref(),
+    )
+    .await
+    .map_err(e500)?;
+    let link = format!(
+        "{}/preferences/confirm_email?subscription_token={}",
+        base_url.0,
+        subscription_token.as_ref()
+    );
+    email_templates::send(
+        &pool,
+        &email_client,
+        EmailKind::EmailChange,
+        &new_email,
+        &[
+            ("confirmation_link", &link),
+            ("validity_hours", &EMAIL_CHANGE_VALIDITY_HOURS.to_string()),
+        ],
+    )
+    .await
+    .context("Failed to send an email change confirmation.")
+    .map_err(e500)?;
+    FlashMessage::info(format!(
+        "We have sent a confirmation link to {}.",
+        new_email.as_ref()
+    ))
+    .send();
+    Ok(preferences_page(&token))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmParameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(name = "Confirm an email change", skip_all)]
+pub async fn confirm_email_change(
+    parameters: web::Query<ConfirmParameters>,
+    pool: web::Data<PgPool>,
+    origin: RequestOrigin,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscription_token =
+        SubcriptionToken::parse(parameters.0.subscription_token).map_err(e400)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let change =
+        preferences::confirm_email_change(&mut transaction, subscription_token.as_ref(), &origin)
+            .await
+            .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change an email address.")
+        .map_err(e500)?;
+    let email = match change {
+        EmailChange::Changed { new_email } => {
+            FlashMessage::info(format!("Your email address is now {}.", new_email)).send();
+            new_email
+        }
+        EmailChange::Taken { old_email } => {
+            FlashMessage::error("This address is already subscribed, so it cannot replace yours.")
+                .send();
+            old_email
+        }
+        EmailChange::Invalid => {
+            return Err(actix_web::error::ErrorUnauthorized(
+                "This link is invalid or has expired, please ask for a new one.",
+            ));
+        }
+    };
+    let token = preferences::token_for(pool.get_ref(), &email)
+        .await
+        .map_err(e500)?;
+    Ok(preferences_page(&token))
+}
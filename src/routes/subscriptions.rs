@@ -13,22 +13,31 @@ use sqlx::{Executor, PgPool, Postgres, Row, Transaction};
 use uuid::Uuid;
 
 use crate::{
-    domain::NewSubscriber, domain::SubcriptionToken, email_client::EmailClient,
+    domain::NewSubscriber,
+    domain::SubcriptionToken,
+    email_client::EmailClient,
+    email_templates::{self, EmailKind},
+    lists,
     startup::ApplicationBaseUrl,
+    subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin},
+    telemetry::Pii,
 };
 
 #[derive(Deserialize)]
 pub struct SubscriptionForm {
     pub name: String,
     pub email: String,
+    /// The slug of the list to subscribe to, the default list when missing
+    #[serde(default)]
+    pub list: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool, base_url),
+    skip(form, db_pool, base_url, origin),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = %Pii(&form.email),
+        subscriber_name = %Pii(&form.name)
     )
 )]
 pub async fn subscribe(
@@ -36,7 +45,9 @@ pub async fn subscribe(
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    origin: RequestOrigin,
 ) -> Result<HttpResponse, error::SubscribeError> {
+    let list = lists::find_or_default(db_pool.get_ref(), form.list.as_deref()).await?;
     // Implementing a standard library trait for our type conversion makes our intent clear to Rustaceans,
     // so, very ideomatic.
     let new_sub = form.0.try_into().map_err(SubscribeError::Validation)?;
@@ -44,9 +55,20 @@ pub async fn subscribe(
         .begin()
         .await
         .context("Failed to acquire a Postgres connection form the pool")?;
-    let subscriber_id = insert_subscriber(&new_sub, &mut transaction)
+    let subscriber_id = insert_subscriber(&new_sub, list.list_id, &mut transaction)
         .await
         .context("Failed to insert a new subscriber in the database.")?;
+    subscription_events::record(
+        &mut *transaction,
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::Subscribed,
+            source: EventSource::Form,
+            origin: &origin,
+            subscription_token: None,
+        },
+    )
+    .await?;
     let subscription_token = SubcriptionToken::generate();
     store_token(&mut transaction, subscriber_id, subscription_token.as_ref())
         .await
@@ -56,13 +78,26 @@ pub async fn subscribe(
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
     send_confirmation_email(
-        email_client,
+        db_pool.get_ref(),
+        &email_client,
         new_sub,
+        &list.name,
         &base_url.0,
         subscription_token.as_ref(),
     )
     .await
     .context("Failed to send a confirmation email.")?;
+    subscription_events::record(
+        db_pool.get_ref(),
+        subscriber_id,
+        NewEvent {
+            event_type: EventType::ConfirmationEmailSent,
+            source: EventSource::Form,
+            origin: &origin,
+            subscription_token: Some(subscription_token.as_ref()),
+        },
+    )
+    .await?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -70,18 +105,19 @@ pub async fn subscribe(
     name = "Store subscription in the database",
     skip(transaction, subscription_token)
 )]
-async fn store_token(
+pub(crate) async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), error::StoreTokenError> {
     let query = sqlx::query!(
         r#"
-            INSERT INTO subscription_tokens (subscriber_id, subscription_token) 
-            VALUES ($1, $2)
+            INSERT INTO subscription_tokens (subscriber_id, subscription_token, created_at)
+            VALUES ($1, $2, $3)
         "#,
         subscriber_id,
-        subscription_token
+        subscription_token,
+        Utc::now()
     );
     transaction
         .execute(query)
@@ -95,15 +131,17 @@ async fn store_token(
     name = "Saving new subscriber details in the database",
     skip(new_sub, transaction)
 )]
-async fn subcriber_exists(
+pub(crate) async fn subcriber_exists(
     new_sub: &NewSubscriber,
+    list_id: Uuid,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<Option<Uuid>, sqlx::Error> {
     let query = sqlx::query!(
         r#"
-            SELECT id FROM subscriptions WHERE email = $1
+            SELECT id FROM subscriptions WHERE lower(email) = lower($1) AND list_id = $2
         "#,
         new_sub.email.as_ref(),
+        list_id
     );
     match transaction.fetch_one(query).await {
         Ok(row) => Ok(row.get("id")),
@@ -118,25 +156,39 @@ async fn subcriber_exists(
     name = "Saving new subscriber details in the database",
     skip(new_sub, transaction)
 )]
-async fn insert_subscriber(
+pub(crate) async fn insert_subscriber(
     new_sub: &NewSubscriber,
+    list_id: Uuid,
     transaction: &mut Transaction<'_, Postgres>,
 ) -> Result<Uuid, sqlx::Error> {
-    let existing_sub = subcriber_exists(new_sub, transaction).await?;
+    let existing_sub = subcriber_exists(new_sub, list_id, transaction).await?;
 
     let subscriber_id = match existing_sub {
-        Some(existing_id) => return Ok(existing_id),
+        Some(existing_id) => {
+            // Subscribing again after unsubscribing needs a new confirmation
+            let query = sqlx::query!(
+                r#"
+                    UPDATE subscriptions
+                    SET status = 'pending_confirmation', confirmed_at = NULL, unsubscribed_at = NULL
+                    WHERE id = $1 AND status = 'unsubscribed'
+                "#,
+                existing_id
+            );
+            transaction.execute(query).await?;
+            return Ok(existing_id);
+        }
         None => Uuid::new_v4(),
     };
     let query = sqlx::query!(
         r#"
-            INSERT INTO subscriptions (id, email, name, subscribed_at, status) 
-            VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, list_id)
+            VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
         "#,
         subscriber_id,
         new_sub.email.as_ref(),
         new_sub.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        list_id
     );
     transaction.execute(query).await?;
 
@@ -145,28 +197,30 @@ async fn insert_subscriber(
 
 #[tracing::instrument(
     name = "Send a confirmation link to a new subscriber",
-    skip(email_client, new_sub, base_url)
+    skip(pool, email_client, new_sub, base_url)
 )]
-async fn send_confirmation_email(
-    email_client: web::Data<EmailClient>,
+pub(crate) async fn send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
     new_sub: NewSubscriber,
+    list_name: &str,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let html_body = format!(
-        "Welcome to newsletter!<br />\
-            Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    let plain_body = format!(
-        "Welcome to newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    email_client
-        .send_email(&new_sub.email, "Welcomen", &html_body, &plain_body)
-        .await
+    email_templates::send(
+        pool,
+        email_client,
+        EmailKind::Confirmation,
+        &new_sub.email,
+        &[
+            ("name", new_sub.name.as_ref()),
+            ("list_name", list_name),
+            ("confirmation_link", &confirmation_link),
+        ],
+    )
+    .await
 }
@@ -2,6 +2,7 @@ use std::fmt::Formatter;
 
 use actix_web::{ResponseError, http::StatusCode};
 
+use crate::lists::ListError;
 use crate::utility::error_chain_fmt;
 
 #[derive(thiserror::Error)]
@@ -34,6 +35,15 @@ pub enum SubscribeError {
 // TransactionCommit(#[source] sqlx::Error),
 //}
 
+impl From<ListError> for SubscribeError {
+    fn from(e: ListError) -> Self {
+        match e {
+            ListError::Unknown(_) => SubscribeError::Validation(e.to_string()),
+            ListError::Unexpected(e) => SubscribeError::Unexpected(e),
+        }
+    }
+}
+
 impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
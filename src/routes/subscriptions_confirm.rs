@@ -4,38 +4,63 @@ mod error;
 use actix_web::{HttpResponse, web};
 use anyhow::Context;
 use error::ConfirmError;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::domain::SubcriptionToken;
+use crate::subscription_events::{self, EventSource, EventType, NewEvent, RequestOrigin};
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, db_pool))]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, db_pool, origin)
+)]
 // Just adding this param means that we get a 400 when it is missing
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     db_pool: web::Data<PgPool>,
+    origin: RequestOrigin,
 ) -> Result<HttpResponse, ConfirmError> {
-    let subscripion_token = parameters
+    let subscripion_token: SubcriptionToken = parameters
         .subscription_token
         .to_owned()
         .try_into()
         .map_err(ConfirmError::Validation)?;
-    let id = get_subscriber_id_from_token(&db_pool, subscripion_token)
+    let id = get_subscriber_id_from_token(&db_pool, &subscripion_token)
         .await
         .context("Failed to get a subscriber in the database with the provided token.")?;
 
-    match id {
-        // Non-existing token!
-        None => return Err(ConfirmError::Unauthorized),
-        Some(subscriber_id) => confirm_subscriber(&db_pool, subscriber_id)
-            .await
-            .context("Failed to confirm the subcriber in the database.")?,
+    // Non-existing token!
+    let subscriber_id = id.ok_or(ConfirmError::Unauthorized)?;
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let confirmed = confirm_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to confirm the subcriber in the database.")?;
+    // Following the link again is fine, but it is not a new consent
+    if confirmed {
+        subscription_events::record(
+            &mut *transaction,
+            subscriber_id,
+            NewEvent {
+                event_type: EventType::Confirmed,
+                source: EventSource::ConfirmationLink,
+                origin: &origin,
+                subscription_token: Some(subscripion_token.as_ref()),
+            },
+        )
+        .await?;
     }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -45,7 +70,7 @@ pub async fn confirm(
 )]
 async fn get_subscriber_id_from_token(
     db_pool: &PgPool,
-    subscription_token: SubcriptionToken,
+    subscription_token: &SubcriptionToken,
 ) -> Result<Option<Uuid>, sqlx::Error> {
     let subcriber_id = sqlx::query!(
         r#"
@@ -63,19 +88,24 @@ async fn get_subscriber_id_from_token(
 
 #[tracing::instrument(
     name = "Mark subscriber as confirmed in the database",
-    skip(subscriber_id, db_pool)
+    skip(subscriber_id, transaction)
 )]
-async fn confirm_subscriber(db_pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+/// Returns `false` if the subscriber was not pending confirmation,
+/// e.g. because they have already confirmed.
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
         r#"
-            UPDATE subscriptions 
-            SET status = 'confirmed'
-            WHERE id = $1 
+            UPDATE subscriptions
+            SET status = 'confirmed', confirmed_at = now()
+            WHERE id = $1 AND status = 'pending_confirmation'
         "#,
         subscriber_id
     )
-    .execute(db_pool)
+    .execute(&mut **transaction)
     .await?;
 
-    Ok(())
+    Ok(result.rows_affected() == 1)
 }
@@ -0,0 +1,45 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::configuration::TrackingSettings;
+use crate::tracking;
+use crate::utility::{e500, see_other};
+
+/// A transparent GIF, one pixel wide.
+const PIXEL: [u8; 43] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// The pixel at the end of tracked issues: loading it means the issue was opened.
+#[tracing::instrument(name = "Track an open", skip_all)]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    tracking::record_open(&pool, &settings, &token)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        // Each open should reach us
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL.as_slice()))
+}
+
+/// Where the links of tracked issues lead, on their way to their address.
+#[tracing::instrument(name = "Track a click", skip_all)]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<TrackingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let url = tracking::record_click(&pool, &settings, &token)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("This link is invalid."))?;
+    Ok(see_other(&url))
+}
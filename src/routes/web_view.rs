@@ -0,0 +1,95 @@
+use actix_web::http::header::ContentType;
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments;
+use crate::personalisation::{Variables, personalise};
+use crate::preferences;
+use crate::startup::ApplicationBaseUrl;
+use crate::utility::e500;
+
+/// Where `{{ web_view_url }}` leads: the issue as a web page, personalised
+/// for the subscriber the token belongs to, as in the email they got.
+#[tracing::instrument(name = "Show an issue as a web page", skip_all)]
+pub async fn issue_web_view(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (issue_id, token) = path.into_inner();
+    let email = recipient_of(&pool, issue_id, &token).await?;
+    let html_content = sqlx::query_scalar!(
+        "SELECT html_content FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such issue."))?;
+    let variables = Variables::for_subscriber(&pool, &base_url.0, issue_id, &email)
+        .await
+        .map_err(e500)?;
+    // Images shown inline in emails are served alongside the page
+    let html_content = html_content.replace(
+        "src=\"cid:",
+        &format!("src=\"/issues/{}/{}/images/", issue_id, token),
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(personalise(&html_content, &variables, true)))
+}
+
+/// An image shown in an issue, for its web view.
+#[tracing::instrument(name = "Show an image of an issue", skip_all)]
+pub async fn issue_image(
+    path: web::Path<(Uuid, String, String)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (issue_id, token, file_name) = path.into_inner();
+    recipient_of(&pool, issue_id, &token).await?;
+    let image = attachments::find_inline(pool.get_ref(), issue_id, &file_name)
+        .await
+        .context("Failed to retrieve an image of a newsletter issue.")
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such image."))?;
+    Ok(HttpResponse::Ok()
+        .content_type(image.content_type)
+        .body(image.content))
+}
+
+/// The subscriber the token belongs to, provided the issue was sent to them,
+/// or is about to be. Other subscribers are told there is no such issue.
+async fn recipient_of(
+    pool: &PgPool,
+    issue_id: Uuid,
+    token: &str,
+) -> Result<String, actix_web::Error> {
+    let email = preferences::email_from_token(pool, token)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("This link is invalid."))?;
+    let is_recipient = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM newsletter_deliveries
+            WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+            UNION ALL
+            SELECT 1 FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        ) AS "is_recipient!"
+        "#,
+        issue_id,
+        email
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check the recipients of a newsletter issue.")
+    .map_err(e500)?;
+    if !is_recipient {
+        return Err(actix_web::error::ErrorNotFound("There is no such issue."));
+    }
+    Ok(email)
+}
@@ -0,0 +1,47 @@
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{HttpRequest, HttpResponse, web};
+use base64::Engine;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use subtle::ConstantTimeEq;
+
+use crate::configuration::WebhookSettings;
+use crate::email_events::{self, PostmarkEvent};
+use crate::utility::{e400, e500};
+
+/// Bounces, spam complaints and deliveries, as reported by Postmark.
+///
+/// Postmark must be set up to send the configured credentials with HTTP
+/// Basic authentication. They are checked before the payload is read.
+#[tracing::instrument(name = "Receive an email webhook", skip_all)]
+pub async fn receive_email_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<WebhookSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !is_authenticated(&request, &settings) {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, r#"Basic realm="webhooks""#))
+            .finish());
+    }
+    let event: PostmarkEvent = serde_json::from_slice(&body).map_err(e400)?;
+    email_events::record(&pool, &event).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn is_authenticated(request: &HttpRequest, settings: &WebhookSettings) -> bool {
+    let expected = format!(
+        "{}:{}",
+        settings.username,
+        settings.password.expose_secret()
+    );
+    let credentials = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| base64::engine::general_purpose::STANDARD.decode(value).ok())
+        .unwrap_or_default();
+    credentials.ct_eq(expected.as_bytes()).into()
+}
@@ -0,0 +1,127 @@
+use actix_web::{
+    HttpMessage,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{
+        CONTENT_SECURITY_POLICY, HeaderMap, HeaderName, HeaderValue, REFERRER_POLICY,
+        STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    middleware::Next,
+    web,
+};
+use base64::Engine;
+use rand::RngCore;
+
+use crate::{configuration::SecurityHeadersSettings, utility::e500};
+
+/// Placeholder in the configured Content-Security-Policy that gets
+/// replaced by the nonce of the current request.
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// A random value, unique to each request, that inline scripts must carry
+/// (`<script nonce="...">`) to be allowed by our Content-Security-Policy.
+#[derive(Clone, Debug)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        rand::rng().fill_bytes(&mut bytes);
+        Self(base64::engine::general_purpose::STANDARD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for CspNonce {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for CspNonce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Attach the configured security headers to every response, errors included.
+///
+/// A fresh `CspNonce` is made available to handlers through the request
+/// extensions. Headers already set by a handler are left untouched.
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req
+        .app_data::<web::Data<SecurityHeadersSettings>>()
+        .cloned()
+        .ok_or_else(|| e500("Security headers settings are not registered"))?;
+    let nonce = CspNonce::generate();
+    req.extensions_mut().insert(nonce.clone());
+
+    match next.call(req).await {
+        Ok(mut response) => {
+            insert_security_headers(response.headers_mut(), &settings, &nonce)?;
+            Ok(response)
+        }
+        // Errors raised by inner middleware (e.g. the redirect for anonymous
+        // users) only become responses further up, so we render them here.
+        Err(e) => {
+            let mut response = e.error_response();
+            insert_security_headers(response.headers_mut(), &settings, &nonce)?;
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn insert_security_headers(
+    headers: &mut HeaderMap,
+    settings: &SecurityHeadersSettings,
+    nonce: &CspNonce,
+) -> Result<(), actix_web::Error> {
+    if let Some(policy) = &settings.content_security_policy {
+        let policy = policy.replace(NONCE_PLACEHOLDER, nonce.as_ref());
+        insert_if_missing(headers, CONTENT_SECURITY_POLICY, &policy)?;
+    }
+    if let Some(frame_options) = &settings.frame_options {
+        insert_if_missing(headers, X_FRAME_OPTIONS, frame_options)?;
+    }
+    if let Some(referrer_policy) = &settings.referrer_policy {
+        insert_if_missing(headers, REFERRER_POLICY, referrer_policy)?;
+    }
+    if let Some(max_age) = settings.hsts_max_age_seconds {
+        let hsts = format!("max-age={}; includeSubDomains", max_age);
+        insert_if_missing(headers, STRICT_TRANSPORT_SECURITY, &hsts)?;
+    }
+    insert_if_missing(headers, X_CONTENT_TYPE_OPTIONS, "nosniff")
+}
+
+fn insert_if_missing(
+    headers: &mut HeaderMap,
+    name: HeaderName,
+    value: &str,
+) -> Result<(), actix_web::Error> {
+    if !headers.contains_key(&name) {
+        let value = HeaderValue::from_str(value).map_err(e500)?;
+        headers.insert(name, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CspNonce;
+
+    #[test]
+    fn generated_nonces_are_different() {
+        let nonce1 = CspNonce::generate();
+        let nonce2 = CspNonce::generate();
+        assert_ne!(nonce1.as_ref(), nonce2.as_ref());
+    }
+
+    #[test]
+    fn nonces_are_valid_header_values() {
+        let nonce = CspNonce::generate();
+        assert!(actix_web::http::header::HeaderValue::from_str(nonce.as_ref()).is_ok());
+    }
+}
@@ -0,0 +1,275 @@
+//! Tags on subscribers, and the segments built from them and from
+//! subscriber fields to send an issue to part of the audience.
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Tags are matched case-insensitively, so they are stored lowercase.
+/// Commas separate tags in forms, they cannot be part of one.
+pub fn parse_tag(tag: &str) -> Result<String, String> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err("Tags cannot be empty.".into());
+    }
+    if tag.chars().count() > MAX_TAG_LENGTH {
+        return Err(format!(
+            "Tags are at most {} characters long.",
+            MAX_TAG_LENGTH
+        ));
+    }
+    if tag.chars().any(|c| c == ',' || c.is_control()) {
+        return Err(format!("'{}' is not a valid tag.", tag));
+    }
+    Ok(tag)
+}
+
+/// Comma-separated tags, as typed in a form.
+pub fn parse_tags(tags: &str) -> Result<Vec<String>, String> {
+    let mut parsed = Vec::new();
+    for tag in tags.split(',').filter(|t| !t.trim().is_empty()) {
+        let tag = parse_tag(tag)?;
+        if !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    Ok(parsed)
+}
+
+pub async fn tags_of(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let tags = sqlx::query_scalar!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve the tags of a subscriber.")?;
+    Ok(tags)
+}
+
+/// Tagging twice is not an error.
+pub async fn add_tag(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag
+    )
+    .execute(executor)
+    .await
+    .context("Failed to tag a subscriber.")?;
+    Ok(())
+}
+
+/// Returns `false` if the subscriber did not have the tag.
+pub async fn remove_tag(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag
+    )
+    .execute(executor)
+    .await
+    .context("Failed to remove a tag from a subscriber.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Which subscribers of the chosen lists an issue goes to.
+/// All filters must match; empty ones match everybody.
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+    /// Subscribers must have every one of these tags...
+    pub with_tags: Vec<String>,
+    /// ...and none of these
+    pub without_tags: Vec<String>,
+    /// First day of subscription, in UTC
+    pub subscribed_from: Option<NaiveDate>,
+    /// Last day of subscription, in UTC
+    pub subscribed_to: Option<NaiveDate>,
+    /// Lowercase, without the `@`
+    pub email_domain: Option<String>,
+}
+
+impl Segment {
+    /// The filters in plain words, for the admin pages.
+    pub fn description(&self) -> String {
+        let mut filters = Vec::new();
+        if !self.with_tags.is_empty() {
+            filters.push(format!("tagged {}", self.with_tags.join(" and ")));
+        }
+        if !self.without_tags.is_empty() {
+            filters.push(format!("not tagged {}", self.without_tags.join(" or ")));
+        }
+        if let Some(from) = self.subscribed_from {
+            filters.push(format!("subscribed on or after {}", from));
+        }
+        if let Some(to) = self.subscribed_to {
+            filters.push(format!("subscribed on or before {}", to));
+        }
+        if let Some(domain) = &self.email_domain {
+            filters.push(format!("with an address at {}", domain));
+        }
+        if filters.is_empty() {
+            "Everybody".into()
+        } else {
+            let mut description = filters.join(", ");
+            description[..1].make_ascii_uppercase();
+            description
+        }
+    }
+}
+
+pub async fn all(executor: impl PgExecutor<'_>) -> Result<Vec<Segment>, anyhow::Error> {
+    let segments = sqlx::query_as!(
+        Segment,
+        r#"
+        SELECT
+            segment_id, name, with_tags, without_tags,
+            subscribed_from, subscribed_to, email_domain
+        FROM segments
+        ORDER BY name
+        "#
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve the segments.")?;
+    Ok(segments)
+}
+
+pub async fn find(
+    executor: impl PgExecutor<'_>,
+    segment_id: Uuid,
+) -> Result<Option<Segment>, anyhow::Error> {
+    let segment = sqlx::query_as!(
+        Segment,
+        r#"
+        SELECT
+            segment_id, name, with_tags, without_tags,
+            subscribed_from, subscribed_to, email_domain
+        FROM segments
+        WHERE segment_id = $1
+        "#,
+        segment_id
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve a segment.")?;
+    Ok(segment)
+}
+
+/// Returns `false` when a segment with the same name already exists.
+pub async fn create(
+    executor: impl PgExecutor<'_>,
+    segment: &Segment,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO segments (
+            segment_id, name, with_tags, without_tags,
+            subscribed_from, subscribed_to, email_domain
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        segment.segment_id,
+        segment.name,
+        &segment.with_tags,
+        &segment.without_tags,
+        segment.subscribed_from,
+        segment.subscribed_to,
+        segment.email_domain
+    )
+    .execute(executor)
+    .await
+    .context("Failed to create a segment.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// How many addresses an issue to `list_ids` and `segment_id` would go to,
+/// counting once those on several lists, whatever the case they were given in.
+pub async fn count_recipients(
+    executor: impl PgExecutor<'_>,
+    list_ids: &[Uuid],
+    segment_id: Option<Uuid>,
+) -> Result<i64, anyhow::Error> {
+    let count = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(DISTINCT lower(email)) AS "count!"
+        FROM subscriptions s
+        WHERE
+            s.status = 'confirmed' AND
+            s.list_id = ANY($1) AND
+            subscriber_in_segment(s.id, $2) AND
+            email_sha256(s.email) NOT IN (SELECT email_sha256 FROM email_suppressions)
+        "#,
+        list_ids,
+        segment_id
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to count the recipients of a segment.")?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Segment, parse_tag, parse_tags};
+    use chrono::NaiveDate;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        assert_ok_eq!(parse_tag("  Beta Testers "), "beta testers".to_string());
+    }
+
+    #[test]
+    fn empty_long_and_comma_separated_tags_are_rejected() {
+        assert_err!(parse_tag(" "));
+        assert_err!(parse_tag(&"a".repeat(65)));
+        assert_err!(parse_tag("beta\ttesters"));
+    }
+
+    #[test]
+    fn tag_lists_skip_blanks_and_duplicates() {
+        assert_ok_eq!(
+            parse_tags("beta, vip,, Beta "),
+            vec!["beta".to_string(), "vip".to_string()]
+        );
+        assert_ok_eq!(parse_tags(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn segments_describe_their_filters() {
+        let segment = Segment {
+            with_tags: vec!["beta".into(), "vip".into()],
+            without_tags: vec!["churned".into()],
+            subscribed_from: NaiveDate::from_ymd_opt(2025, 1, 1),
+            email_domain: Some("example.com".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            segment.description(),
+            "Tagged beta and vip, not tagged churned, subscribed on or after 2025-01-01, \
+            with an address at example.com"
+        );
+        assert_eq!(Segment::default().description(), "Everybody");
+    }
+}
@@ -4,9 +4,12 @@ use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::FromRequest;
 use uuid::Uuid;
 
+use crate::authentication::CsrfToken;
+
 pub struct TypedSession(Session);
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -20,6 +23,17 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_csrf_token(&self, token: &CsrfToken) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token.as_ref())
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<CsrfToken>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::CSRF_TOKEN_KEY)?
+            .map(CsrfToken::from))
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
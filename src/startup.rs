@@ -1,6 +1,16 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    reject_anonymous_users, reject_invalid_api_keys, reject_invalid_csrf_token,
+};
+use crate::configuration::{
+    DatabaseSettings, HealthCheckSettings, MetricsSettings, SecurityHeadersSettings, Settings,
+    TrackingSettings, WebhookSettings,
+};
 use crate::email_client::EmailClient;
+use crate::metrics::record_http_metrics;
+use crate::routes::api_error_handler;
+use crate::security_headers::add_security_headers;
+use crate::subscription_events::TrustedProxies;
+use actix_multipart::form::MultipartFormConfig;
 use actix_session::SessionMiddleware;
 use actix_session::storage::RedisSessionStore;
 use actix_web::cookie::Key;
@@ -12,10 +22,12 @@ use actix_web::{
 };
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_flash_messages::storage::CookieMessageStore;
+use redis::aio::ConnectionManager;
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
 use std::io::Error;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
@@ -40,7 +52,13 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.trusted_proxies,
             configuration.redis_uri,
+            configuration.security_headers,
+            configuration.health_check,
+            configuration.metrics,
+            configuration.tracking,
+            configuration.webhooks,
         )
         .await?;
 
@@ -71,44 +89,139 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 // a raw `String` would expose us to conflicts.
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     address: &SocketAddr,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: SecretString,
+    trusted_proxies: Vec<IpAddr>,
     redis_uri: SecretString,
+    security_headers: SecurityHeadersSettings,
+    health_check: HealthCheckSettings,
+    metrics: MetricsSettings,
+    tracking: TrackingSettings,
+    webhooks: WebhookSettings,
 ) -> Result<Server, anyhow::Error> {
     // Wrap the db_pool in a smart, reference-counted, thread-safe pointer,
     // such that various instances of the app can share the same db connection
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
+    let security_headers = web::Data::new(security_headers);
+    let health_check = web::Data::new(health_check);
+    let metrics = web::Data::new(metrics);
+    let tracking = web::Data::new(tracking);
+    let webhooks = web::Data::new(webhooks);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    // The session store does not expose its connection, we need our own
+    // to check on Redis in the readiness probe.
+    let redis_connection = web::Data::new(
+        ConnectionManager::new(redis::Client::open(redis_uri.expose_secret())?).await?,
+    );
     // Move the connection into the closure
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(add_security_headers))
             .wrap(TracingLogger::default())
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
                 redis_store.clone(),
                 secret_key.clone(),
             ))
+            .wrap(from_fn(record_http_metrics))
             .route("/", web::get().to(crate::routes::home))
             .route("/login", web::get().to(crate::routes::login_form))
             .route("/login", web::post().to(crate::routes::login))
             .route("/health_check", web::get().to(crate::routes::health_check))
+            .route("/health_check/live", web::get().to(crate::routes::liveness))
+            .route(
+                "/health_check/ready",
+                web::get().to(crate::routes::readiness),
+            )
+            .route("/metrics", web::get().to(crate::routes::get_metrics))
             .route("/subscriptions", web::post().to(crate::routes::subscribe))
             .route(
                 "/subscriptions/confirm",
                 web::get().to(crate::routes::confirm),
             )
+            .route(
+                "/personal_data",
+                web::get().to(crate::routes::personal_data_form),
+            )
+            .route(
+                "/personal_data",
+                web::post().to(crate::routes::request_personal_data),
+            )
+            .route(
+                "/personal_data/{token}",
+                web::get().to(crate::routes::personal_data_page),
+            )
+            .route(
+                "/personal_data/{token}/export",
+                web::get().to(crate::routes::export_personal_data),
+            )
+            .route(
+                "/personal_data/{token}/erase",
+                web::post().to(crate::routes::erase_personal_data),
+            )
+            // Before `/preferences/{token}`, which would match it too
+            .route(
+                "/preferences/confirm_email",
+                web::get().to(crate::routes::confirm_email_change),
+            )
+            .route(
+                "/preferences/{token}",
+                web::get().to(crate::routes::preferences_page),
+            )
+            .route(
+                "/preferences/{token}",
+                web::post().to(crate::routes::update_preferences),
+            )
+            .route(
+                "/preferences/{token}/email",
+                web::post().to(crate::routes::request_email_change),
+            )
+            .route(
+                "/preferences/{token}/unsubscribe",
+                web::get().to(crate::routes::unsubscribe_page),
+            )
+            .route(
+                "/preferences/{token}/unsubscribe",
+                web::post().to(crate::routes::unsubscribe_from_everything),
+            )
+            .route(
+                "/issues/{newsletter_issue_id}/{token}",
+                web::get().to(crate::routes::issue_web_view),
+            )
+            .route(
+                "/issues/{newsletter_issue_id}/{token}/images/{file_name}",
+                web::get().to(crate::routes::issue_image),
+            )
+            .route("/t/o/{token}.gif", web::get().to(crate::routes::track_open))
+            .route("/t/c/{token}", web::get().to(crate::routes::track_click))
+            .route(
+                "/webhooks/email",
+                web::post().to(crate::routes::receive_email_webhook),
+            )
             .service(
                 web::scope("/admin")
+                    // Middleware wrapped last runs first: we only check the
+                    // CSRF token once we know who the user is.
+                    .wrap(from_fn(reject_invalid_csrf_token))
                     .wrap(from_fn(reject_anonymous_users))
+                    // The CSRF check reads whole request bodies, uploads included
+                    .app_data(web::PayloadConfig::new(crate::routes::MAX_IMPORT_SIZE))
+                    .app_data(
+                        MultipartFormConfig::default()
+                            .total_limit(crate::routes::MAX_IMPORT_SIZE)
+                            .memory_limit(crate::routes::MAX_IMPORT_SIZE),
+                    )
                     .route("/dashboard", web::get().to(crate::routes::admin_dashboard))
                     .route(
                         "/password",
@@ -123,12 +236,162 @@ async fn run(
                     .route(
                         "/newsletters",
                         web::get().to(crate::routes::get_newsletters_page),
+                    )
+                    .route(
+                        "/newsletters/recipients",
+                        web::get().to(crate::routes::get_recipient_count),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(crate::routes::get_issue_page),
+                    )
+                    .route("/lists", web::get().to(crate::routes::get_lists_page))
+                    .route("/lists", web::post().to(crate::routes::create_list))
+                    .route("/segments", web::get().to(crate::routes::get_segments_page))
+                    .route("/segments", web::post().to(crate::routes::create_segment))
+                    .route(
+                        "/segments/{segment_id}/delete",
+                        web::post().to(crate::routes::delete_segment),
+                    )
+                    .route(
+                        "/email_templates",
+                        web::get().to(crate::routes::get_email_templates_page),
+                    )
+                    .route(
+                        "/email_templates/{name}",
+                        web::get().to(crate::routes::get_email_template_page),
+                    )
+                    .route(
+                        "/email_templates/{name}",
+                        web::post().to(crate::routes::update_email_template),
+                    )
+                    .route("/api_keys", web::get().to(crate::routes::get_api_keys_page))
+                    .route("/api_keys", web::post().to(crate::routes::create_api_key))
+                    .route(
+                        "/api_keys/{api_key_id}/revoke",
+                        web::post().to(crate::routes::revoke_api_key),
+                    )
+                    .route(
+                        "/personal_data",
+                        web::get().to(crate::routes::get_personal_data_page),
+                    )
+                    .route(
+                        "/personal_data/export",
+                        web::get().to(crate::routes::admin_export_personal_data),
+                    )
+                    .route(
+                        "/personal_data/erase",
+                        web::post().to(crate::routes::admin_erase_personal_data),
+                    )
+                    .route(
+                        "/subscribers",
+                        web::get().to(crate::routes::get_subscribers_page),
+                    )
+                    // Before `/subscribers/{subscriber_id}`, which would match them too
+                    .route(
+                        "/subscribers/export",
+                        web::get().to(crate::routes::export_subscribers),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(crate::routes::get_import_page),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::post().to(crate::routes::import_subscribers),
+                    )
+                    .route(
+                        "/subscribers/imports/{import_id}",
+                        web::get().to(crate::routes::get_import_report_page),
+                    )
+                    .route(
+                        "/subscribers/imports/{import_id}/report.csv",
+                        web::get().to(crate::routes::download_import_report),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(crate::routes::get_subscriber_page),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/events.csv",
+                        web::get().to(crate::routes::download_subscriber_events),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(crate::routes::unsubscribe_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/resend_confirmation",
+                        web::post().to(crate::routes::resend_confirmation),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(crate::routes::admin_delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/tags",
+                        web::post().to(crate::routes::add_subscriber_tags),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/tags/remove",
+                        web::post().to(crate::routes::remove_subscriber_tag),
+                    ),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_keys))
+                    // Extractor failures get the same JSON body as our own errors
+                    .app_data(web::JsonConfig::default().error_handler(api_error_handler))
+                    .app_data(web::QueryConfig::default().error_handler(api_error_handler))
+                    .app_data(web::PathConfig::default().error_handler(api_error_handler))
+                    .route(
+                        "/subscribers",
+                        web::get().to(crate::routes::list_subscribers),
+                    )
+                    .route(
+                        "/subscribers",
+                        web::post().to(crate::routes::create_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(crate::routes::get_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(crate::routes::delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/tags/{tag}",
+                        web::put().to(crate::routes::tag_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/tags/{tag}",
+                        web::delete().to(crate::routes::untag_subscriber),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::get().to(crate::routes::list_newsletter_issues),
+                    )
+                    .route(
+                        "/newsletters",
+                        web::post().to(crate::routes::create_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(crate::routes::get_newsletter_issue),
                     ),
             )
             // Register DB connection as part of application state
             .app_data(web::Data::clone(&db_pool))
             .app_data(web::Data::clone(&email_client))
             .app_data(web::Data::clone(&base_url))
+            .app_data(web::Data::clone(&trusted_proxies))
+            .app_data(web::Data::clone(&security_headers))
+            .app_data(web::Data::clone(&health_check))
+            .app_data(web::Data::clone(&metrics))
+            .app_data(web::Data::clone(&tracking))
+            .app_data(web::Data::clone(&webhooks))
+            .app_data(web::Data::clone(&redis_connection))
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .bind(address)?
@@ -0,0 +1,302 @@
+//! The append-only trail of how and when each subscriber gave, confirmed
+//! or withdrew their consent.
+use std::future::{Ready, ready};
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    http::header::{FORWARDED, HeaderMap, USER_AGENT, X_FORWARDED_FOR},
+    web,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug)]
+pub enum EventType {
+    Subscribed,
+    ConfirmationEmailSent,
+    Confirmed,
+    Unsubscribed,
+    EmailChanged,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Subscribed => "subscribed",
+            EventType::ConfirmationEmailSent => "confirmation_email_sent",
+            EventType::Confirmed => "confirmed",
+            EventType::Unsubscribed => "unsubscribed",
+            EventType::EmailChanged => "email_changed",
+        }
+    }
+}
+
+/// Through what an event came about.
+#[derive(Clone, Copy, Debug)]
+pub enum EventSource {
+    /// The public subscription form
+    Form,
+    Api,
+    Admin,
+    Import,
+    ConfirmationLink,
+    /// The preference center linked from issues
+    Preferences,
+}
+
+impl EventSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventSource::Form => "form",
+            EventSource::Api => "api",
+            EventSource::Admin => "admin",
+            EventSource::Import => "import",
+            EventSource::ConfirmationLink => "confirmation_link",
+            EventSource::Preferences => "preferences",
+        }
+    }
+}
+
+/// The proxies allowed to tell us who their clients are.
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// Who sent the request that reached us from `peer` through `hops`, the
+    /// forwarded addresses from the client's end to ours. Each proxy appends
+    /// the address it got the request from, so the chain is read from our
+    /// end: the first hop that is not one of our proxies is the client,
+    /// whatever it wrote further left.
+    fn client_address(&self, peer: IpAddr, hops: &[String]) -> Option<IpAddr> {
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            if !self.0.contains(&client) {
+                break;
+            }
+            client = parse_hop(hop)?;
+        }
+        Some(client)
+    }
+}
+
+/// The `for` addresses of `Forwarded`, or `X-Forwarded-For` without it,
+/// in the order the proxies appended them.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<String> {
+    let forwarded: Vec<String> = headers
+        .get_all(FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for")
+                    .then(|| value.trim_matches('"').to_owned())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().to_owned())
+        .collect()
+}
+
+/// A forwarded address, with or without a port. `unknown` and obfuscated
+/// identifiers are not addresses.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|address| address.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// Who made the request that caused an event.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOrigin {
+    /// The peer address, unless the peer is one of the [`TrustedProxies`]:
+    /// the first address forwarded to it by anyone else then. Only what our
+    /// own proxies add to `Forwarded`/`X-Forwarded-For` can be believed.
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for RequestOrigin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let peer = req.peer_addr().map(|address| address.ip());
+        let ip_address = match (peer, req.app_data::<web::Data<TrustedProxies>>()) {
+            (Some(peer), Some(proxies)) => {
+                proxies.client_address(peer, &forwarded_hops(req.headers()))
+            }
+            (peer, None) => peer,
+            (None, _) => None,
+        };
+        ready(Ok(Self {
+            ip_address: ip_address.map(|address| address.to_string()),
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+        }))
+    }
+}
+
+/// An event to append to a subscriber's trail.
+pub struct NewEvent<'a> {
+    pub event_type: EventType,
+    pub source: EventSource,
+    pub origin: &'a RequestOrigin,
+    pub subscription_token: Option<&'a str>,
+}
+
+#[tracing::instrument(name = "Record a subscription event", skip_all, fields(event_type = ?event.event_type))]
+pub async fn record(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    event: NewEvent<'_>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (
+            event_id, subscriber_id, event_type, source, occurred_at,
+            ip_address, user_agent, subscription_token
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event.event_type.as_str(),
+        event.source.as_str(),
+        Utc::now(),
+        event.origin.ip_address,
+        event.origin.user_agent,
+        event.subscription_token
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record a subscription event.")?;
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionEvent {
+    pub event_type: String,
+    pub source: String,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub subscription_token: Option<String>,
+}
+
+impl SubscriptionEvent {
+    /// How the event reads on the admin pages.
+    pub fn description(&self) -> &str {
+        match self.event_type.as_str() {
+            "subscribed" => "Subscribed",
+            "confirmation_email_sent" => "Confirmation email sent",
+            "confirmed" => "Confirmed",
+            "unsubscribed" => "Unsubscribed",
+            "email_changed" => "Email address changed",
+            other => other,
+        }
+    }
+}
+
+/// The trail of a subscriber, oldest event first.
+pub async fn list(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<SubscriptionEvent>, anyhow::Error> {
+    let events = sqlx::query_as!(
+        SubscriptionEvent,
+        r#"
+        SELECT event_type, source, occurred_at, ip_address, user_agent, subscription_token
+        FROM subscription_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at, event_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(executor)
+    .await
+    .context("Failed to retrieve subscription events.")?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrustedProxies, forwarded_hops};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use claims::{assert_none, assert_some_eq};
+    use std::net::IpAddr;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn proxies() -> TrustedProxies {
+        TrustedProxies(vec![ip("10.0.0.1"), ip("10.0.0.2")])
+    }
+
+    fn hops(headers: &[(&'static str, &'static str)]) -> Vec<String> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        forwarded_hops(&map)
+    }
+
+    #[test]
+    fn the_first_untrusted_hop_from_our_end_is_the_client() {
+        let hops = hops(&[("x-forwarded-for", "6.6.6.6, 203.0.113.7, 10.0.0.2")]);
+        assert_some_eq!(
+            proxies().client_address(ip("10.0.0.1"), &hops),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn hops_are_ignored_when_the_peer_is_not_a_trusted_proxy() {
+        let hops = hops(&[("x-forwarded-for", "203.0.113.7")]);
+        assert_some_eq!(
+            proxies().client_address(ip("198.51.100.2"), &hops),
+            ip("198.51.100.2")
+        );
+    }
+
+    #[test]
+    fn forwarded_takes_precedence_and_may_carry_ports() {
+        let hops = hops(&[
+            (
+                "forwarded",
+                r#"for=6.6.6.6, for="[2001:db8::1]:4711";proto=https"#,
+            ),
+            ("forwarded", "for=10.0.0.2:8080"),
+            ("x-forwarded-for", "203.0.113.7"),
+        ]);
+        assert_eq!(hops, ["6.6.6.6", "[2001:db8::1]:4711", "10.0.0.2:8080"]);
+        assert_some_eq!(
+            proxies().client_address(ip("10.0.0.1"), &hops),
+            ip("2001:db8::1")
+        );
+    }
+
+    #[test]
+    fn hops_that_are_not_addresses_leave_the_client_unknown() {
+        let hops = hops(&[("forwarded", "for=unknown, for=10.0.0.2")]);
+        assert_none!(proxies().client_address(ip("10.0.0.1"), &hops));
+    }
+}
@@ -1,10 +1,41 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
 use tokio::task::JoinHandle;
 use tracing::{Subscriber, subscriber::set_global_default};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
-use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::{MakeWriter, writer::BoxMakeWriter};
+use tracing_subscriber::{EnvFilter, Layer, Registry, layer::SubscriberExt};
+
+use crate::configuration::{LogFileSettings, LogFormat, LogOutput, LogRotation, TelemetrySettings};
+
+mod redaction;
+
+pub use redaction::{Pii, RedactingMakeWriter};
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Kept around to flush the spans that have not been exported yet on shutdown.
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
 
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
+/// Log lines are written to `sink` in the configured format, after redacting
+/// personal data according to the configured policy.
+///
+/// Spans are always tracked as OpenTelemetry spans, so that W3C `traceparent`
+/// headers are propagated. They are only exported when an OTLP traces endpoint
+/// (e.g. `http://localhost:4318/v1/traces`) is configured, and we fail if
+/// an exporter cannot be built for it.
+///
 /// # Implementation Notes
 ///
 /// We are using `impl Subscriber` as a return type to avoid having to spell
@@ -13,9 +44,9 @@ use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::Subscriber
 /// to make it possible to pass it to `init_subscriber` later on.
 pub fn get_subscriber<Sink>(
     name: String,
-    env_filter: String,
+    settings: &TelemetrySettings,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+) -> Result<impl Subscriber + Send + Sync, anyhow::Error>
 where
     // This "weird" syntax is a higher-ranked trait bound (HRTB)
     // It basically means that Sink implements the `MakeWriter`
@@ -24,15 +55,121 @@ where
     // for more details.
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
-    // We are falling back to printing all spans at info-level or above if the `RUST_LOG` environent var has not been set.
+    redaction::set_policy(settings.redaction);
+    // We are falling back to the configured level if the `RUST_LOG` environent var has not been set.
     let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.log_level));
+    let tracer_provider = get_tracer_provider(&name, settings.otlp_traces_endpoint.clone())?;
+    let opentelemetry_layer =
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(name.clone()));
+    let sink = RedactingMakeWriter::new(sink, settings.redaction);
+    // Colours only make sense in a terminal
+    let ansi = matches!(settings.log_output, LogOutput::Stdout);
+    // Each format is a different type: we box them to pick one at runtime
+    let formatting_layer = match settings.log_format {
+        LogFormat::Bunyan => JsonStorageLayer
+            .and_then(BunyanFormattingLayer::new(name, sink))
+            .boxed(),
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .pretty()
+            .with_ansi(ansi)
+            .with_writer(sink)
+            .boxed(),
+        LogFormat::Compact => tracing_subscriber::fmt::layer()
+            .compact()
+            .with_ansi(ansi)
+            .with_writer(sink)
+            .boxed(),
+    };
     // The `with` method is provided by `SubscriberExt`, an extension trait for `Subscriber` exposed by `tracing_subscriber`
-    Registry::default()
+    Ok(Registry::default()
         .with(env_filter)
-        .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(opentelemetry_layer))
+}
+
+/// Where log lines go according to `settings`: stdout, or files rotated
+/// in the configured directory.
+pub fn get_sink(settings: &TelemetrySettings) -> BoxMakeWriter {
+    match settings.log_output {
+        LogOutput::Stdout => BoxMakeWriter::new(std::io::stdout),
+        LogOutput::File => {
+            let LogFileSettings {
+                directory,
+                prefix,
+                rotation,
+            } = &settings.log_file;
+            let rotation = match rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            BoxMakeWriter::new(RollingFileAppender::new(rotation, directory, prefix))
+        }
+    }
+}
+
+fn get_tracer_provider(
+    name: &str,
+    otlp_traces_endpoint: Option<String>,
+) -> Result<SdkTracerProvider, anyhow::Error> {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let mut builder = SdkTracerProvider::builder().with_resource(
+        Resource::builder()
+            .with_service_name(name.to_owned())
+            .build(),
+    );
+    if let Some(endpoint) = otlp_traces_endpoint {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpJson)
+            .with_endpoint(endpoint)
+            .build()
+            .context("Failed to build the OTLP span exporter")?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+    let tracer_provider = builder.build();
+    opentelemetry::global::set_tracer_provider(tracer_provider.clone());
+    let _ = TRACER_PROVIDER.set(tracer_provider.clone());
+    Ok(tracer_provider)
+}
+
+/// Export the spans that are still buffered. Call it before exiting.
+pub fn shutdown_tracer_provider() {
+    if let Some(Err(e)) = TRACER_PROVIDER.get().map(SdkTracerProvider::shutdown) {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to shut down the tracer provider"
+        );
+    }
+}
+
+/// The W3C `traceparent` of the current span, to be stored and linked to later.
+pub fn current_traceparent() -> Option<String> {
+    trace_context_headers().remove(TRACEPARENT_HEADER)
+}
+
+/// Link `span` to the span identified by a stored W3C `traceparent`,
+/// e.g. to relate work done in the background to the request that caused it.
+pub fn link_to_traceparent(span: &tracing::Span, traceparent: &str) {
+    let carrier = HashMap::from([(TRACEPARENT_HEADER.to_owned(), traceparent.to_owned())]);
+    let context =
+        opentelemetry::global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        span.add_link(span_context);
+    }
+}
+
+/// The W3C trace context of the current span, as headers for an outgoing request.
+pub fn trace_context_headers() -> HashMap<String, String> {
+    let context = tracing::Span::current().context();
+    let mut headers = HashMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers)
+    });
+    headers
 }
 
 /// Register a subscriber as global default to process span data.
@@ -55,3 +192,41 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{get_subscriber, shutdown_tracer_provider};
+    use crate::configuration::get_configuration;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spans_are_exported_to_the_otlp_collector() {
+        // Arrange
+        let collector = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/traces"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&collector)
+            .await;
+        let mut settings = get_configuration().unwrap().telemetry;
+        settings.otlp_traces_endpoint = Some(format!("{}/v1/traces", collector.uri()));
+        let subscriber = get_subscriber("test".into(), &settings, std::io::sink).unwrap();
+
+        // Act
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("Exported span").in_scope(|| {});
+        });
+        // The exporter blocks on its HTTP calls
+        tokio::task::spawn_blocking(shutdown_tracer_provider)
+            .await
+            .unwrap();
+
+        // Assert
+        let requests = collector.received_requests().await.unwrap();
+        assert!(!requests.is_empty());
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("Exported span"));
+        assert!(body.contains("\"stringValue\": \"test\""));
+    }
+}
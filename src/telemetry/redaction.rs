@@ -0,0 +1,184 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::sync::{LazyLock, OnceLock};
+
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::configuration::RedactionPolicy;
+
+/// Set once, when the subscriber is built. Nothing is redacted until then.
+static POLICY: OnceLock<RedactionPolicy> = OnceLock::new();
+
+static EMAIL_ADDRESS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)+").unwrap());
+
+pub(super) fn set_policy(policy: RedactionPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+fn policy() -> RedactionPolicy {
+    POLICY.get().copied().unwrap_or(RedactionPolicy::None)
+}
+
+/// Personal data about a subscriber (e.g. their name or email address)
+/// to be recorded in a span field or event.
+///
+/// It is displayed according to the configured redaction policy:
+/// ```ignore
+/// tracing::info!(subscriber_email = %Pii(&email), "Sending a confirmation email");
+/// ```
+pub struct Pii<T>(pub T);
+
+impl<T: Display> Display for Pii<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        redact(&self.0.to_string(), policy()).fmt(f)
+    }
+}
+
+fn redact(value: &str, policy: RedactionPolicy) -> String {
+    match policy {
+        RedactionPolicy::None => value.to_owned(),
+        RedactionPolicy::Mask => mask(value),
+        RedactionPolicy::Hash => hash(value),
+    }
+}
+
+/// `ursula@example.com` becomes `u***@example.com`, `Ursula` becomes `U***`.
+fn mask(value: &str) -> String {
+    let (local_part, domain) = match value.rsplit_once('@') {
+        Some((local_part, domain)) => (local_part, Some(domain)),
+        None => (value, None),
+    };
+    let mut masked: String = local_part.chars().take(1).collect();
+    masked.push_str("***");
+    if let Some(domain) = domain {
+        masked.push('@');
+        masked.push_str(domain);
+    }
+    masked
+}
+
+/// A stable digest, so that log lines about the same person can still be
+/// correlated. Case is ignored, as email addresses are case-insensitive.
+fn hash(value: &str) -> String {
+    let digest = Sha256::digest(value.to_lowercase().as_bytes());
+    // 12 hex characters are plenty to tell subscribers apart in logs
+    let prefix: String = digest
+        .iter()
+        .take(6)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", prefix)
+}
+
+/// Wrap a log sink to redact email addresses that end up in log lines without
+/// going through `Pii`, e.g. inside error messages.
+///
+/// Formatters write each line in a single call, which we rely on:
+/// an address split across two writes would go through unredacted.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    policy: RedactionPolicy,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, policy: RedactionPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            policy: self.policy,
+        }
+    }
+}
+
+pub struct RedactingWriter<W> {
+    inner: W,
+    policy: RedactionPolicy,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let RedactionPolicy::None = self.policy {
+            return self.inner.write(buf);
+        }
+        let line = String::from_utf8_lossy(buf);
+        let redacted = EMAIL_ADDRESS.replace_all(&line, |captures: &regex::Captures| {
+            redact(&captures[0], self.policy)
+        });
+        self.inner.write_all(redacted.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RedactingMakeWriter, hash, mask};
+    use crate::configuration::RedactionPolicy;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    #[test]
+    fn emails_are_masked_but_keep_their_domain() {
+        assert_eq!(mask("ursula_le_guin@gmail.com"), "u***@gmail.com");
+        assert_eq!(mask("le guin"), "l***");
+        assert_eq!(mask(""), "***");
+    }
+
+    #[test]
+    fn hashes_are_stable_and_case_insensitive() {
+        let hashed = hash("Ursula_Le_Guin@gmail.com");
+        assert_eq!(hashed, hash("ursula_le_guin@gmail.com"));
+        assert_ne!(hashed, hash("le_guin@gmail.com"));
+        assert!(hashed.starts_with("sha256:"));
+        assert_eq!(hashed.len(), "sha256:".len() + 12);
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn email_addresses_in_log_lines_are_redacted() {
+        let buffer = Buffer::default();
+        let make_writer = RedactingMakeWriter::new(buffer.clone(), RedactionPolicy::Mask);
+
+        make_writer
+            .make_writer()
+            .write_all(br#"{"msg":"ursula_le_guin@gmail.com is already subscribed."}"#)
+            .unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output, r#"{"msg":"u***@gmail.com is already subscribed."}"#);
+    }
+}
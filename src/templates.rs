@@ -0,0 +1,25 @@
+//! Server-rendered pages are askama templates living in `templates/`.
+//! They all extend `base.html`, which takes care of the document skeleton
+//! and of displaying flash messages. Every interpolated value is HTML-escaped
+//! unless explicitly marked as `safe`.
+use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+
+use crate::utility::e500;
+
+/// Render a template into a `200 OK` HTML response.
+pub fn render_html(template: &impl Template) -> Result<HttpResponse, actix_web::Error> {
+    let body = template.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Collect the content of the incoming flash messages, as expected by `base.html`.
+pub fn flash_messages(flash_messages: &IncomingFlashMessages) -> Vec<String> {
+    flash_messages
+        .iter()
+        .map(|m| m.content().to_owned())
+        .collect()
+}
@@ -0,0 +1,358 @@
+//! Opens and clicks of issues published with tracking on.
+//!
+//! Each delivery gets a token, and each of its links another one. Links to
+//! other sites are rewritten to go through `/t/c/<link token>`, and a pixel
+//! loaded from `/t/o/<token>.gif` tells us the email was opened.
+use std::sync::LazyLock;
+
+use anyhow::Context;
+use regex::{Captures, Regex};
+use sqlx::{Executor, PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::configuration::TrackingSettings;
+use crate::domain::SubcriptionToken;
+
+/// Links as written by the sanitiser. Those with placeholders lead to
+/// the subscriber's own pages, they are left alone.
+static LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"href="(https?://[^"{}]+)""#).unwrap());
+
+/// The addresses `html_content` links to, each once, in the order they appear.
+/// As written in the HTML: `&` is `&amp;`.
+fn links(html_content: &str) -> Vec<&str> {
+    let mut links = Vec::new();
+    for link in LINK.captures_iter(html_content) {
+        let link = link.get(1).unwrap().as_str();
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+fn unescape(url: &str) -> String {
+    url.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Track the opens and clicks of an issue, numbering its links.
+#[tracing::instrument(skip(transaction, html_content))]
+pub async fn track_issue(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+    html_content: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        "UPDATE newsletter_issues SET tracked = true WHERE newsletter_issue_id = $1",
+        newsletter_issue_id
+    );
+    transaction.execute(query).await?;
+    for (i, link) in links(html_content).into_iter().enumerate() {
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO issue_links (newsletter_issue_id, link_number, url)
+            VALUES ($1, $2, $3)
+            "#,
+            newsletter_issue_id,
+            i as i32 + 1,
+            unescape(link)
+        );
+        transaction.execute(query).await?;
+    }
+    Ok(())
+}
+
+/// The token of the delivery of an issue to `email`.
+async fn token_for(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    email: &str,
+) -> Result<String, sqlx::Error> {
+    let new_token = SubcriptionToken::generate();
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO tracking_tokens (token, newsletter_issue_id, subscriber_email)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (newsletter_issue_id, subscriber_email)
+        DO UPDATE SET subscriber_email = EXCLUDED.subscriber_email
+        RETURNING token
+        "#,
+        new_token.as_ref(),
+        newsletter_issue_id,
+        email
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// The tokens of the first `n_links` links of the delivery `delivery_token`
+/// belongs to, in the order of the links.
+async fn link_tokens_for(
+    executor: impl PgExecutor<'_>,
+    delivery_token: &str,
+    n_links: usize,
+) -> Result<Vec<String>, sqlx::Error> {
+    let new_tokens: Vec<String> = (0..n_links)
+        .map(|_| SubcriptionToken::generate().as_ref().to_owned())
+        .collect();
+    let mut tokens = sqlx::query!(
+        r#"
+        INSERT INTO tracking_link_tokens (token, delivery_token, link_number)
+        SELECT token, $2, link_number::int
+        FROM UNNEST($1::text[]) WITH ORDINALITY AS l(token, link_number)
+        ON CONFLICT (delivery_token, link_number)
+        DO UPDATE SET delivery_token = EXCLUDED.delivery_token
+        RETURNING link_number, token
+        "#,
+        &new_tokens,
+        delivery_token
+    )
+    .fetch_all(executor)
+    .await?;
+    tokens.sort_by_key(|token| token.link_number);
+    Ok(tokens.into_iter().map(|token| token.token).collect())
+}
+
+/// Rewrite the links of `html_content`, as delivered to `email`, and add
+/// the pixel at its end.
+#[tracing::instrument(skip(pool, base_url, html_content))]
+pub async fn instrument(
+    pool: &PgPool,
+    base_url: &str,
+    newsletter_issue_id: Uuid,
+    email: &str,
+    html_content: &str,
+) -> Result<String, anyhow::Error> {
+    let token = token_for(pool, newsletter_issue_id, email)
+        .await
+        .context("Failed to store a tracking token.")?;
+    let links = links(html_content);
+    let link_tokens = link_tokens_for(pool, &token, links.len())
+        .await
+        .context("Failed to store the tracking tokens of links.")?;
+    let mut html_content = LINK
+        .replace_all(html_content, |link: &Captures| {
+            let i = links
+                .iter()
+                .position(|l| *l == &link[1])
+                .unwrap_or_default();
+            format!("href=\"{}/t/c/{}\"", base_url, link_tokens[i])
+        })
+        .into_owned();
+    html_content.push_str(&format!(
+        "<img src=\"{}/t/o/{}.gif\" width=\"1\" height=\"1\" alt=\"\">",
+        base_url, token
+    ));
+    Ok(html_content)
+}
+
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+async fn delivery_of(pool: &PgPool, token: &str) -> Result<Option<Delivery>, anyhow::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email
+        FROM tracking_tokens
+        WHERE token = $1
+        "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a tracking token.")
+}
+
+async fn insert_event(
+    pool: &PgPool,
+    delivery: &Delivery,
+    link_number: Option<i32>,
+) -> Result<(), anyhow::Error> {
+    let kind = if link_number.is_some() {
+        "click"
+    } else {
+        "open"
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (newsletter_issue_id, subscriber_email, kind, link_number)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        delivery.newsletter_issue_id,
+        delivery.subscriber_email,
+        kind,
+        link_number
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a tracking event.")?;
+    Ok(())
+}
+
+/// Record that the delivery `token` belongs to was opened.
+#[tracing::instrument(skip_all)]
+pub async fn record_open(
+    pool: &PgPool,
+    settings: &TrackingSettings,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    if !settings.enabled {
+        return Ok(());
+    }
+    if let Some(delivery) = delivery_of(pool, token).await? {
+        insert_event(pool, &delivery, None).await?;
+    }
+    Ok(())
+}
+
+/// Record a click on the link `token` was given to.
+/// Returns where the link leads, `None` when the token is unknown.
+#[tracing::instrument(skip_all)]
+pub async fn record_click(
+    pool: &PgPool,
+    settings: &TrackingSettings,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let Some(link) = sqlx::query!(
+        r#"
+        SELECT d.newsletter_issue_id, d.subscriber_email, l.link_number, i.url
+        FROM tracking_link_tokens l
+        JOIN tracking_tokens d ON d.token = l.delivery_token
+        JOIN issue_links i
+            ON i.newsletter_issue_id = d.newsletter_issue_id
+            AND i.link_number = l.link_number
+        WHERE l.token = $1
+        "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a link.")?
+    else {
+        return Ok(None);
+    };
+    // Links keep working once tracking is off
+    if settings.enabled {
+        let delivery = Delivery {
+            newsletter_issue_id: link.newsletter_issue_id,
+            subscriber_email: link.subscriber_email,
+        };
+        insert_event(pool, &delivery, Some(link.link_number)).await?;
+    }
+    Ok(Some(link.url))
+}
+
+/// How many of those an issue was sent to opened it, and clicked its links.
+pub struct Engagement {
+    pub sent: i64,
+    /// Clicking a link means the issue was opened, even if its images
+    /// were not loaded
+    pub opened: i64,
+    pub clicked: i64,
+    pub links: Vec<LinkClicks>,
+}
+
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    /// How many subscribers clicked it
+    pub clicked: i64,
+}
+
+impl Engagement {
+    pub fn open_rate(&self) -> String {
+        rate(self.opened, self.sent)
+    }
+
+    pub fn click_rate(&self) -> String {
+        rate(self.clicked, self.sent)
+    }
+}
+
+fn rate(n: i64, sent: i64) -> String {
+    if sent == 0 {
+        return "-".into();
+    }
+    format!("{:.1}%", 100.0 * n as f64 / sent as f64)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn engagement(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Engagement, anyhow::Error> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            (
+                SELECT COUNT(*) FROM newsletter_deliveries
+                WHERE newsletter_issue_id = $1 AND outcome = 'sent'
+            ) AS "sent!",
+            COUNT(DISTINCT subscriber_email) AS "opened!",
+            COUNT(DISTINCT subscriber_email) FILTER (WHERE kind = 'click') AS "clicked!"
+        FROM tracking_events
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the opens and clicks of an issue.")?;
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            l.url,
+            COUNT(e.subscriber_email) AS "clicks!",
+            COUNT(DISTINCT e.subscriber_email) AS "clicked!"
+        FROM issue_links l
+        LEFT JOIN tracking_events e
+            ON e.newsletter_issue_id = l.newsletter_issue_id
+            AND e.link_number = l.link_number
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.link_number, l.url
+        ORDER BY l.link_number
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the clicks of each link of an issue.")?;
+    Ok(Engagement {
+        sent: totals.sent,
+        opened: totals.opened,
+        clicked: totals.clicked,
+        links,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{links, rate, unescape};
+
+    #[test]
+    fn links_to_other_sites_are_tracked_once() {
+        let html = "<a href=\"https://example.com/a?x=1&amp;y=2\">A</a>\
+            <a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>\
+            <a href=\"mailto:hi@example.com\">Write</a>\
+            <a href=\"http://example.com/b\">B</a>\
+            <a href=\"https://example.com/a?x=1&amp;y=2\">A again</a>";
+        assert_eq!(
+            links(html),
+            vec!["https://example.com/a?x=1&amp;y=2", "http://example.com/b"]
+        );
+        assert_eq!(unescape(links(html)[0]), "https://example.com/a?x=1&y=2");
+    }
+
+    #[test]
+    fn rates_are_percentages_of_the_issues_sent() {
+        assert_eq!(rate(1, 3), "33.3%");
+        assert_eq!(rate(0, 0), "-");
+    }
+}
@@ -34,3 +34,40 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// `search` as the literal part of a `LIKE` pattern: its `%`, `_` and `\`
+/// match themselves, with `ESCAPE '\'`.
+pub fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// `value` as a CSV cell that spreadsheets show as is: one that would be
+/// read as a formula gets a leading `'`.
+pub fn spreadsheet_safe(value: String) -> String {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_like, spreadsheet_safe};
+
+    #[test]
+    fn wildcards_are_escaped_in_like_patterns() {
+        assert_eq!(escape_like(r"50%_off\"), r"50\%\_off\\");
+        assert_eq!(escape_like("ursula"), "ursula");
+    }
+
+    #[test]
+    fn formulas_are_neutralised_in_csv_cells() {
+        assert_eq!(spreadsheet_safe("=1+1".into()), "'=1+1");
+        assert_eq!(spreadsheet_safe("@SUM(A1)".into()), "'@SUM(A1)");
+        assert_eq!(spreadsheet_safe("Ursula Le Guin".into()), "Ursula Le Guin");
+    }
+}
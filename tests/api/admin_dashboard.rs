@@ -37,3 +37,25 @@ async fn logout_clears_session_state() {
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn usernames_are_html_escaped_on_the_admin_dashboard() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    sqlx::query!(
+        "UPDATE users SET username = $1 WHERE user_id = $2",
+        "<script>alert('pwned')</script>",
+        app.test_user.user_id
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(!html_page.contains("<script>alert"));
+    assert!(html_page.contains("Welcome &#60;script&#62;alert(&#39;pwned&#39;)&#60;/script&#62;!"));
+}
@@ -0,0 +1,496 @@
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber,
+    last_email, spawn_app, when_sending_an_email,
+};
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, list_id)
+        SELECT $1, $2, $3, now(), $4, list_id
+        FROM lists WHERE slug = 'newsletter'
+        "#,
+        id,
+        email,
+        name,
+        status
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+    id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+
+    // Act
+    let list = app.get_admin_subscribers("").await;
+    let detail = app.get_admin_page(&format!("subscribers/{}", id)).await;
+
+    // Assert
+    assert_is_redirect_to(&list, "/login");
+    assert_is_redirect_to(&detail, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia", "confirmed").await;
+    insert_subscriber(
+        &app,
+        "ursula.pending@example.com",
+        "Ursula",
+        "pending_confirmation",
+    )
+    .await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let search = app.get_admin_page_html("subscribers?search=URSULA").await;
+    let filtered = app
+        .get_admin_page_html("subscribers?search=ursula&status=confirmed")
+        .await;
+    let wildcard = app.get_admin_page_html("subscribers?search=%25").await;
+
+    // Assert
+    assert!(search.contains("2 subscriber(s) found."));
+    assert!(search.contains("ursula.pending@example.com"));
+    assert!(!search.contains("octavia@example.com"));
+    assert!(filtered.contains("1 subscriber(s) found."));
+    assert!(!filtered.contains("ursula.pending@example.com"));
+    assert!(wildcard.contains("No subscribers found."));
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_and_sorted_by_subscription_date() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..30 {
+        let id = insert_subscriber(
+            &app,
+            &format!("reader{:02}@example.com", i),
+            "Reader",
+            "confirmed",
+        )
+        .await;
+        sqlx::query!(
+            "UPDATE subscriptions SET subscribed_at = now() - make_interval(days => $1) WHERE id = $2",
+            30 - i,
+            id
+        )
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+    }
+    app.test_user.login(&app).await;
+
+    // Act
+    let newest_first = app.get_admin_page_html("subscribers").await;
+    let oldest_first = app.get_admin_page_html("subscribers?sort=oldest").await;
+    let second_page = app
+        .get_admin_page_html("subscribers?sort=oldest&page=2")
+        .await;
+
+    // Assert
+    assert!(newest_first.contains("reader29@example.com"));
+    assert!(!newest_first.contains("reader00@example.com"));
+    assert!(newest_first.contains("Next &gt;"));
+    assert!(oldest_first.contains("reader00@example.com"));
+    assert!(!oldest_first.contains("reader29@example.com"));
+    assert!(second_page.contains("reader29@example.com"));
+    assert!(!second_page.contains("reader00@example.com"));
+    assert!(second_page.contains("&lt; Previous"));
+    assert!(!second_page.contains("Next &gt;"));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["?status=gone", "?page=0", "?sort=sideways"] {
+        // Act
+        let response = app.get_admin_subscribers(query).await;
+
+        // Assert
+        assert_eq!(400, response.status().as_u16(), "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_the_confirmation_history_and_the_issues_sent() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .email;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Our first issue",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let id = subscriber_id(&app, &email).await;
+
+    // Act
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", id))
+        .await;
+
+    // Assert
+    assert!(html_page.contains(r#"<span id="status">confirmed</span>"#));
+    let subscribed = html_page.find("<td>Subscribed</td>").unwrap();
+    let sent = html_page.find("<td>Confirmation email sent</td>").unwrap();
+    let confirmed = html_page.find("<td>Confirmed</td>").unwrap();
+    assert!(subscribed < sent && sent < confirmed);
+    assert!(html_page.contains("<td>Our first issue</td>"));
+    assert!(html_page.contains("<td>sent</td>"));
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_stop_receiving_newsletters() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Unsubscribe
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    let response = app
+        .post_admin_form(&format!("subscribers/{}/unsubscribe", id), &body)
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", id))
+        .await;
+    assert!(html_page.contains("The subscriber has been unsubscribed."));
+    assert!(html_page.contains(r#"<span id="status">unsubscribed</span>"#));
+    assert!(html_page.contains("<td>Unsubscribed</td>"));
+
+    // Act - Part 2 - Publish
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn a_new_confirmation_email_can_be_sent_to_pending_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = create_unconfirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    let response = app
+        .post_admin_form(&format!("subscribers/{}/resend_confirmation", id), &body)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    let email_request = last_email(&app).await;
+    let new_links = app.get_confirmation_links(&email_request);
+    assert_ne!(first_links.html, new_links.html);
+    reqwest::get(new_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", id))
+        .await;
+    assert!(html_page.contains("A new confirmation email has been sent."));
+    assert_eq!(
+        html_page
+            .matches("<td>Confirmation email sent</td>")
+            .count(),
+        2
+    );
+    assert!(html_page.contains(r#"<span id="status">confirmed</span>"#));
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_not_sent_another_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    app.post_admin_form(&format!("subscribers/{}/resend_confirmation", id), &body)
+        .await;
+
+    // Assert
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", id))
+        .await;
+    assert!(
+        html_page
+            .contains("Only subscribers pending confirmation can be sent a confirmation email.")
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    let response = app
+        .post_admin_form(&format!("subscribers/{}/delete", id), &body)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_admin_subscribers("").await.text().await.unwrap();
+    assert!(html_page.contains("The subscriber has been deleted."));
+    assert!(!html_page.contains("ursula@example.com"));
+    let response = app.get_admin_page(&format!("subscribers/{}", id)).await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn actions_require_a_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_admin_form(
+            &format!("subscribers/{}/delete", id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    subscriber_id(&app, "ursula@example.com").await;
+}
+
+async fn set_subscribed_at(app: &TestApp, id: Uuid, subscribed_at: &str) {
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = $1::text::timestamptz WHERE id = $2",
+        subscribed_at,
+        id
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_page("subscribers/export").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_by_default() {
+    // Arrange
+    let app = spawn_app().await;
+    let ursula =
+        insert_subscriber(&app, "ursula@example.com", "Le Guin, Ursula", "confirmed").await;
+    let octavia = insert_subscriber(&app, "octavia@example.com", "Octavia", "confirmed").await;
+    set_subscribed_at(&app, ursula, "2025-01-01T10:00:00Z").await;
+    set_subscribed_at(&app, octavia, "2025-02-01T10:00:00Z").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_admin_page("subscribers/export").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(
+        response.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .starts_with("attachment")
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            "id,email,name,list,status,subscribed_at,confirmed_at,unsubscribed_at".to_string(),
+            format!(
+                "{},ursula@example.com,\"Le Guin, Ursula\",newsletter,confirmed,2025-01-01T10:00:00Z,,",
+                ursula
+            ),
+            format!(
+                "{},octavia@example.com,Octavia,newsletter,confirmed,2025-02-01T10:00:00Z,,",
+                octavia
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn names_are_not_exported_as_spreadsheet_formulas() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "=HYPERLINK(\"x\")", "confirmed").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let csv = app
+        .get_admin_page("subscribers/export")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let json: serde_json::Value = app
+        .get_admin_page("subscribers/export?format=json")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(csv.contains(r#",ursula@example.com,"'=HYPERLINK(""x"")","#));
+    assert_eq!(json[0]["name"], "=HYPERLINK(\"x\")");
+}
+
+#[tokio::test]
+async fn exports_can_be_filtered_by_status_and_subscription_date() {
+    // Arrange
+    let app = spawn_app().await;
+    for (email, status, subscribed_at) in [
+        ("december@example.com", "confirmed", "2024-12-31T23:59:59Z"),
+        ("january@example.com", "confirmed", "2025-01-15T10:00:00Z"),
+        (
+            "pending@example.com",
+            "pending_confirmation",
+            "2025-01-15T10:00:00Z",
+        ),
+        (
+            "end-of-january@example.com",
+            "confirmed",
+            "2025-01-31T23:59:59Z",
+        ),
+        ("february@example.com", "confirmed", "2025-02-01T00:00:00Z"),
+    ] {
+        let id = insert_subscriber(&app, email, "Reader", status).await;
+        set_subscribed_at(&app, id, subscribed_at).await;
+    }
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_admin_page(
+            "subscribers/export?format=json&status=confirmed&from=2025-01-01&to=2025-01-31",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    let body: Vec<serde_json::Value> = response.json().await.unwrap();
+    let emails: Vec<&str> = body.iter().map(|s| s["email"].as_str().unwrap()).collect();
+    assert_eq!(
+        emails,
+        vec!["january@example.com", "end-of-january@example.com"]
+    );
+    assert_eq!(body[0]["status"], "confirmed");
+    assert!(body[0]["confirmed_at"].is_null());
+}
+
+#[tokio::test]
+async fn empty_exports_are_still_valid_json() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_admin_page("subscribers/export?format=json").await;
+
+    // Assert
+    let body: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn invalid_export_filters_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    for query in ["?format=xml", "?status=gone", "?from=01/02/2025"] {
+        // Act
+        let response = app
+            .get_admin_page(&format!("subscribers/export{}", query))
+            .await;
+
+        // Assert
+        assert_eq!(400, response.status().as_u16(), "{}", query);
+    }
+}
@@ -0,0 +1,282 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, spawn_app, spawn_app_with_api_key, when_sending_an_email,
+};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+async fn get_issue(app: &TestApp, api_key: &str, issue_id: &str) -> serde_json::Value {
+    let response = app
+        .api_v1(Method::GET, &format!("newsletters/{}", issue_id), api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn published_issues_report_their_delivery_progress() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_id = issue["id"].as_str().unwrap();
+    assert_eq!(issue["title"], "Newsletter title");
+    assert_eq!(issue["status"], "in_progress");
+    assert_eq!(issue["recipients"], 2);
+    assert_eq!(issue["pending"], 2);
+    assert_eq!(issue["sent"], 0);
+
+    // Act - Part 2 - Deliver and poll
+    app.dispatch_all_pending_emails().await;
+    let issue = get_issue(&app, &api_key, issue_id).await;
+
+    // Assert
+    assert_eq!(issue["status"], "completed");
+    assert_eq!(issue["pending"], 0);
+    assert_eq!(issue["sent"], 2);
+    assert_eq!(issue["failed"], 0);
+    assert_eq!(issue["skipped"], 0);
+}
+
+#[tokio::test]
+async fn deliveries_that_failed_are_not_reported_as_sent() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    create_confirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .unwrap();
+    let issue: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = get_issue(&app, &api_key, issue["id"].as_str().unwrap()).await;
+    assert_eq!(issue["status"], "completed");
+    assert_eq!(issue["sent"], 0);
+    assert_eq!(issue["failed"], 1);
+}
+
+#[tokio::test]
+async fn retrying_with_the_same_idempotency_key_returns_the_saved_response() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    create_confirmed_subscriber(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let publish = || {
+        app.api_v1(Method::POST, "newsletters", &api_key)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&newsletter_request_body())
+            .send()
+    };
+
+    // Act
+    let first_response = publish().await.unwrap();
+    assert_eq!(202, first_response.status().as_u16());
+    let first_location = first_response.headers()["Location"].clone();
+    let first_body = first_response.text().await.unwrap();
+    let second_response = publish().await.unwrap();
+
+    // Assert
+    assert_eq!(202, second_response.status().as_u16());
+    assert_eq!(second_response.headers()["Location"], first_location);
+    assert_eq!(
+        second_response.headers()["Content-Type"],
+        "application/json"
+    );
+    assert_eq!(second_response.text().await.unwrap(), first_body);
+    let n_issues = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(1, n_issues);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn publishing_requires_a_valid_idempotency_key_header() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let test_cases = vec![
+        (None, "missing header"),
+        (Some(String::new()), "empty key"),
+        (Some("a".repeat(51)), "key too long"),
+    ];
+
+    for (idempotency_key, description) in test_cases {
+        let mut request = app
+            .api_v1(Method::POST, "newsletters", &api_key)
+            .json(&newsletter_request_body());
+        if let Some(key) = idempotency_key {
+            request = request.header("Idempotency-Key", key);
+        }
+
+        // Act
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(400, response.status().as_u16(), "{}", description);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn publishing_rejects_invalid_json_bodies() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({"title": "Newsletter title"}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "validation_error");
+}
+
+#[tokio::test]
+async fn publishing_requires_an_api_key() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", "")
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unknown_issues_return_404() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(
+            Method::GET,
+            &format!("newsletters/{}", Uuid::new_v4()),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issues_published_from_the_admin_area_are_listed_too() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let mut body = newsletter_request_body();
+    body["idempotency_key"] = Uuid::new_v4().to_string().into();
+    app.post_newsletters(&body).await;
+    app.api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&newsletter_request_body())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_v1(Method::GET, "newsletters", &api_key)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let page: serde_json::Value = response.json().await.unwrap();
+    let issues = page["data"].as_array().unwrap();
+    assert_eq!(issues.len(), 2);
+    assert!(issues.iter().all(|i| i["status"] == "completed"));
+}
+
+#[tokio::test]
+async fn html_published_through_the_api_is_sanitised() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let mut body = newsletter_request_body();
+    body["html_content"] = "<script>alert(1)</script>\
+        <p onclick=\"alert(1)\">Hi <a href=\"javascript:alert(1)\">there</a></p>"
+        .into();
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let html_content = sqlx::query_scalar!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert!(html_content.contains("<p>Hi <a>there</a></p>"));
+    assert!(!html_content.contains("script"));
+}
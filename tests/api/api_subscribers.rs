@@ -0,0 +1,350 @@
+use reqwest::Method;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+    spawn_app_with_api_key, when_sending_an_email,
+};
+
+fn assert_json_error(body: &serde_json::Value, code: &str) {
+    assert_eq!(
+        body["error"]["code"], code,
+        "Unexpected error body: {}",
+        body
+    );
+    assert!(body["error"]["message"].is_string());
+}
+
+/// `api_key` with the last character of its secret changed.
+fn with_wrong_secret(api_key: &str) -> String {
+    let wrong_last = if api_key.ends_with('x') { 'y' } else { 'x' };
+    format!("{}{}", &api_key[..api_key.len() - 1], wrong_last)
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_api_key_are_rejected() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let wrong_key = with_wrong_secret(&api_key);
+
+    for (key, description) in [
+        ("", "empty"),
+        ("not-a-key", "malformed"),
+        (wrong_key.as_str(), "wrong secret"),
+    ] {
+        // Act
+        let response = app
+            .api_v1(Method::GET, "subscribers", key)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(401, response.status().as_u16(), "{} key", description);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+        assert_json_error(&response.json().await.unwrap(), "unauthorized");
+    }
+}
+
+#[tokio::test]
+async fn only_valid_keys_are_recorded_as_used() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let wrong_key = with_wrong_secret(&api_key);
+    let last_used_at = || async {
+        sqlx::query_scalar!("SELECT last_used_at FROM api_keys")
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap()
+    };
+
+    // Act - Part 1 - Guess the secret
+    app.api_v1(Method::GET, "subscribers", &wrong_key)
+        .send()
+        .await
+        .unwrap();
+    assert!(last_used_at().await.is_none());
+
+    // Act - Part 2 - Use the key
+    app.api_v1(Method::GET, "subscribers", &api_key)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(last_used_at().await.is_some());
+}
+
+#[tokio::test]
+async fn revoked_api_keys_are_rejected() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let api_key_id = sqlx::query!("SELECT api_key_id FROM api_keys")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .api_key_id;
+
+    // Act
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    let response = app
+        .post_admin_form(&format!("api_keys/{}/revoke", api_key_id), &body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_keys");
+
+    // Assert
+    let response = app
+        .api_v1(Method::GET, "subscribers", &api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_keys_are_only_stored_as_hashes() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+
+    // Act
+    let record = sqlx::query!("SELECT prefix, key_hash FROM api_keys")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    let html_page = app.get_api_keys_html().await;
+
+    // Assert
+    assert!(api_key.contains(&record.prefix));
+    assert!(!record.key_hash.contains(&api_key));
+    assert!(html_page.contains(&record.prefix));
+    assert!(!html_page.contains(&api_key));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_keys() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_api_keys().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn create_subscriber_returns_201_and_sends_a_confirmation_email() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "subscribers", &api_key)
+        .json(&serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"}))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["name"], "le guin");
+    assert_eq!(body["status"], "pending_confirmation");
+
+    let response = app
+        .api_v1(
+            Method::GET,
+            &format!("subscribers/{}", body["id"].as_str().unwrap()),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let fetched: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(fetched, body);
+}
+
+#[tokio::test]
+async fn create_subscriber_rejects_invalid_payloads_with_a_json_error() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "le guin", "email": "definitely-not-an-email"}),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "empty name",
+        ),
+        (serde_json::json!({"name": "le guin"}), "missing email"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app
+            .api_v1(Method::POST, "subscribers", &api_key)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(400, response.status().as_u16(), "{}", description);
+        assert_json_error(&response.json().await.unwrap(), "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn creating_an_existing_subscriber_returns_409() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({"name": "le guin", "email": "ursula_le_guin@gmail.com"});
+    app.api_v1(Method::POST, "subscribers", &api_key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "subscribers", &api_key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    assert_json_error(&response.json().await.unwrap(), "conflict");
+}
+
+#[tokio::test]
+async fn list_subscribers_supports_search_filters_and_pagination() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    let get = |query: &'static str| {
+        let request = app.api_v1(Method::GET, &format!("subscribers?{}", query), &api_key);
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(200, response.status().as_u16());
+            response.json::<serde_json::Value>().await.unwrap()
+        }
+    };
+
+    // Act
+    let everyone = get("").await;
+    let confirmed = get("status=confirmed").await;
+    let second_page = get("limit=2&offset=2").await;
+
+    // Assert
+    assert_eq!(everyone["total"], 3);
+    assert_eq!(everyone["data"].as_array().unwrap().len(), 3);
+    assert_eq!(confirmed["total"], 2);
+    assert!(
+        confirmed["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|s| s["status"] == "confirmed")
+    );
+    assert_eq!(second_page["total"], 3);
+    assert_eq!(second_page["data"].as_array().unwrap().len(), 1);
+
+    let email = everyone["data"][0]["email"].as_str().unwrap();
+    let response = app
+        .api_v1(Method::GET, "subscribers", &api_key)
+        .query(&[("search", email.to_uppercase())])
+        .send()
+        .await
+        .unwrap();
+    let found: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(found["total"], 1);
+    assert_eq!(found["data"][0]["email"], email);
+
+    // Wildcards match themselves only
+    let response = app
+        .api_v1(Method::GET, "subscribers", &api_key)
+        .query(&[("search", "%")])
+        .send()
+        .await
+        .unwrap();
+    let found: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(found["total"], 0);
+}
+
+#[tokio::test]
+async fn list_subscribers_rejects_invalid_parameters_with_a_json_error() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+
+    for query in [
+        "status=unknown",
+        "limit=0",
+        "limit=1000",
+        "offset=-1",
+        "limit=abc",
+    ] {
+        // Act
+        let response = app
+            .api_v1(Method::GET, &format!("subscribers?{}", query), &api_key)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(400, response.status().as_u16(), "{}", query);
+        assert_json_error(&response.json().await.unwrap(), "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_gone() {
+    // Arrange
+    let (app, api_key) = spawn_app_with_api_key().await;
+    create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id;
+    let path = format!("subscribers/{}", subscriber_id);
+
+    // Act
+    let response = app
+        .api_v1(Method::DELETE, &path, &api_key)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(204, response.status().as_u16());
+    let response = app
+        .api_v1(Method::GET, &path, &api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+    assert_json_error(&response.json().await.unwrap(), "not_found");
+    let response = app
+        .api_v1(Method::DELETE, &path, &api_key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,212 @@
+use reqwest::Url;
+use reqwest::multipart::Part;
+use uuid::Uuid;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, confirmed_subscriber, issue_received, spawn_logged_in_app,
+};
+
+fn file(file_name: &str, content_type: &str, content: &[u8]) -> Part {
+    Part::bytes(content.to_vec())
+        .file_name(file_name.to_owned())
+        .mime_str(content_type)
+        .unwrap()
+}
+
+async fn publish(app: &TestApp, html_content: &str, files: Vec<Part>) -> reqwest::Response {
+    let idempotency_key = Uuid::new_v4().to_string();
+    app.post_newsletters_with_attachments(
+        &[
+            ("title", "Newsletter title"),
+            (
+                "text_content",
+                "Newsletter body as plain text\n{{ web_view_url }}",
+            ),
+            ("html_content", html_content),
+            ("idempotency_key", &idempotency_key),
+        ],
+        files,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn files_are_attached_and_images_shown_inline() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let response = publish(
+        &app,
+        "<p><img src=\"cid:logo.png\" alt=\"Our logo\"></p>",
+        vec![
+            file("logo.png", "image/png", b"png"),
+            file("programme.pdf", "application/pdf", b"pdf"),
+        ],
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let body = issue_received(&app).await;
+    assert_eq!(
+        body["Attachments"],
+        serde_json::json!([
+            {
+                "Name": "logo.png",
+                "Content": "cG5n",
+                "ContentType": "image/png",
+                "ContentID": "cid:logo.png",
+            },
+            {
+                "Name": "programme.pdf",
+                "Content": "cGRm",
+                "ContentType": "application/pdf",
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn every_recipient_gets_the_files() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    confirmed_subscriber(&app, "Octavia", "octavia@example.com").await;
+
+    // Act
+    publish(
+        &app,
+        "<p>Newsletter body as HTML</p>",
+        vec![file("programme.pdf", "application/pdf", b"pdf")],
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issues: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "Newsletter title")
+        .collect();
+    assert_eq!(issues.len(), 2);
+    for issue in issues {
+        assert_eq!(issue["Attachments"][0]["Content"], "cGRm");
+    }
+}
+
+#[tokio::test]
+async fn issues_without_attachments_can_still_be_sent_as_multipart_forms() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let response = publish(
+        &app,
+        "<p>Newsletter body as HTML</p>",
+        // What browsers send when no file was picked
+        vec![file("", "application/octet-stream", b"")],
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let body = issue_received(&app).await;
+    assert!(body.get("Attachments").is_none());
+}
+
+#[tokio::test]
+async fn files_that_could_run_cannot_be_attached() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act - Part 1 - Publish
+    let response = publish(
+        &app,
+        "<p>Newsletter body as HTML</p>",
+        vec![file("setup.exe", "application/octet-stream", b"MZ")],
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("`setup.exe` cannot be attached"));
+}
+
+#[tokio::test]
+async fn images_shown_must_be_attached() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    publish(
+        &app,
+        "<p><img src=\"cid:logo.png\" alt=\"Our logo\"></p>",
+        vec![],
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("no image attached is called `logo.png`"));
+}
+
+#[tokio::test]
+async fn web_views_show_inline_images() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    publish(
+        &app,
+        "<p><img src=\"cid:logo.png\" alt=\"Our logo\"></p>",
+        vec![file("logo.png", "image/png", b"png")],
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let body = issue_received(&app).await;
+    let web_view_link = body["TextBody"].as_str().unwrap().lines().nth(1).unwrap();
+    let mut web_view_link = Url::parse(web_view_link).unwrap();
+    web_view_link.set_port(Some(app.port)).unwrap();
+
+    // Act
+    let web_view = app
+        .api_client
+        .get(web_view_link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    let image_path = format!("{}/images/logo.png", web_view_link.path());
+    assert!(web_view.contains(&format!("src=\"{}\"", image_path)));
+    let image = app
+        .api_client
+        .get(web_view_link.join(&image_path).unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(image.status().as_u16(), 200);
+    assert_eq!(image.headers()["Content-Type"], "image/png");
+    assert_eq!(image.bytes().await.unwrap().as_ref(), b"png");
+}
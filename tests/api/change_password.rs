@@ -108,7 +108,9 @@ async fn new_password_must_be_long_enough() {
     // Act - Part 3 - Follow the redirect
     let html_page = app.get_change_password_html().await;
     assert!(
-        html_page.contains("<p><i>The new password is too short, should be 12 < p < 129.</i></p>")
+        html_page.contains(
+            "<p><i>The new password is too short, should be 12 &#60; p &#60; 129.</i></p>"
+        )
     );
 }
 
@@ -134,7 +136,9 @@ async fn new_password_must_be_short_enough() {
     // Act - Part 3 - Follow the redirect
     let html_page = app.get_change_password_html().await;
     assert!(
-        html_page.contains("<p><i>The new password is too long, should be 12 < p < 129.</i></p>")
+        html_page.contains(
+            "<p><i>The new password is too long, should be 12 &#60; p &#60; 129.</i></p>"
+        )
     );
 }
 
@@ -0,0 +1,139 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn admin_forms_embed_the_session_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.get_csrf_token().await.expect("No CSRF token found");
+    let hidden_field = format!(r#"name="csrf_token" value="{}""#, csrf_token);
+
+    // Act
+    let dashboard = app.get_admin_dashboard_html().await;
+    let newsletters = app.get_newsletters_html().await;
+    let password = app.get_change_password_html().await;
+
+    // Assert
+    assert!(dashboard.contains(&hidden_field));
+    assert!(newsletters.contains(&hidden_field));
+    assert!(password.contains(&hidden_field));
+}
+
+#[tokio::test]
+async fn posts_without_a_csrf_token_are_rejected_with_a_403() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let test_cases = vec![
+        (
+            "newsletters",
+            serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": Uuid::new_v4().to_string()
+            }),
+        ),
+        (
+            "password",
+            serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }),
+        ),
+        ("logout", serde_json::json!({})),
+    ];
+
+    for (path, body) in test_cases {
+        // Act
+        let response = app.post_admin_form(path, &body).await;
+
+        // Assert
+        assert_eq!(
+            403,
+            response.status().as_u16(),
+            "The API did not fail with 403 Forbidden when posting to /admin/{} without a CSRF token.",
+            path
+        );
+    }
+    // We are still logged in
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(200, response.status().as_u16());
+    let n_issues = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, n_issues);
+}
+
+#[tokio::test]
+async fn posts_with_an_invalid_csrf_token_are_rejected_with_a_403() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = serde_json::json!({ "csrf_token": "not-the-right-token" });
+    let response = app.post_admin_form("logout", &body).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn csrf_tokens_are_bound_to_a_single_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_app = spawn_app().await;
+    other_app.test_user.login(&other_app).await;
+    let foreign_token = other_app.get_csrf_token().await.unwrap();
+
+    // Act
+    let body = serde_json::json!({ "csrf_token": foreign_token });
+    let response = app.post_admin_form("logout", &body).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn posts_with_the_csrf_token_in_a_header_are_accepted() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let csrf_token = app.get_csrf_token().await.unwrap();
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", csrf_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logging_in_again_issues_a_new_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let first_token = app.get_csrf_token().await.unwrap();
+    app.post_logout().await;
+
+    // Act
+    app.test_user.login(&app).await;
+    let second_token = app.get_csrf_token().await.unwrap();
+
+    // Assert
+    assert_ne!(first_token, second_token);
+}
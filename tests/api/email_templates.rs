@@ -0,0 +1,223 @@
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, assert_is_redirect_to, last_email, spawn_app, spawn_logged_in_app};
+
+async fn subscribe(app: &TestApp) {
+    app.post_subscriptions("name=Ursula&email=ursula%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn post_template(
+    app: &TestApp,
+    name: &str,
+    template: &serde_json::Value,
+) -> reqwest::Response {
+    let body = app.with_csrf_token(template).await;
+    app.post_admin_form(&format!("email_templates/{}", name), &body)
+        .await
+}
+
+fn confirmation_template(action: &str) -> serde_json::Value {
+    serde_json::json!({
+        "subject": "Hello {{ name }}",
+        "html_body": "<p>Confirm <a href=\"{{ confirmation_link }}\">here</a>.</p>",
+        "text_body": "Confirm at {{ confirmation_link }}",
+        "action": action,
+    })
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_email_templates() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_page("email_templates/confirmation").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_default_template() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    subscribe(&app).await;
+
+    // Assert
+    let email: serde_json::Value = last_email(&app).await.body_json().unwrap();
+    assert_eq!(email["Subject"], "Welcome to Newsletter!");
+    assert!(
+        email["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Hi Ursula,\n\nWelcome to Newsletter!")
+    );
+}
+
+#[tokio::test]
+async fn edited_templates_are_used_for_the_next_emails() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    let response = post_template(&app, "confirmation", &confirmation_template("save")).await;
+    subscribe(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/email_templates/confirmation");
+    let email: serde_json::Value = last_email(&app).await.body_json().unwrap();
+    assert_eq!(email["Subject"], "Hello Ursula");
+    assert!(
+        email["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Confirm at http://127.0.0.1")
+    );
+    let html_page = app.get_admin_page_html("email_templates").await;
+    assert_eq!(html_page.matches("Default").count(), 3);
+}
+
+#[tokio::test]
+async fn templates_with_unknown_placeholders_are_not_saved() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let mut template = confirmation_template("save");
+    template["text_body"] = "Confirm at {{ link }}".into();
+
+    // Act
+    let response = post_template(&app, "confirmation", &template).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("In the text body: There is no `link` variable"));
+    assert!(html_page.contains("Confirm at {{ link }}"));
+    subscribe(&app).await;
+    let email: serde_json::Value = last_email(&app).await.body_json().unwrap();
+    assert_eq!(email["Subject"], "Welcome to Newsletter!");
+}
+
+#[tokio::test]
+async fn previews_use_made_up_values_and_are_not_saved() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    let response = post_template(&app, "confirmation", &confirmation_template("preview")).await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<b>Hello Ursula</b>"));
+    assert!(html_page.contains(
+        "Confirm at https://example.com/subscriptions/confirm?subscription_token=example"
+    ));
+    assert!(html_page.contains("This preview has not been saved yet."));
+    subscribe(&app).await;
+    let email: serde_json::Value = last_email(&app).await.body_json().unwrap();
+    assert_eq!(email["Subject"], "Welcome to Newsletter!");
+}
+
+#[tokio::test]
+async fn templates_can_be_reset_to_their_default() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    post_template(&app, "confirmation", &confirmation_template("save")).await;
+
+    // Act
+    let response = post_template(&app, "confirmation", &confirmation_template("reset")).await;
+    subscribe(&app).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/email_templates/confirmation");
+    let email: serde_json::Value = last_email(&app).await.body_json().unwrap();
+    assert_eq!(email["Subject"], "Welcome to Newsletter!");
+}
+
+#[tokio::test]
+async fn issues_written_in_markdown_use_the_edited_layout() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    post_template(
+        &app,
+        "newsletter_layout",
+        &serde_json::json!({
+            "html_body": "<header>Our newsletter</header>{{content}}",
+            "text_body": "OUR NEWSLETTER\n\n{{ content }}",
+            "action": "save",
+        }),
+    )
+    .await;
+
+    // Act
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "mode": "markdown",
+        "markdown_content": "Hi *{{ name }}*",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.html_content,
+        "<header>Our newsletter</header><p>Hi <em>{{ name }}</em></p>\n"
+    );
+    assert_eq!(issue.text_content, "OUR NEWSLETTER\n\nHi {{ name }}");
+}
+
+#[tokio::test]
+async fn issues_written_in_html_use_the_edited_layout_too() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    post_template(
+        &app,
+        "newsletter_layout",
+        &serde_json::json!({
+            "html_body": "<header>Our newsletter</header>{{content}}",
+            "text_body": "OUR NEWSLETTER\n\n{{ content }}",
+            "action": "save",
+        }),
+    )
+    .await;
+
+    // Act
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name }}",
+        "html_content": "<p>Hi {{ name }}</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT text_content, html_content FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        issue.html_content,
+        "<header>Our newsletter</header><p>Hi {{ name }}</p>"
+    );
+    assert_eq!(issue.text_content, "OUR NEWSLETTER\n\nHi {{ name }}");
+}
+
+#[tokio::test]
+async fn unknown_templates_are_not_found() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    let response = app.get_admin_page("email_templates/password_reset").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
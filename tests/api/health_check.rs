@@ -1,4 +1,9 @@
-use crate::helpers::spawn_app;
+use std::time::Duration;
+
+use wiremock::matchers::method;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -19,3 +24,107 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_health(address: &str, probe: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/health_check/{}", address, probe))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn liveness_check_returns_200() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_health(&app.address, "live").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+}
+
+#[tokio::test]
+async fn readiness_check_reports_each_dependency() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_health(&app.address, "ready").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    for dependency in ["postgres", "redis"] {
+        assert_eq!(body["checks"][dependency]["status"], "ok");
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+    // The email provider is only checked when enabled
+    assert!(body["checks"].get("email_provider").is_none());
+}
+
+#[tokio::test]
+async fn readiness_check_fails_when_the_email_provider_is_down() {
+    // Arrange
+    let app = spawn_app_with(|c| c.health_check.check_email_provider = true).await;
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = get_health(&app.address, "ready").await;
+
+    // Assert
+    assert_eq!(503, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"]["email_provider"]["status"], "unavailable");
+    assert!(body["checks"]["email_provider"]["error"].is_string());
+    assert_eq!(body["checks"]["postgres"]["status"], "ok");
+}
+
+#[tokio::test]
+async fn readiness_check_times_out_on_slow_dependencies() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.health_check.check_email_provider = true;
+        c.health_check.timeout_milliseconds = 100;
+    })
+    .await;
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = get_health(&app.address, "ready").await;
+
+    // Assert
+    assert_eq!(503, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "unavailable");
+}
+
+#[tokio::test]
+async fn readiness_check_accepts_client_errors_from_the_email_provider() {
+    // Arrange
+    let app = spawn_app_with(|c| c.health_check.check_email_provider = true).await;
+    Mock::given(method("HEAD"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = get_health(&app.address, "ready").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["email_provider"]["status"], "ok");
+}
@@ -8,7 +8,9 @@ use reqwest::Url;
 use secrecy::SecretString;
 use wiremock::{MockBuilder, MockServer};
 use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{ExecutionOutcome, try_execute_task};
+use zero2prod::import_confirmations;
+use zero2prod::issue_delivery_worker::{ExecutionOutcome, IssueCache, try_execute_task};
+use zero2prod::personal_data;
 use zero2prod::startup::{Application, get_connection_pool};
 
 use once_cell::sync::Lazy;
@@ -16,24 +18,26 @@ use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
-use zero2prod::configuration::DatabaseSettings;
+use zero2prod::configuration::{DatabaseSettings, Settings, TrackingSettings};
 use zero2prod::telemetry;
 
 // Ensure that the `tracing` stack is only initialised once using `once_cell`
 static TRACING: Lazy<()> = Lazy::new(|| {
-    let default_filter_level = "info".to_string();
+    let settings = zero2prod::configuration::get_configuration()
+        .expect("Failed to read config")
+        .telemetry;
     let subscriber_name = "test".to_string();
     // We cannot assign the output of `get_subscriber` to a variable based on the
     // value TEST_LOG` because the sink is part of the type returned by
     // `get_subscriber`, therefore they are not the same type. We could work around
     // it, but this is the most straight-forward way of moving forward.
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = telemetry::get_subscriber(subscriber_name, &settings, std::io::stdout)
+            .expect("Failed to build the subscriber");
         telemetry::init_subscriber(subscriber);
     } else {
-        let subscriber =
-            telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = telemetry::get_subscriber(subscriber_name, &settings, std::io::sink)
+            .expect("Failed to build the subscriber");
         telemetry::init_subscriber(subscriber);
     }
 });
@@ -46,6 +50,10 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    /// What links in the emails we send start with
+    pub base_url: String,
+    /// What the delivery worker is run with
+    pub tracking: TrackingSettings,
 }
 
 pub struct TestUser {
@@ -135,9 +143,39 @@ impl TestApp {
         Body: serde::Serialize,
     {
         let endpoint = format!("{}/admin/newsletters", &self.address);
+        let mut body = self.with_csrf_token(body).await;
+        if body.get("lists").is_none() {
+            body["lists"] = serde_json::json!(["newsletter"]);
+        }
+        // Lists are sent as repeated fields, which `.form()` cannot encode
         self.api_client
             .post(endpoint)
-            .form(&body)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(serde_html_form::to_string(&body).unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Publish an issue with files attached, as a multipart form.
+    pub async fn post_newsletters_with_attachments(
+        &self,
+        fields: &[(&str, &str)],
+        files: Vec<reqwest::multipart::Part>,
+    ) -> reqwest::Response {
+        let mut form = reqwest::multipart::Form::new().text("lists", "newsletter");
+        for (name, value) in fields {
+            form = form.text(name.to_string(), value.to_string());
+        }
+        for file in files {
+            form = form.part("attachments", file);
+        }
+        if let Some(csrf_token) = self.get_csrf_token().await {
+            form = form.text("csrf_token", csrf_token);
+        }
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .multipart(form)
             .send()
             .await
             .expect("Failed to execute request")
@@ -175,6 +213,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         let endpoint = format!("{}/admin/password", &self.address);
+        let body = self.with_csrf_token(body).await;
         self.api_client
             .post(endpoint)
             // This `reqwest` method makes sure that the body is URL-encoded
@@ -219,15 +258,146 @@ impl TestApp {
             .expect("Could not get the response as text")
     }
 
+    pub async fn get_api_keys(&self) -> reqwest::Response {
+        let endpoint = format!("{}/admin/api_keys", &self.address);
+        self.api_client
+            .get(&endpoint)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.get_api_keys()
+            .await
+            .text()
+            .await
+            .expect("Could not get the response as text")
+    }
+
+    /// `query` is appended as is, e.g. `?status=confirmed`.
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.get_admin_page(&format!("subscribers{}", query)).await
+    }
+
+    pub async fn get_admin_page(&self, path: &str) -> reqwest::Response {
+        let endpoint = format!("{}/admin/{}", &self.address, path);
+        self.api_client
+            .get(&endpoint)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_page_html(&self, path: &str) -> String {
+        self.get_admin_page(path)
+            .await
+            .text()
+            .await
+            .expect("Could not get the response as text")
+    }
+
+    /// Upload a CSV of subscribers through the admin UI.
+    pub async fn post_subscriber_import(&self, csv: &str, mode: &str) -> reqwest::Response {
+        let mut form = reqwest::multipart::Form::new()
+            .part(
+                "csv",
+                reqwest::multipart::Part::text(csv.to_owned())
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap(),
+            )
+            .text("mode", mode.to_owned());
+        if let Some(csrf_token) = self.get_csrf_token().await {
+            form = form.text("csrf_token", csrf_token);
+        }
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         let endpoint = format!("{}/admin/logout", &self.address);
+        let body = self.with_csrf_token(&serde_json::json!({})).await;
         self.api_client
             .post(endpoint)
+            .form(&body)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    /// Create an API key through the admin UI and return it.
+    /// The test user must be logged in.
+    pub async fn create_api_key(&self) -> String {
+        let body = serde_json::json!({ "name": "Test key" });
+        let response = self
+            .post_admin_form("api_keys", &self.with_csrf_token(&body).await)
+            .await;
+        assert_eq!(200, response.status().as_u16());
+        let html_page = response.text().await.unwrap();
+        let marker = r#"<code id="apiKey">"#;
+        let start = html_page
+            .find(marker)
+            .expect("The API key is not displayed")
+            + marker.len();
+        let end = start + html_page[start..].find('<').unwrap();
+        html_page[start..end].to_owned()
+    }
+
+    /// Start building a request to the JSON API, authenticated with `api_key`.
+    pub fn api_v1(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        api_key: &str,
+    ) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/api/v1/{}", &self.address, path))
+            .bearer_auth(api_key)
+    }
+
+    /// Post a form to the admin area exactly as given, without adding
+    /// the session's CSRF token.
+    pub async fn post_admin_form<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let endpoint = format!("{}/admin/{}", &self.address, path);
+        self.api_client
+            .post(endpoint)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Extract the CSRF token embedded in the forms of the admin dashboard.
+    /// Returns `None` if we are not logged in.
+    pub async fn get_csrf_token(&self) -> Option<String> {
+        let html_page = self.get_admin_dashboard_html().await;
+        let marker = r#"name="csrf_token" value=""#;
+        let start = html_page.find(marker)? + marker.len();
+        let end = start + html_page[start..].find('"')?;
+        Some(html_page[start..end].to_owned())
+    }
+
+    /// Add the session's CSRF token to a form body, mimicking what a browser
+    /// submitting one of our forms would send.
+    pub async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        if let Some(csrf_token) = self.get_csrf_token().await {
+            body["csrf_token"] = csrf_token.into();
+        }
+        body
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
@@ -246,8 +416,8 @@ impl TestApp {
         // Parse the body as JSON, startign from raw bytes
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
-        let html_link = get_link(&body["HtmlBody"].as_str().unwrap());
-        let text_link = get_link(&body["TextBody"].as_str().unwrap());
+        let html_link = get_link(body["HtmlBody"].as_str().unwrap());
+        let text_link = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks {
             html: html_link,
@@ -255,21 +425,47 @@ impl TestApp {
         }
     }
 
+    /// Send the issues, the confirmation emails of imports and the
+    /// personal data links left for the worker.
     pub async fn dispatch_all_pending_emails(&self) {
+        let mut issues = IssueCache::default();
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.pg_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.pg_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.tracking,
+                &mut issues,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
+        while let ExecutionOutcome::TaskCompleted =
+            import_confirmations::try_send_next(&self.pg_pool, &self.email_client, &self.base_url)
+                .await
+                .unwrap()
+        {}
+        while let ExecutionOutcome::TaskCompleted = personal_data::try_answer_next_request(
+            &self.pg_pool,
+            &self.email_client,
+            &self.base_url,
+        )
+        .await
+        .unwrap()
+        {}
     }
 }
 
 /// Spin up an instance of the web server and return its address (i.e. http://localhost:XXXXX)
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, letting the test adjust the configuration first.
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialise` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -284,6 +480,7 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        customise(&mut c);
         c
     };
 
@@ -297,6 +494,7 @@ pub async fn spawn_app() -> TestApp {
 
     // Tokio spins up a new runtime for each test, shutting down and cleaning up
     // after the test ran. Therefore, no cleanup needed.
+    #[allow(clippy::let_underscore_future)]
     let _ = tokio::spawn(app.run_until_stopped());
 
     let api_client = reqwest::Client::builder()
@@ -315,6 +513,8 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client,
         email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url.clone(),
+        tracking: configuration.tracking.clone(),
     };
     test_app.test_user.store(&test_app.pg_pool).await;
     test_app
@@ -354,7 +554,7 @@ pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let email: String = SafeEmail().fake();
     // We are working with multiple subscribers now,
     // their details must be randomised to avoid conflicts!
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     }))
@@ -369,7 +569,7 @@ pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -381,7 +581,7 @@ pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .unwrap()
         .pop()
         .unwrap();
-    app.get_confirmation_links(&email_request)
+    app.get_confirmation_links(email_request)
 }
 
 pub async fn create_confirmed_subscriber(app: &TestApp) {
@@ -393,6 +593,72 @@ pub async fn create_confirmed_subscriber(app: &TestApp) {
         .unwrap();
 }
 
+/// Subscribe and confirm `email` to the default list, returning its id.
+///
+/// Unlike [`create_confirmed_subscriber`], this mounts no mock of its own:
+/// the email server must already accept the confirmation email.
+pub async fn confirmed_subscriber(app: &TestApp, name: &str, email: &str) -> Uuid {
+    let body = serde_urlencoded::to_string([("name", name), ("email", email)]).unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&last_email(app).await);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+}
+
+/// `app`, logged in, with the email server accepting everything.
+pub async fn logged_in(app: TestApp) -> TestApp {
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+pub async fn spawn_logged_in_app() -> TestApp {
+    logged_in(spawn_app().await).await
+}
+
+/// Logged in, with a key to call the API.
+pub async fn spawn_app_with_api_key() -> (TestApp, String) {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key().await;
+    (app, api_key)
+}
+
+/// The last email the email server received.
+pub async fn last_email(app: &TestApp) -> wiremock::Request {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+/// The body of the last issue sent, titled "Newsletter title".
+pub async fn issue_received(app: &TestApp) -> serde_json::Value {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .rfind(|body| body["Subject"] == "Newsletter title")
+        .unwrap()
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
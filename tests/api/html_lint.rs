@@ -0,0 +1,121 @@
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, last_email, spawn_app,
+    when_sending_an_email,
+};
+
+const UNSAFE_HTML: &str = "<p onclick=\"alert(1)\">Newsletter body as HTML</p>\
+    <script>alert(1)</script><img src=\"https://example.com/cat.png\">";
+
+fn newsletter_form(html_content: &str, idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": html_content,
+        "idempotency_key": idempotency_key,
+    })
+}
+
+/// The value of the `reviewed` field of the publish form.
+fn reviewed_field(html_page: &str) -> String {
+    let marker = r#"name="reviewed" value=""#;
+    let start = html_page.find(marker).unwrap() + marker.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_owned()
+}
+
+#[tokio::test]
+async fn unsafe_html_is_sent_back_with_what_was_found() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&newsletter_form(UNSAFE_HTML, &Uuid::new_v4().to_string()))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Review the HTML content before publishing it"));
+    assert!(
+        html_page.contains("&#60;script&#62; is not allowed: it is removed, with its content.")
+    );
+    assert!(html_page.contains("The `onclick` attribute of &#60;p&#62; is not allowed"));
+    assert!(html_page.contains("the image `https://example.com/cat.png` has no alt text"));
+    // The form is filled in with what would be sent
+    assert!(html_page.contains(r#"value="Newsletter title""#));
+    assert!(html_page.contains("&#60;p&#62;Newsletter body as HTML&#60;/p&#62;&#60;img"));
+    assert!(!html_page.contains("onclick=&#34;alert"));
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) as "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn reviewed_html_is_published_as_it_was_sent_back() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let html_page = app
+        .post_newsletters(&newsletter_form(UNSAFE_HTML, &idempotency_key))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let sanitised = "<p>Newsletter body as HTML</p><img src=\"https://example.com/cat.png\">";
+
+    // Act
+    let mut form = newsletter_form(sanitised, &idempotency_key);
+    form["reviewed"] = reviewed_field(&html_page).into();
+    let response = app.post_newsletters(&form).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let email_request = last_email(&app).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"].as_str().unwrap().contains(sanitised));
+}
+
+#[tokio::test]
+async fn html_changed_after_review_is_checked_again() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let html_page = app
+        .post_newsletters(&newsletter_form(UNSAFE_HTML, &idempotency_key))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Act
+    let mut form = newsletter_form(UNSAFE_HTML, &idempotency_key);
+    form["reviewed"] = reviewed_field(&html_page).into();
+    let response = app.post_newsletters(&form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Review the HTML content before publishing it"));
+}
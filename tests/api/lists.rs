@@ -0,0 +1,347 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, last_email, spawn_app, when_sending_an_email,
+};
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) -> reqwest::Response {
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "slug": slug, "name": name }))
+        .await;
+    app.post_admin_form("lists", &body).await
+}
+
+/// Subscribe `email` to `list`, returning the confirmation email we sent.
+async fn subscribe(app: &TestApp, email: &str, list: &str) -> wiremock::Request {
+    let body = serde_urlencoded::to_string([("name", "Ursula"), ("email", email), ("list", list)])
+        .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    last_email(app).await
+}
+
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list: &str) {
+    let email_request = subscribe(app, email, list).await;
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn status_on_list(app: &TestApp, email: &str, list: &str) -> String {
+    sqlx::query_scalar!(
+        r#"
+        SELECT s.status
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        list
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+/// Logged in, with a second list next to the default one.
+async fn spawn_app_with_two_lists() -> TestApp {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "weekly", "Weekly digest").await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+/// How many issues each address received.
+async fn newsletters_received(app: &TestApp) -> Vec<String> {
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "Newsletter title")
+        .map(|body| body["To"].as_str().unwrap().to_owned())
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+fn newsletter_form(lists: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "lists": lists,
+    })
+}
+
+#[tokio::test]
+async fn lists_can_be_created_from_the_admin_pages() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = create_list(&app, "weekly", "Weekly digest").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_admin_page_html("lists").await;
+    assert!(html_page.contains("The Weekly digest list has been created."));
+    assert!(html_page.contains("<code>weekly</code>"));
+    assert!(html_page.contains("<code>newsletter</code>"));
+}
+
+#[tokio::test]
+async fn lists_need_a_valid_and_unused_identifier() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Invalid
+    create_list(&app, "Weekly Digest", "Weekly digest").await;
+    let html_page = app.get_admin_page_html("lists").await;
+    assert!(html_page.contains("is not a valid list identifier"));
+
+    // Act - Part 2 - Taken
+    create_list(&app, "newsletter", "Another newsletter").await;
+    let html_page = app.get_admin_page_html("lists").await;
+    assert!(html_page.contains("There already is a list called &#39;newsletter&#39;."));
+    let n_lists: i64 = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM lists"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_lists, 1);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_page("lists").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribing_to_a_named_list_sends_a_confirmation_for_that_list() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+
+    // Act
+    let email_request = subscribe(&app, "ursula@example.com", "weekly").await;
+
+    // Assert
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().contains("Weekly digest"));
+    assert_eq!(
+        status_on_list(&app, "ursula@example.com", "weekly").await,
+        "pending_confirmation"
+    );
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40example.com&list=unknown".into())
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn each_list_is_confirmed_separately() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+
+    // Act
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe(&app, "ursula@example.com", "weekly").await;
+
+    // Assert
+    assert_eq!(
+        status_on_list(&app, "ursula@example.com", "newsletter").await,
+        "confirmed"
+    );
+    assert_eq!(
+        status_on_list(&app, "ursula@example.com", "weekly").await,
+        "pending_confirmation"
+    );
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_lists_they_are_published_to() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "octavia@example.com", "weekly").await;
+
+    // Act
+    let response = app.post_newsletters(&newsletter_form(&["weekly"])).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(newsletters_received(&app).await, ["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn subscribers_on_several_lists_get_an_issue_once() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "ursula@example.com", "weekly").await;
+    subscribe_and_confirm(&app, "octavia@example.com", "weekly").await;
+
+    // Act
+    app.post_newsletters(&newsletter_form(&["newsletter", "weekly"]))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        newsletters_received(&app).await,
+        ["octavia@example.com", "ursula@example.com"]
+    );
+    let n_recipients = sqlx::query_scalar!("SELECT n_recipients FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_recipients, 2);
+}
+
+#[tokio::test]
+async fn an_address_is_on_a_list_once_whatever_its_case() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "Ursula@Example.com", "weekly").await;
+
+    // Act
+    subscribe(&app, "URSULA@example.com", "newsletter").await;
+    app.post_newsletters(&newsletter_form(&["newsletter", "weekly"]))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_subscriptions = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscriptions, 2);
+    assert_eq!(newsletters_received(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn issues_must_be_published_to_at_least_one_list() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+
+    // Act
+    let response = app.post_newsletters(&newsletter_form(&[])).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("Pick at least one list to publish to."));
+    let n_issues: i64 = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn issues_cannot_be_published_to_unknown_lists() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+
+    // Act
+    let response = app
+        .post_newsletters(&newsletter_form(&["weekly", "unknown"]))
+        .await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_api_publishes_to_the_lists_it_is_given() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+    let api_key = app.create_api_key().await;
+    subscribe_and_confirm(&app, "ursula@example.com", "newsletter").await;
+    subscribe_and_confirm(&app, "octavia@example.com", "weekly").await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "lists": ["weekly"],
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["lists"], serde_json::json!(["weekly"]));
+    assert_eq!(issue["recipients"], 1);
+}
+
+#[tokio::test]
+async fn the_api_subscribes_to_the_list_it_is_given() {
+    // Arrange
+    let app = spawn_app_with_two_lists().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "subscribers", &api_key)
+        .json(&serde_json::json!({
+            "name": "Ursula",
+            "email": "ursula@example.com",
+            "list": "weekly",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["list"], "weekly");
+    let response = app
+        .api_v1(Method::GET, "subscribers?list=newsletter", &api_key)
+        .send()
+        .await
+        .unwrap();
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["total"], 0);
+}
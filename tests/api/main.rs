@@ -1,11 +1,31 @@
 mod admin_dashboard;
+mod admin_subscribers;
+mod api_newsletters;
+mod api_subscribers;
+mod attachments;
 mod change_password;
+mod csrf;
+mod email_templates;
 mod health_check;
 mod helpers;
+mod html_lint;
+mod lists;
 mod login;
+mod markdown;
+mod metrics;
 mod newsletters;
+mod personal_data;
+mod personalisation;
+mod preferences;
+mod security_headers;
+mod segments;
+mod subscriber_import;
+mod subscription_events;
 mod subscriptions;
 mod subscriptions_confirm;
+mod trace_context;
+mod tracking;
+mod webhooks;
 // This pattern causes our integration tests to only be one executable
 // with sub modules, instead of one crate per file, each duplicating the
 // helper module.
@@ -0,0 +1,154 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::{
+    assert_is_redirect_to, confirmed_subscriber, issue_received, spawn_logged_in_app,
+};
+
+const MARKDOWN: &str = "# Hello {{ name }}\n\n\
+    Read [our blog](https://example.com/blog) or [stop reading]({{unsubscribe_url}}).\n\n\
+    <script>alert('hi')</script>";
+
+fn markdown_form(markdown_content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "mode": "markdown",
+        "markdown_content": markdown_content,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+#[tokio::test]
+async fn issues_written_in_markdown_are_sent_as_html_and_text() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let response = app.post_newsletters(&markdown_form(MARKDOWN)).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let body = issue_received(&app).await;
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<div style="));
+    assert!(html_body.contains("<h1>Hello Ursula</h1>"));
+    assert!(html_body.contains(r#"<a href="https://example.com/blog""#));
+    assert!(html_body.contains(r#"<a href="http://127.0.0.1/preferences/"#));
+    assert!(!html_body.contains("<script"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    // Headings are underlined before the name is filled in
+    assert!(text_body.starts_with("Hello Ursula\n====="));
+    assert!(text_body.contains(
+        "Read our blog[1] or stop reading[2].\n\n\
+        [1] https://example.com/blog\n\
+        [2] http://127.0.0.1/preferences/"
+    ));
+}
+
+#[tokio::test]
+async fn the_markdown_of_an_issue_is_kept() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    app.post_newsletters(&markdown_form(MARKDOWN)).await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT markdown_content, text_content FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.markdown_content.as_deref(), Some(MARKDOWN));
+    assert!(issue.text_content.starts_with("Hello {{ name }}"));
+}
+
+#[tokio::test]
+async fn markdown_issues_need_markdown() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "mode": "markdown",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("Fill in the Markdown content."));
+    let n_issues = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn the_api_accepts_markdown() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": MARKDOWN,
+        }))
+        .send()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let body = issue_received(&app).await;
+    assert!(
+        body["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Hello Ursula")
+    );
+}
+
+#[tokio::test]
+async fn the_api_rejects_markdown_alongside_other_content() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "markdown_content": MARKDOWN,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"]["message"],
+        "Send either `text_content` and `html_content`, or `markdown_content`."
+    );
+}
@@ -0,0 +1,119 @@
+use secrecy::SecretString;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    create_confirmed_subscriber, spawn_app, spawn_app_with, when_sending_an_email,
+};
+
+async fn get_metrics(address: &str, token: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new().get(format!("{}/metrics", address));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn metrics_are_exposed_in_the_prometheus_text_format() {
+    // Arrange
+    let app = spawn_app().await;
+    reqwest::get(format!("{}/health_check", app.address))
+        .await
+        .unwrap();
+
+    // Act
+    let response = get_metrics(&app.address, None).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let body = response.text().await.unwrap();
+    assert!(
+        body.contains(r#"http_requests_total{method="GET",route="/health_check",status="200"}"#)
+    );
+    assert!(
+        body.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/health_check""#)
+    );
+    assert!(body.contains("pg_pool_connections "));
+    assert!(body.contains("pg_pool_idle_connections "));
+    assert!(body.contains("issue_delivery_queue_depth 0"));
+    assert!(body.contains("issue_delivery_queue_oldest_task_age_seconds 0"));
+}
+
+#[tokio::test]
+async fn unknown_paths_do_not_create_new_series() {
+    // Arrange
+    let app = spawn_app().await;
+    let path = Uuid::new_v4().to_string();
+    reqwest::get(format!("{}/{}", app.address, path))
+        .await
+        .unwrap();
+
+    // Act
+    let body = get_metrics(&app.address, None).await.text().await.unwrap();
+
+    // Assert
+    assert!(!body.contains(&path));
+    assert!(body.contains(r#"route="unmatched",status="404""#));
+}
+
+#[tokio::test]
+async fn delivery_queue_and_email_outcomes_are_tracked() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+    app.post_newsletters(&newsletter_request_body).await;
+
+    // Act - Part 1 - Before delivery
+    let body = get_metrics(&app.address, None).await.text().await.unwrap();
+
+    // Assert
+    assert!(body.contains("issue_delivery_queue_depth 1"));
+
+    // Act - Part 2 - After delivery
+    app.dispatch_all_pending_emails().await;
+    let body = get_metrics(&app.address, None).await.text().await.unwrap();
+
+    // Assert
+    assert!(body.contains("issue_delivery_queue_depth 0"));
+    assert!(body.contains(r#"newsletter_emails_total{outcome="sent"}"#));
+    assert!(body.contains("email_send_duration_seconds_count"));
+}
+
+#[tokio::test]
+async fn metrics_require_the_configured_bearer_token() {
+    // Arrange
+    let token = Uuid::new_v4().to_string();
+    let app = spawn_app_with(|c| {
+        c.metrics.bearer_token = Some(SecretString::from(token.clone()));
+    })
+    .await;
+
+    // Act
+    let without_token = get_metrics(&app.address, None).await;
+    let wrong_token = get_metrics(&app.address, Some("wrong-token")).await;
+    let right_token = get_metrics(&app.address, Some(&token)).await;
+
+    // Assert
+    assert_eq!(401, without_token.status().as_u16());
+    assert_eq!(without_token.headers()["WWW-Authenticate"], "Bearer");
+    assert_eq!(401, wrong_token.status().as_u16());
+    assert_eq!(200, right_token.status().as_u16());
+}
@@ -225,3 +225,60 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
+
+#[tokio::test]
+async fn issues_and_delivery_tasks_record_the_publishing_request() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let body = app
+        .with_csrf_token(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+            "lists": "newsletter"
+        }))
+        .await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", trace_id),
+        )
+        .form(&body)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let issue = sqlx::query!(
+        "SELECT published_by, publish_request_id, publish_traceparent FROM newsletter_issues"
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.published_by, Some(app.test_user.user_id));
+    assert!(issue.publish_request_id.is_some());
+    assert!(issue.publish_traceparent.unwrap().contains(trace_id));
+
+    let tasks = sqlx::query!(
+        "SELECT published_by, publish_request_id, publish_traceparent FROM issue_delivery_queue"
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(tasks.len(), 2);
+    for task in tasks {
+        assert_eq!(task.published_by, Some(app.test_user.user_id));
+        assert_eq!(task.publish_request_id, issue.publish_request_id);
+        assert!(task.publish_traceparent.unwrap().contains(trace_id));
+    }
+}
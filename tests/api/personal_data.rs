@@ -0,0 +1,314 @@
+use wiremock::ResponseTemplate;
+use zero2prod::personal_data;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, create_confirmed_subscriber, last_email, spawn_app,
+    when_sending_an_email,
+};
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+async fn post_personal_data_request(app: &TestApp, email: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/personal_data", &app.address))
+        .form(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Request a link for `email` and return it, pointing at the test server.
+async fn get_personal_data_link(app: &TestApp, email: &str) -> reqwest::Url {
+    let _mock_guard = when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    post_personal_data_request(app, email).await;
+    // Only the request: issues left in the queue stay there
+    personal_data::try_answer_next_request(&app.pg_pool, &app.email_client, &app.base_url)
+        .await
+        .unwrap();
+    let email_request = last_email(app).await;
+    app.get_confirmation_links(&email_request).html
+}
+
+async fn count_erasures(app: &TestApp, requested_by: &str) -> i64 {
+    sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM personal_data_erasures WHERE requested_by = $1"#,
+        requested_by
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_but_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_personal_data_request(&app, "nobody@example.com").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/personal_data");
+    let html_page = app
+        .api_client
+        .get(format!("{}/personal_data", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("If we hold data about this address, we have sent it a link"));
+}
+
+#[tokio::test]
+async fn known_addresses_get_the_same_answer_even_if_the_email_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_personal_data_request(&app, &email).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/personal_data");
+    let html_page = app
+        .api_client
+        .get(format!("{}/personal_data", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("If we hold data about this address, we have sent it a link"));
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_from_the_emailed_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+
+    // Act
+    let link = get_personal_data_link(&app, &email).await;
+    let html_page = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let export = app
+        .api_client
+        .get(format!("{}/export", link))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(&format!("Data we hold about {}", email)));
+    assert!(html_page.contains("Confirmation links sent: 1"));
+    assert_eq!(200, export.status().as_u16());
+    assert!(
+        export.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .starts_with("attachment")
+    );
+    let data: serde_json::Value = export.json().await.unwrap();
+    assert_eq!(data["email"], email.as_str());
+    assert_eq!(data["subscriptions"][0]["status"], "confirmed");
+    assert_eq!(data["confirmation_tokens"].as_array().unwrap().len(), 1);
+    let events: Vec<_> = data["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["event_type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        ["subscribed", "confirmation_email_sent", "confirmed"]
+    );
+    assert!(data["deliveries"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data_from_the_emailed_link() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    let link = get_personal_data_link(&app, &email).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/erase", link))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/personal_data");
+    for table in [
+        "subscriptions",
+        "subscription_tokens",
+        "subscription_events",
+        "issue_delivery_queue",
+        "personal_data_tokens",
+    ] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} still has rows", table);
+    }
+    assert_eq!(count_erasures(&app, "subscriber").await, 1);
+    let response = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn expired_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    let link = get_personal_data_link(&app, &email).await;
+    sqlx::query!("UPDATE personal_data_tokens SET created_at = now() - interval '25 hours'")
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let page = app.api_client.get(link.clone()).send().await.unwrap();
+    let erase = app
+        .api_client
+        .post(format!("{}/erase", link))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, page.status().as_u16());
+    assert_eq!(401, erase.status().as_u16());
+    assert_eq!(count_erasures(&app, "subscriber").await, 0);
+}
+
+#[tokio::test]
+async fn admins_can_export_and_erase_the_data_held_about_an_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Export
+    let export: serde_json::Value = app
+        .get_admin_page(&format!("personal_data/export?email={}", email))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["subscriptions"][0]["status"], "confirmed");
+
+    // Act - Part 2 - Erase
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "email": email }))
+        .await;
+    let response = app.post_admin_form("personal_data/erase", &body).await;
+    assert_is_redirect_to(&response, "/admin/personal_data");
+
+    // Assert
+    let html_page = app.get_admin_page_html("personal_data").await;
+    assert!(html_page.contains("row(s) of personal data have been erased."));
+    assert!(html_page.contains("<td>admin</td>"));
+    assert!(!html_page.contains(&email));
+    assert_eq!(count_erasures(&app, "admin").await, 1);
+    let export: serde_json::Value = app
+        .get_admin_page(&format!("personal_data/export?email={}", email))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(export["subscriptions"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn erasures_ignore_the_case_of_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "email": email.to_uppercase() }))
+        .await;
+    app.post_admin_form("personal_data/erase", &body).await;
+
+    // Assert
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_is_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+    app.post_admin_form(&format!("subscribers/{}/delete", id), &body)
+        .await;
+
+    // Assert
+    assert_eq!(count_erasures(&app, "admin").await, 1);
+}
@@ -0,0 +1,229 @@
+use reqwest::{Method, Url};
+use uuid::Uuid;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, confirmed_subscriber, spawn_app, spawn_logged_in_app,
+};
+
+fn newsletter_form(text_content: &str, html_content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": text_content,
+        "html_content": html_content,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+/// The bodies of the issue each address received.
+async fn issues_received(app: &TestApp) -> Vec<(String, serde_json::Value)> {
+    let mut issues: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "Newsletter title")
+        .map(|body| (body["To"].as_str().unwrap().to_owned(), body))
+        .collect();
+    issues.sort_by(|a, b| a.0.cmp(&b.0));
+    issues
+}
+
+/// The link `text` starts with, pointed at the application under test.
+fn link_at_start_of(app: &TestApp, text: &str) -> Url {
+    let link = text.split_whitespace().next().unwrap();
+    let mut link = Url::parse(link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn issues_are_personalised_for_each_subscriber() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "O'Brien & Co", "obrien@example.com").await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    app.post_newsletters(&newsletter_form(
+        "Hi {{ name }}, this went to {{email}}.",
+        "<p>Hi {{ name }}, this went to {{ email }}.</p>",
+    ))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issues = issues_received(&app).await;
+    let (_, obrien) = &issues[0];
+    assert!(
+        obrien["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Hi O'Brien & Co, this went to obrien@example.com.")
+    );
+    assert!(
+        obrien["HtmlBody"]
+            .as_str()
+            .unwrap()
+            .contains("<p>Hi O&#39;Brien &amp; Co, this went to obrien@example.com.</p>")
+    );
+    let (_, ursula) = &issues[1];
+    assert!(
+        ursula["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Hi Ursula, this went to ursula@example.com.")
+    );
+}
+
+#[tokio::test]
+async fn issues_link_to_their_web_view_and_to_unsubscribing() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    app.post_newsletters(&newsletter_form(
+        "{{ web_view_url }}\n{{ unsubscribe_url }}",
+        "<p>Hi {{ name }}</p>",
+    ))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issues = issues_received(&app).await;
+    let text_body = issues[0].1["TextBody"].as_str().unwrap();
+    let mut lines = text_body.lines();
+    let web_view_link = link_at_start_of(&app, lines.next().unwrap());
+    let unsubscribe_link = link_at_start_of(&app, lines.next().unwrap());
+    let web_view = app
+        .api_client
+        .get(web_view_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(web_view.contains("<p>Hi Ursula</p>"));
+    let unsubscribe_page = app
+        .api_client
+        .get(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(unsubscribe_page.contains("Stop sending anything to ursula@example.com?"));
+}
+
+#[tokio::test]
+async fn web_views_are_only_shown_to_the_recipients_of_the_issue() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let form = || newsletter_form("{{ web_view_url }}", "<p>Hi {{ name }}</p>");
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    app.post_newsletters(&form()).await;
+    app.dispatch_all_pending_emails().await;
+    // Octavia subscribes after the first issue went out
+    confirmed_subscriber(&app, "Octavia", "octavia@example.com").await;
+    app.post_newsletters(&form()).await;
+    app.dispatch_all_pending_emails().await;
+    let issues = issues_received(&app).await;
+    let first_issue = link_at_start_of(&app, issues[1].1["TextBody"].as_str().unwrap());
+    let first_issue_id = first_issue.path_segments().unwrap().nth(1).unwrap();
+    let octavia_link = link_at_start_of(&app, issues[0].1["TextBody"].as_str().unwrap());
+    let octavia_token = octavia_link.path_segments().unwrap().nth(2).unwrap();
+
+    // Act
+    let own_issue = app
+        .api_client
+        .get(octavia_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let mut other_issue = octavia_link.clone();
+    other_issue.set_path(&format!("/issues/{}/{}", first_issue_id, octavia_token));
+    let other_issue = app.api_client.get(other_issue).send().await.unwrap();
+
+    // Assert
+    assert_eq!(200, own_issue.status().as_u16());
+    assert_eq!(404, other_issue.status().as_u16());
+}
+
+#[tokio::test]
+async fn web_views_need_a_valid_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/issues/{}/UnknownToken123456789abcd",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn issues_with_unknown_variables_are_not_published() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let response = app
+        .post_newsletters(&newsletter_form("Hi {{ nmae }}", "<p>Hi {{ name }}</p>"))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("In the text content: There is no `nmae` variable (line 1)"));
+    assert_eq!(n_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn the_api_rejects_issues_with_template_syntax_errors() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ name }}",
+            "html_content": "<p>Hi\n{{ name </p>",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"]["message"],
+        "In the HTML content: A placeholder opened on line 2 is never closed with `}}`."
+    );
+    assert_eq!(n_issues(&app).await, 0);
+}
@@ -0,0 +1,389 @@
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, confirmed_subscriber, last_email, spawn_app,
+    when_sending_an_email,
+};
+
+const EMAIL: &str = "ursula@example.com";
+
+/// With the email server accepting everything and one confirmed subscriber,
+/// `EMAIL`, on the default list.
+async fn spawn_app_with_a_subscriber() -> TestApp {
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    confirmed_subscriber(&app, "Ursula", EMAIL).await;
+    app
+}
+
+/// The path of the preference center of `email`, e.g. `/preferences/abc`.
+async fn preferences_path(app: &TestApp, email: &str) -> String {
+    let token = sqlx::query_scalar!(
+        r#"
+        INSERT INTO subscriber_preferences (email, preference_token)
+        VALUES ($1, $2)
+        ON CONFLICT (email) DO UPDATE SET email = EXCLUDED.email
+        RETURNING preference_token
+        "#,
+        email,
+        Uuid::new_v4().simple().to_string()[..25].to_owned()
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    format!("/preferences/{}", token)
+}
+
+async fn get_page(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", app.address, path))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn get_page_html(app: &TestApp, path: &str) -> String {
+    get_page(app, path).await.text().await.unwrap()
+}
+
+/// Lists are checkboxes, sent as repeated fields.
+async fn post_form(app: &TestApp, path: &str, form: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}{}", app.address, path))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(serde_html_form::to_string(form).unwrap())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn publish_an_issue(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+struct SubscriptionRow {
+    email: String,
+    name: String,
+    slug: String,
+    status: String,
+}
+
+async fn subscriptions(app: &TestApp) -> Vec<SubscriptionRow> {
+    sqlx::query_as!(
+        SubscriptionRow,
+        r#"
+        SELECT s.email, s.name, l.slug, s.status
+        FROM subscriptions s
+        JOIN lists l USING (list_id)
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preference_center_of_their_recipient() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+
+    // Act
+    publish_an_issue(&app).await;
+
+    // Assert
+    let email_request = last_email(&app).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    let link = linkify::LinkFinder::new()
+        .links(text_body)
+        .map(|l| l.as_str().to_owned())
+        .find(|l| l.contains("/preferences/"))
+        .unwrap();
+    assert!(body["HtmlBody"].as_str().unwrap().contains(&link));
+    let mut link = Url::parse(&link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    let html_page = get_page_html(&app, link.path()).await;
+    assert!(html_page.contains(EMAIL));
+    assert!(html_page.contains(r#"value="Ursula""#));
+}
+
+#[tokio::test]
+async fn unknown_preference_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = get_page(&app, "/preferences/UnknownToken123456789abcd").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+
+    // Act
+    let response = post_form(
+        &app,
+        &path,
+        &serde_json::json!({ "name": "Ursula K.", "lists": ["newsletter"], "format": "html" }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &path);
+    assert!(
+        get_page_html(&app, &path)
+            .await
+            .contains("Your preferences have been saved.")
+    );
+    let rows = subscriptions(&app).await;
+    assert_eq!(rows[0].name, "Ursula K.");
+    assert_eq!(rows[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn invalid_names_are_rejected() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+
+    // Act
+    post_form(
+        &app,
+        &path,
+        &serde_json::json!({ "name": "<script>", "lists": ["newsletter"], "format": "html" }),
+    )
+    .await;
+
+    // Assert
+    assert!(
+        get_page_html(&app, &path)
+            .await
+            .contains("is not a valid subscriber name")
+    );
+    assert_eq!(subscriptions(&app).await[0].name, "Ursula");
+}
+
+#[tokio::test]
+async fn subscribers_can_choose_their_lists() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    app.test_user.login(&app).await;
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "slug": "weekly", "name": "Weekly digest" }))
+        .await;
+    app.post_admin_form("lists", &body).await;
+    let path = preferences_path(&app, EMAIL).await;
+
+    // Act
+    post_form(
+        &app,
+        &path,
+        &serde_json::json!({ "name": "Ursula", "lists": ["weekly"], "format": "html" }),
+    )
+    .await;
+
+    // Assert
+    let rows = subscriptions(&app).await;
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].slug, "newsletter");
+    assert_eq!(rows[0].status, "unsubscribed");
+    assert_eq!(rows[1].slug, "weekly");
+    assert_eq!(rows[1].status, "confirmed");
+    let events = sqlx::query_scalar!(
+        r#"
+        SELECT event_type
+        FROM subscription_events
+        WHERE source = 'preferences'
+        ORDER BY occurred_at, event_id
+        "#
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(events, ["unsubscribed", "subscribed", "confirmed"]);
+}
+
+#[tokio::test]
+async fn text_only_subscribers_get_issues_without_html() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+    post_form(
+        &app,
+        &path,
+        &serde_json::json!({ "name": "Ursula", "lists": ["newsletter"], "format": "text" }),
+    )
+    .await;
+
+    // Act
+    publish_an_issue(&app).await;
+
+    // Assert
+    let body: serde_json::Value = serde_json::from_slice(&last_email(&app).await.body).unwrap();
+    assert_eq!(body["Subject"], "Newsletter title");
+    assert!(body.get("HtmlBody").is_none());
+    assert!(
+        body["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Newsletter body as plain text")
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_everything() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+
+    // Act
+    let response = post_form(
+        &app,
+        &format!("{}/unsubscribe", path),
+        &serde_json::json!({}),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &path);
+    assert_eq!(subscriptions(&app).await[0].status, "unsubscribed");
+    let n_emails = app.email_server.received_requests().await.unwrap().len();
+    publish_an_issue(&app).await;
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        n_emails
+    );
+}
+
+#[tokio::test]
+async fn changing_email_takes_effect_once_the_new_address_is_confirmed() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+
+    // Act - Part 1 - Request the change
+    let response = post_form(
+        &app,
+        &format!("{}/email", path),
+        &serde_json::json!({ "email": "ursula@example.org" }),
+    )
+    .await;
+    assert_is_redirect_to(&response, &path);
+    let email_request = last_email(&app).await;
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.org");
+    assert_eq!(subscriptions(&app).await[0].email, EMAIL);
+
+    // Act - Part 2 - Confirm it
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    let response = get_page(
+        &app,
+        &format!(
+            "{}?{}",
+            confirmation_links.html.path(),
+            confirmation_links.html.query().unwrap()
+        ),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &path);
+    assert!(
+        get_page_html(&app, &path)
+            .await
+            .contains("Your email address is now ursula@example.org.")
+    );
+    assert_eq!(subscriptions(&app).await[0].email, "ursula@example.org");
+    let n_changes: i64 = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "n!" FROM subscription_events WHERE event_type = 'email_changed'"#
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_changes, 1);
+}
+
+#[tokio::test]
+async fn email_cannot_be_changed_to_an_address_already_subscribed() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+    post_form(
+        &app,
+        &format!("{}/email", path),
+        &serde_json::json!({ "email": "octavia@example.com" }),
+    )
+    .await;
+    let confirmation_links = app.get_confirmation_links(&last_email(&app).await);
+    app.post_subscriptions("name=Octavia&email=octavia%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(confirmation_links.html)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(303, response.status().as_u16());
+    let emails: Vec<_> = subscriptions(&app)
+        .await
+        .into_iter()
+        .map(|row| row.email)
+        .collect();
+    assert!(emails.contains(&EMAIL.to_owned()));
+}
+
+#[tokio::test]
+async fn email_change_links_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app_with_a_subscriber().await;
+    let path = preferences_path(&app, EMAIL).await;
+    post_form(
+        &app,
+        &format!("{}/email", path),
+        &serde_json::json!({ "email": "ursula@example.org" }),
+    )
+    .await;
+    let confirmation_links = app.get_confirmation_links(&last_email(&app).await);
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    client
+        .get(confirmation_links.html.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // Act
+    let response = client.get(confirmation_links.html).send().await.unwrap();
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,79 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn security_headers_are_set_on_html_pages() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // The admin dashboard redirects anonymous users to the login page
+    for path in ["/", "/login", "/admin/dashboard"] {
+        // Act
+        let response = app
+            .api_client
+            .get(format!("{}{}", &app.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request");
+
+        // Assert
+        let headers = response.headers();
+        assert!(headers.contains_key("Content-Security-Policy"));
+        assert_eq!(headers["X-Frame-Options"], "DENY");
+        assert_eq!(
+            headers["Referrer-Policy"],
+            "strict-origin-when-cross-origin"
+        );
+        assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+        // HSTS is only enabled in production, where we are behind HTTPS
+        assert!(!headers.contains_key("Strict-Transport-Security"));
+    }
+}
+
+#[tokio::test]
+async fn inline_scripts_carry_the_nonce_of_the_content_security_policy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_newsletters().await;
+
+    // Assert
+    let csp = response.headers()["Content-Security-Policy"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let html_page = response.text().await.unwrap();
+    let nonce = extract_nonce(&csp);
+    assert!(html_page.contains(&format!(r#"<script nonce="{}">"#, nonce)));
+}
+
+#[tokio::test]
+async fn a_new_nonce_is_generated_for_each_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let get_csp = || async {
+        app.api_client
+            .get(&app.address)
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .headers()["Content-Security-Policy"]
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+
+    // Act
+    let csp1 = get_csp().await;
+    let csp2 = get_csp().await;
+
+    // Assert
+    assert_ne!(extract_nonce(&csp1), extract_nonce(&csp2));
+}
+
+fn extract_nonce(csp: &str) -> &str {
+    let start = csp.find("'nonce-").expect("No nonce in the CSP") + "'nonce-".len();
+    let end = start + csp[start..].find('\'').unwrap();
+    &csp[start..end]
+}
@@ -0,0 +1,407 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, assert_is_redirect_to, confirmed_subscriber, spawn_logged_in_app};
+
+async fn tag(app: &TestApp, subscriber_id: Uuid, tags: &str) -> reqwest::Response {
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "tags": tags }))
+        .await;
+    app.post_admin_form(&format!("subscribers/{}/tags", subscriber_id), &body)
+        .await
+}
+
+async fn tags_of(app: &TestApp, subscriber_id: Uuid) -> Vec<String> {
+    sqlx::query_scalar!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+/// Create a segment from the admin page, returning its id.
+async fn create_segment(app: &TestApp, form: serde_json::Value) -> Option<Uuid> {
+    let name = form["name"].as_str().unwrap().to_owned();
+    let body = app.with_csrf_token(&form).await;
+    let response = app.post_admin_form("segments", &body).await;
+    assert_is_redirect_to(&response, "/admin/segments");
+    sqlx::query_scalar!("SELECT segment_id FROM segments WHERE name = $1", name)
+        .fetch_optional(&app.pg_pool)
+        .await
+        .unwrap()
+}
+
+async fn publish_to_segment(app: &TestApp, segment_id: Uuid) -> reqwest::Response {
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "segment": segment_id.to_string(),
+    }))
+    .await
+}
+
+async fn newsletters_received(app: &TestApp) -> Vec<String> {
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "Newsletter title")
+        .map(|body| body["To"].as_str().unwrap().to_owned())
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+async fn admins_can_tag_and_untag_subscribers() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let id = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act - Part 1 - Tag
+    let response = tag(&app, id, "Beta, vip, beta").await;
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", id));
+    assert_eq!(tags_of(&app, id).await, ["beta", "vip"]);
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", id))
+        .await;
+    assert!(html_page.contains("vip"));
+
+    // Act - Part 2 - Untag
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "tag": "beta" }))
+        .await;
+    app.post_admin_form(&format!("subscribers/{}/tags/remove", id), &body)
+        .await;
+    assert_eq!(tags_of(&app, id).await, ["vip"]);
+}
+
+#[tokio::test]
+async fn the_subscriber_list_can_be_filtered_by_tag() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let ursula = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    confirmed_subscriber(&app, "Ursula", "octavia@example.com").await;
+    tag(&app, ursula, "vip").await;
+
+    // Act
+    let html_page = app
+        .get_admin_subscribers("?tag=vip")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("octavia@example.com"));
+}
+
+#[tokio::test]
+async fn the_api_tags_and_untags_subscribers() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let api_key = app.create_api_key().await;
+    let id = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act - Part 1 - Tag
+    let response = app
+        .api_v1(
+            Method::PUT,
+            &format!("subscribers/{}/tags/VIP", id),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+    let page: serde_json::Value = app
+        .api_v1(Method::GET, "subscribers?tag=vip", &api_key)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["data"][0]["tags"], serde_json::json!(["vip"]));
+
+    // Act - Part 2 - Untag
+    let response = app
+        .api_v1(
+            Method::DELETE,
+            &format!("subscribers/{}/tags/vip", id),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+    let response = app
+        .api_v1(
+            Method::DELETE,
+            &format!("subscribers/{}/tags/vip", id),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_api_does_not_tag_unknown_subscribers() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let api_key = app.create_api_key().await;
+
+    // Act
+    let response = app
+        .api_v1(
+            Method::PUT,
+            &format!("subscribers/{}/tags/vip", Uuid::new_v4()),
+            &api_key,
+        )
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn segments_can_be_created_from_the_admin_pages() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Beta testers", "with_tags": "beta" }),
+    )
+    .await;
+
+    // Assert
+    assert!(segment_id.is_some());
+    let html_page = app.get_admin_page_html("segments").await;
+    assert!(html_page.contains("The Beta testers segment has been created."));
+    assert!(html_page.contains("Tagged beta"));
+}
+
+#[tokio::test]
+async fn invalid_segments_are_rejected() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "name": "Dates", "subscribed_from": "yesterday" }),
+            "yesterday is not a valid date",
+        ),
+        (
+            serde_json::json!({
+                "name": "Backwards",
+                "subscribed_from": "2025-02-01",
+                "subscribed_to": "2025-01-01",
+            }),
+            "The subscription period ends before it starts.",
+        ),
+        (
+            serde_json::json!({ "name": "Domain", "email_domain": "example" }),
+            "example is not a valid email domain.",
+        ),
+    ];
+
+    for (form, error_message) in test_cases {
+        // Act
+        let segment_id = create_segment(&app, form).await;
+
+        // Assert
+        assert!(segment_id.is_none());
+        let html_page = app.get_admin_page_html("segments").await;
+        assert!(
+            html_page.contains(error_message),
+            "Expected {:?} in the page",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn issues_to_a_segment_only_go_to_its_subscribers() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let ursula = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let octavia = confirmed_subscriber(&app, "Ursula", "octavia@example.com").await;
+    let nk = confirmed_subscriber(&app, "Ursula", "nk@example.org").await;
+    confirmed_subscriber(&app, "Ursula", "ted@example.com").await;
+    tag(&app, ursula, "beta").await;
+    tag(&app, octavia, "beta, churned").await;
+    tag(&app, nk, "beta").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({
+            "name": "Beta testers",
+            "with_tags": "beta",
+            "without_tags": "churned",
+            "email_domain": "@Example.com",
+        }),
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let response = publish_to_segment(&app, segment_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(newsletters_received(&app).await, ["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn segments_can_select_subscribers_by_subscription_date() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let octavia = confirmed_subscriber(&app, "Ursula", "octavia@example.com").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = '2024-06-15T12:00:00Z' WHERE id = $1",
+        octavia
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({
+            "name": "Early subscribers",
+            "subscribed_from": "2024-06-15",
+            "subscribed_to": "2024-12-31",
+        }),
+    )
+    .await
+    .unwrap();
+
+    // Act
+    publish_to_segment(&app, segment_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(newsletters_received(&app).await, ["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+
+    // Act
+    let response = publish_to_segment(&app, Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn the_publish_form_shows_how_many_subscribers_an_issue_goes_to() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let ursula = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    confirmed_subscriber(&app, "Ursula", "octavia@example.com").await;
+    tag(&app, ursula, "beta").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Beta testers", "with_tags": "beta" }),
+    )
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Whole list
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains(r#"<span id="recipientCount">2</span>"#));
+
+    // Act - Part 2 - Segment
+    let response = app
+        .get_admin_page(&format!(
+            "newsletters/recipients?lists=newsletter&segment={}",
+            segment_id
+        ))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let count: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(count["recipients"], 1);
+}
+
+#[tokio::test]
+async fn segments_that_issues_went_to_cannot_be_deleted() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let used = create_segment(&app, serde_json::json!({ "name": "Used" }))
+        .await
+        .unwrap();
+    let unused = create_segment(&app, serde_json::json!({ "name": "Unused" }))
+        .await
+        .unwrap();
+    publish_to_segment(&app, used).await;
+    let body = app.with_csrf_token(&serde_json::json!({})).await;
+
+    // Act
+    app.post_admin_form(&format!("segments/{}/delete", unused), &body)
+        .await;
+    app.post_admin_form(&format!("segments/{}/delete", used), &body)
+        .await;
+
+    // Assert
+    let html_page = app.get_admin_page_html("segments").await;
+    assert!(html_page.contains("Segments that issues were sent to cannot be deleted."));
+    let remaining = sqlx::query_scalar!("SELECT name FROM segments")
+        .fetch_all(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, ["Used"]);
+}
+
+#[tokio::test]
+async fn the_api_publishes_to_a_segment() {
+    // Arrange
+    let app = spawn_logged_in_app().await;
+    let api_key = app.create_api_key().await;
+    let ursula = confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    confirmed_subscriber(&app, "Ursula", "octavia@example.com").await;
+    tag(&app, ursula, "beta").await;
+    let segment_id = create_segment(
+        &app,
+        serde_json::json!({ "name": "Beta testers", "with_tags": "beta" }),
+    )
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .api_v1(Method::POST, "newsletters", &api_key)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "segment": segment_id,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["segment"], segment_id.to_string());
+    assert_eq!(issue["recipients"], 1);
+}
@@ -0,0 +1,262 @@
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, last_email, spawn_app, when_sending_an_email,
+};
+
+const CSV: &str = "name,email
+Ursula Le Guin,ursula@example.com
+Octavia Butler,octavia@example.com
+No Email,=not-an-email
+Ursula Again,ursula@example.com
+,nameless@example.com
+Existing,existing@example.com
+";
+
+async fn insert_existing_subscriber(app: &TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, list_id)
+        SELECT $1, 'existing@example.com', 'Existing', now(), 'confirmed', list_id
+        FROM lists WHERE slug = 'newsletter'
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+}
+
+/// Follow the redirect to the import report page.
+async fn get_report_html(app: &TestApp, response: &reqwest::Response) -> String {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers()["Location"].to_str().unwrap();
+    assert!(location.starts_with("/admin/subscribers/imports/"));
+    app.get_admin_page_html(location.trim_start_matches("/admin/"))
+        .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_subscriber_import(CSV, "mark_confirmed").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn imports_require_a_csrf_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let form = reqwest::multipart::Form::new()
+        .text("csv", CSV)
+        .text("mode", "mark_confirmed");
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn valid_rows_can_be_imported_as_confirmed_without_sending_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_existing_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_subscriber_import(CSV, "mark_confirmed").await;
+
+    // Assert
+    let html_page = get_report_html(&app, &response).await;
+    assert!(html_page.contains("2 subscriber(s) have been imported."));
+    assert!(html_page.contains("Imported: 2"));
+    assert!(html_page.contains("Duplicates: 2"));
+    assert!(html_page.contains("Invalid: 2"));
+    let imported = sqlx::query!(
+        "SELECT email, status FROM subscriptions WHERE email <> 'existing@example.com' ORDER BY email"
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(imported.len(), 2);
+    assert_eq!(imported[0].email, "octavia@example.com");
+    assert!(imported.iter().all(|r| r.status == "confirmed"));
+}
+
+#[tokio::test]
+async fn duplicates_are_found_whatever_the_case_of_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_existing_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let csv = "name,email
+Ursula Le Guin,ursula@example.com
+Ursula Again,URSULA@example.com
+Existing,Existing@Example.com
+";
+
+    // Act
+    let response = app.post_subscriber_import(csv, "mark_confirmed").await;
+
+    // Assert
+    let html_page = get_report_html(&app, &response).await;
+    assert!(html_page.contains("Imported: 1"));
+    assert!(html_page.contains("Duplicates: 2"));
+}
+
+#[tokio::test]
+async fn imported_rows_can_be_sent_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Import
+    let response = app.post_subscriber_import(CSV, "send_confirmations").await;
+    get_report_html(&app, &response).await;
+    // The emails are only queued
+    assert!(
+        app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Act - Part 2 - Send the queued emails
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let statuses = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses.len(), 3);
+    assert!(statuses.iter().all(|r| r.status == "pending_confirmation"));
+    let email_request = last_email(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn confirmation_emails_that_could_not_be_sent_are_noted_in_the_report() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_subscriber_import("Ursula,ursula@example.com\n", "send_confirmations")
+        .await;
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let report = app
+        .get_admin_page(&format!(
+            "{}/report.csv",
+            location.trim_start_matches("/admin/")
+        ))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(report.contains(
+        "1,Ursula,ursula@example.com,imported,\"The confirmation email could not be sent, \
+        re-send it from the subscriber's page.\""
+    ));
+    let n_queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM import_confirmation_queue"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn the_report_can_be_downloaded_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_existing_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let response = app.post_subscriber_import(CSV, "mark_confirmed").await;
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+
+    // Act
+    let response = app
+        .get_admin_page(&format!(
+            "{}/report.csv",
+            location.trim_start_matches("/admin/")
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    assert!(
+        response.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .starts_with("attachment")
+    );
+    let report = response.text().await.unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "line,name,email,outcome,detail");
+    assert_eq!(lines[1], "2,Ursula Le Guin,ursula@example.com,imported,");
+    // Cells of the file are not run as formulas
+    assert!(lines[3].starts_with("4,No Email,'=not-an-email,invalid,"));
+    assert!(lines[4].starts_with("5,Ursula Again,ursula@example.com,duplicate,"));
+    assert!(lines[5].starts_with("6,,nameless@example.com,invalid,"));
+    assert!(lines[6].starts_with("7,Existing,existing@example.com,duplicate,"));
+    assert_eq!(lines.len(), 7);
+}
+
+#[tokio::test]
+async fn files_without_subscribers_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("name,email\n", "mark_confirmed")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app.get_admin_page_html("subscribers/import").await;
+    assert!(html_page.contains("The CSV file has no subscribers in it."));
+}
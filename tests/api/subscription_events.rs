@@ -0,0 +1,284 @@
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{
+    TestApp, create_confirmed_subscriber, spawn_app, spawn_app_with, when_sending_an_email,
+};
+
+struct EventRow {
+    event_type: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    subscription_token: Option<String>,
+}
+
+async fn events(app: &TestApp) -> Vec<EventRow> {
+    sqlx::query_as!(
+        EventRow,
+        r#"
+        SELECT event_type, source, ip_address, user_agent, subscription_token
+        FROM subscription_events
+        ORDER BY occurred_at, event_id
+        "#
+    )
+    .fetch_all(&app.pg_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_records_who_consented_and_how() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Subscribing browser")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.api_client
+        .get(confirmation_links.html)
+        .header("User-Agent", "Email client")
+        .header("X-Forwarded-For", "198.51.100.2")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let token: String = sqlx::query_scalar!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    let events = events(&app).await;
+    assert_eq!(events.len(), 3);
+    let [subscribed, sent, confirmed] = &events[..] else {
+        unreachable!()
+    };
+    assert_eq!(subscribed.event_type, "subscribed");
+    assert_eq!(subscribed.source, "form");
+    assert_eq!(subscribed.ip_address.as_deref(), Some("203.0.113.7"));
+    assert_eq!(
+        subscribed.user_agent.as_deref(),
+        Some("Subscribing browser")
+    );
+    assert_eq!(sent.event_type, "confirmation_email_sent");
+    assert_eq!(sent.subscription_token.as_deref(), Some(token.as_str()));
+    assert_eq!(confirmed.event_type, "confirmed");
+    assert_eq!(confirmed.source, "confirmation_link");
+    assert_eq!(confirmed.ip_address.as_deref(), Some("198.51.100.2"));
+    assert_eq!(confirmed.user_agent.as_deref(), Some("Email client"));
+    assert_eq!(
+        confirmed.subscription_token.as_deref(),
+        Some(token.as_str())
+    );
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_ignored_unless_sent_by_a_trusted_proxy() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .header("Forwarded", "for=203.0.113.7")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let events = events(&app).await;
+    assert_eq!(events[0].event_type, "subscribed");
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn addresses_set_by_the_client_before_our_proxies_are_ignored() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "6.6.6.6, 203.0.113.7")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let events = events(&app).await;
+    assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+}
+
+#[tokio::test]
+async fn following_a_confirmation_link_twice_is_recorded_once() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Act
+    for _ in 0..2 {
+        reqwest::get(confirmation_links.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    // Assert
+    let confirmations = events(&app)
+        .await
+        .into_iter()
+        .filter(|e| e.event_type == "confirmed")
+        .count();
+    assert_eq!(confirmations, 1);
+}
+
+#[tokio::test]
+async fn recorded_events_cannot_be_rewritten() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let result = sqlx::query!("UPDATE subscription_events SET source = 'admin'")
+        .execute(&app.pg_pool)
+        .await;
+
+    // Assert
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("append-only"));
+}
+
+#[tokio::test]
+async fn the_history_of_a_subscriber_can_be_downloaded_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .get_admin_page(&format!("subscribers/{}/events.csv", id))
+        .await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert!(
+        response.headers()["Content-Disposition"]
+            .to_str()
+            .unwrap()
+            .contains(&format!("subscriber-{}-events.csv", id))
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "event_type,source,occurred_at,ip_address,user_agent,subscription_token"
+    );
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("subscribed,form,"));
+    assert!(lines[2].starts_with("confirmation_email_sent,form,"));
+    assert!(lines[3].starts_with("confirmed,confirmation_link,"));
+}
+
+#[tokio::test]
+async fn user_agents_are_not_downloaded_as_spreadsheet_formulas() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "=1+1")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+        .id;
+    app.test_user.login(&app).await;
+
+    // Act
+    let csv = app
+        .get_admin_page(&format!("subscribers/{}/events.csv", id))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(csv.lines().nth(1).unwrap().contains(",'=1+1,"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_download_the_history_of_a_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .get_admin_page(&format!("subscribers/{}/events.csv", uuid::Uuid::new_v4()))
+        .await;
+
+    // Assert
+    crate::helpers::assert_is_redirect_to(&response, "/login");
+}
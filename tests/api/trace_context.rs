@@ -0,0 +1,62 @@
+use wiremock::ResponseTemplate;
+
+use crate::helpers::{spawn_app, when_sending_an_email};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+#[tokio::test]
+async fn the_trace_context_of_incoming_requests_is_propagated_to_the_email_api() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header(
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+        )
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request
+        .headers
+        .get("traceparent")
+        .expect("No traceparent header on the email request")
+        .to_str()
+        .unwrap();
+    let parts: Vec<&str> = traceparent.split('-').collect();
+    assert_eq!(parts[1], TRACE_ID);
+    // The email request is a child of one of our spans, not of the caller's
+    assert_ne!(parts[2], "00f067aa0ba902b7");
+}
+
+#[tokio::test]
+async fn outgoing_requests_start_a_new_trace_without_an_incoming_one() {
+    // Arrange
+    let app = spawn_app().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request.headers.get("traceparent").unwrap();
+    assert!(!traceparent.to_str().unwrap().contains(TRACE_ID));
+}
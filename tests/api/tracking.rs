@@ -0,0 +1,211 @@
+use reqwest::Url;
+use uuid::Uuid;
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, confirmed_subscriber, issue_received, logged_in, spawn_app,
+    spawn_app_with,
+};
+
+async fn publish(app: &TestApp, track: bool) -> reqwest::Response {
+    let mut body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p><a href=\"https://example.com/a?x=1&amp;y=2\">A</a> \
+            and <a href=\"https://example.com/b\">B</a></p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    if track {
+        body["track"] = "true".into();
+    }
+    app.post_newsletters(&body).await
+}
+
+/// The HTML body of the issue sent.
+async fn html_received(app: &TestApp) -> String {
+    issue_received(app).await["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .to_owned()
+}
+
+/// The addresses of the links and images in `html`, pointed at the test app.
+fn addresses(app: &TestApp, html: &str) -> Vec<Url> {
+    html.split('"')
+        .filter(|part| part.starts_with("http://127.0.0.1"))
+        .map(|address| {
+            let mut address = Url::parse(address).unwrap();
+            address.set_port(Some(app.port)).unwrap();
+            address
+        })
+        .collect()
+}
+
+async fn issue_id(app: &TestApp) -> Uuid {
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn opens_and_clicks_of_tracked_issues_are_shown_on_the_issue_page() {
+    // Arrange
+    let app = logged_in(spawn_app().await).await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    let response = publish(&app, true).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    let html = html_received(&app).await;
+    let addresses = addresses(&app, &html);
+    let paths: Vec<&str> = addresses.iter().map(|a| a.path()).collect();
+    assert!(paths[0].starts_with("/t/c/") && paths[1].starts_with("/t/c/"));
+    assert_ne!(paths[0], paths[1]);
+    assert!(paths[2].starts_with("/t/o/") && paths[2].ends_with(".gif"));
+
+    // Act - Part 1 - Click the first link
+    let click = app
+        .api_client
+        .get(addresses[0].clone())
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&click, "https://example.com/a?x=1&y=2");
+
+    // Act - Part 2 - Load the pixel
+    let pixel = app
+        .api_client
+        .get(addresses[2].clone())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(pixel.status().as_u16(), 200);
+    assert_eq!(pixel.headers()["Content-Type"], "image/gif");
+    assert_eq!(pixel.headers()["Cache-Control"], "no-store");
+
+    // Assert
+    let html_page = app
+        .get_admin_page_html(&format!("newsletters/{}", issue_id(&app).await))
+        .await;
+    assert!(html_page.contains("<span id=\"openRate\">100.0%</span>"));
+    assert!(html_page.contains("<span id=\"clickRate\">100.0%</span>"));
+    assert!(html_page.contains("<td>https://example.com/a?x=1&#38;y=2</td>\n        <td>1</td>"));
+    assert!(html_page.contains("<td>https://example.com/b</td>\n        <td>0</td>"));
+}
+
+#[tokio::test]
+async fn issues_are_not_tracked_unless_asked_to() {
+    // Arrange
+    let app = logged_in(spawn_app().await).await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    publish(&app, false).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html = html_received(&app).await;
+    assert!(html.contains("<p><a href=\"https://example.com/a?x=1&amp;y=2\">A</a>"));
+    assert!(!html.contains("/t/o/"));
+    let html_page = app
+        .get_admin_page_html(&format!("newsletters/{}", issue_id(&app).await))
+        .await;
+    assert!(html_page.contains("Opens and clicks of this issue are not tracked."));
+}
+
+#[tokio::test]
+async fn nothing_is_tracked_when_tracking_is_off() {
+    // Arrange
+    let app = logged_in(spawn_app_with(|c| c.tracking.enabled = false).await).await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+
+    // Act
+    let html_page = app.get_newsletters_html().await;
+    publish(&app, true).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert!(!html_page.contains("name=\"track\""));
+    let html = html_received(&app).await;
+    assert!(!html.contains("/t/c/") && !html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn issues_already_published_are_sent_untracked_once_tracking_is_off() {
+    // Arrange
+    let mut app = logged_in(spawn_app().await).await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    publish(&app, true).await;
+
+    // Act
+    app.tracking.enabled = false;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html = html_received(&app).await;
+    assert!(html.contains("<a href=\"https://example.com/b\">B</a>"));
+    assert!(!html.contains("/t/c/") && !html.contains("/t/o/"));
+    let n_tokens = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM tracking_tokens"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn unknown_tracked_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/t/c/not-a-token", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasing_personal_data_removes_opens_and_clicks() {
+    // Arrange
+    let app = logged_in(spawn_app().await).await;
+    confirmed_subscriber(&app, "Ursula", "ursula@example.com").await;
+    publish(&app, true).await;
+    app.dispatch_all_pending_emails().await;
+    let pixel = addresses(&app, &html_received(&app).await)[2].clone();
+    app.api_client.get(pixel).send().await.unwrap();
+    let email = sqlx::query_scalar!("SELECT subscriber_email FROM tracking_events")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    let export: serde_json::Value = app
+        .get_admin_page(&format!("personal_data/export?email={}", email))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(export["tracking_events"][0]["kind"], "open");
+
+    // Act
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "email": email }))
+        .await;
+    app.post_admin_form("personal_data/erase", &body).await;
+
+    // Assert
+    let n_rows = sqlx::query_scalar!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM tracking_events)
+            + (SELECT COUNT(*) FROM tracking_tokens)
+            + (SELECT COUNT(*) FROM tracking_link_tokens) AS "n!"
+        "#
+    )
+    .fetch_one(&app.pg_pool)
+    .await
+    .unwrap();
+    assert_eq!(n_rows, 0);
+}
@@ -0,0 +1,298 @@
+use secrecy::SecretString;
+use uuid::Uuid;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{
+    TestApp, assert_is_redirect_to, confirmed_subscriber, create_confirmed_subscriber,
+    spawn_app_with,
+};
+
+async fn spawn_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.webhooks.username = "postmark".into();
+        c.webhooks.password = SecretString::from("webhook-secret");
+    })
+    .await
+}
+
+async fn subscriber(app: &TestApp) -> (Uuid, String) {
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    (subscriber.id, subscriber.email)
+}
+
+async fn post_webhook(
+    app: &TestApp,
+    password: &str,
+    payload: &serde_json::Value,
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/webhooks/email", &app.address))
+        .basic_auth("postmark", Some(password))
+        .json(payload)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+fn bounce(email: &str, bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": 4323372036854775807i64,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Email": email,
+        "BouncedAt": "2025-05-21T16:33:54.9070259Z",
+        "Description": "The server was unable to deliver your message.",
+    })
+}
+
+async fn publish(app: &TestApp) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn hard_bounced_addresses_get_no_more_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (subscriber_id, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_webhook(&app, "webhook-secret", &bounce(&email, "HardBounce")).await;
+    publish(&app).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", subscriber_id))
+        .await;
+    assert!(html_page.contains("it bounced for good."));
+    assert!(html_page.contains("<td>Bounced (HardBounce)</td>"));
+}
+
+#[tokio::test]
+async fn addresses_suppressed_after_publishing_are_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Act
+    post_webhook(&app, "webhook-secret", &bounce(&email, "HardBounce")).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let outcome = sqlx::query_scalar!("SELECT outcome FROM newsletter_deliveries")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(outcome, "skipped");
+}
+
+#[tokio::test]
+async fn complaining_addresses_get_no_more_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    post_webhook(
+        &app,
+        "webhook-secret",
+        &serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "TypeCode": 512,
+            "Email": email,
+            "BouncedAt": "2025-05-21T16:33:54Z",
+        }),
+    )
+    .await;
+    publish(&app).await;
+
+    // Assert
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("<span id=\"recipientCount\">0</span>"));
+}
+
+#[tokio::test]
+async fn suppressions_ignore_the_case_of_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    post_webhook(
+        &app,
+        "webhook-secret",
+        &bounce(&email.to_uppercase(), "HardBounce"),
+    )
+    .await;
+    publish(&app).await;
+
+    // Assert
+    let html_page = app.get_newsletters_html().await;
+    assert!(html_page.contains("<span id=\"recipientCount\">0</span>"));
+}
+
+#[tokio::test]
+async fn suppressions_outlive_the_erasure_of_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+    post_webhook(&app, "webhook-secret", &bounce(&email, "HardBounce")).await;
+    app.test_user.login(&app).await;
+    let body = app
+        .with_csrf_token(&serde_json::json!({ "email": email }))
+        .await;
+    app.post_admin_form("personal_data/erase", &body).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Subscribe again
+    confirmed_subscriber(&app, "Back again", &email).await;
+
+    // Act - Part 2 - Publish
+    publish(&app).await;
+
+    // Assert
+    let n_issues_sent = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+        .filter(|body| body["Subject"] == "Newsletter title")
+        .count();
+    assert_eq!(n_issues_sent, 0);
+}
+
+#[tokio::test]
+async fn soft_bounces_and_deliveries_are_only_recorded() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (subscriber_id, email) = subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    post_webhook(&app, "webhook-secret", &bounce(&email, "SoftBounce")).await;
+    post_webhook(
+        &app,
+        "webhook-secret",
+        &serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Recipient": email,
+            "DeliveredAt": "2025-05-21T13:28:10.2735393-04:00",
+            "Details": "Test delivery webhook details",
+        }),
+    )
+    .await;
+    publish(&app).await;
+
+    // Assert
+    let html_page = app
+        .get_admin_page_html(&format!("subscribers/{}", subscriber_id))
+        .await;
+    assert!(!html_page.contains("id=\"suppression\""));
+    assert!(html_page.contains("<td>Bounced (SoftBounce)</td>"));
+    assert!(html_page.contains("<td>Delivered</td>"));
+}
+
+#[tokio::test]
+async fn webhooks_without_the_right_credentials_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let (_, email) = subscriber(&app).await;
+
+    // Act
+    let response = post_webhook(&app, "wrong-secret", &bounce(&email, "HardBounce")).await;
+    let anonymous = app
+        .api_client
+        .post(format!("{}/webhooks/email", &app.address))
+        .json(&bounce(&email, "HardBounce"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(anonymous.status().as_u16(), 401);
+    let n_events = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM email_events"#)
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_events, 0);
+}
+
+#[tokio::test]
+async fn malformed_payloads_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = post_webhook(
+        &app,
+        "webhook-secret",
+        &serde_json::json!({ "RecordType": "Bounce", "Type": "HardBounce" }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}